@@ -0,0 +1,114 @@
+//! This module reads firmware dumps out of ZIP archives, which is how many dumps are downloaded, so
+//! they do not have to be extracted by hand first.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+/// Extensions of the archive entries that are offered as firmware images, compared case-insensitively.
+pub const FIRMWARE_ENTRY_EXTENSIONS: &[&str] = &["bin", "dat"];
+
+/// Largest entry that is decompressed. Full MS43 dumps are 512KB, so anything bigger is not a dump
+/// and is refused rather than unpacked into memory.
+pub const MAX_ENTRY_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Errors that can occur while reading firmware out of an archive.
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("Could not read the archive: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid ZIP archive: {0}")]
+    Zip(#[from] ZipError),
+    #[error("The archive contains no .bin or .dat file.")]
+    NoFirmware,
+    #[error("The archive entry '{name}' is larger than {} bytes, so it is not a firmware dump.", MAX_ENTRY_SIZE)]
+    EntryTooLarge { name: String },
+}
+
+impl LoadError {
+    /// Returns a stable, machine-readable code identifying the kind of error.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            LoadError::Io(_) => "io_error",
+            LoadError::Zip(_) => "invalid_archive",
+            LoadError::NoFirmware => "no_firmware_in_archive",
+            LoadError::EntryTooLarge { .. } => "archive_entry_too_large",
+        }
+    }
+}
+
+/// Returns true if `path` has a `.zip` extension, in any case.
+pub fn is_zip_path(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+/// Returns true if the archive entry `name` has one of the `FIRMWARE_ENTRY_EXTENSIONS`.
+fn is_firmware_entry(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .is_some_and(|extension| FIRMWARE_ENTRY_EXTENSIONS.iter().any(|wanted| extension.eq_ignore_ascii_case(wanted)))
+}
+
+/// Decompresses every `.bin` and `.dat` file of the ZIP archive at `path` into memory.
+///
+/// Returns `(entry name, contents)` pairs in archive order; entry names keep their folders, e.g.
+/// "dumps/car1.bin". Other files and folders are skipped. Fails with `LoadError::NoFirmware` if no
+/// entry is left.
+pub fn load_from_zip(path: &Path) -> Result<Vec<(String, Vec<u8>)>, LoadError> {
+    let mut archive = ZipArchive::new(fs::File::open(path)?)?;
+    let mut entries = Vec::new();
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index)?;
+        let name = entry.name()?.into_owned();
+        if !entry.is_file() || !is_firmware_entry(&name) {
+            continue;
+        }
+        // The declared size can lie, so the read itself is capped as well.
+        let mut contents = Vec::new();
+        entry.take(MAX_ENTRY_SIZE + 1).read_to_end(&mut contents)?;
+        if contents.len() as u64 > MAX_ENTRY_SIZE {
+            return Err(LoadError::EntryTooLarge { name });
+        }
+        entries.push((name, contents));
+    }
+    if entries.is_empty() {
+        return Err(LoadError::NoFirmware);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    fn write_zip(path: &Path, files: &[(&str, &[u8])]) {
+        let mut writer = zip::ZipWriter::new(fs::File::create(path).unwrap());
+        for &(name, contents) in files {
+            writer.start_file(name, SimpleFileOptions::default()).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn only_firmware_entries_are_extracted() {
+        let path = std::env::temp_dir().join(format!("ewsms43-archive-{}.zip", std::process::id()));
+        write_zip(&path, &[("readme.txt", b"notes"), ("dumps/car1.bin", &[1, 2, 3]), ("CAR2.DAT", &[4, 5])]);
+        let entries = load_from_zip(&path);
+        write_zip(&path, &[("readme.txt", b"notes")]);
+        let empty = load_from_zip(&path);
+        fs::write(&path, b"not a zip").unwrap();
+        let invalid = load_from_zip(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(entries.unwrap(), [("dumps/car1.bin".to_string(), vec![1, 2, 3]), ("CAR2.DAT".to_string(), vec![4, 5])]);
+        assert!(matches!(empty, Err(LoadError::NoFirmware)));
+        assert!(matches!(invalid, Err(LoadError::Zip(_))));
+        assert!(is_zip_path(Path::new("dump.ZIP")));
+        assert!(!is_zip_path(Path::new("dump.bin")));
+    }
+}
@@ -0,0 +1,173 @@
+//! This module keeps a copy of the source file before anything is written, so a single factory read
+//! cannot be lost by saving over it.
+
+use crate::archive::{self, LoadError};
+use crate::ihex::{self, FormatError};
+use crate::version;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Errors that can occur while restoring a backup.
+#[derive(Debug, thiserror::Error)]
+pub enum RestoreError {
+    #[error("Could not read the backup: {0}")]
+    Io(#[from] io::Error),
+    #[error("Could not decode the backup: {0}")]
+    Format(#[from] FormatError),
+    #[error("Could not read the backup archive: {0}")]
+    Archive(#[from] LoadError),
+    #[error("The backup archive has no file named '{0}'.")]
+    MissingEntry(String),
+    #[error("The backup does not hold version '{expected}': {found}")]
+    WrongVersion { expected: String, found: String },
+}
+
+/// Returns the timestamp used in backup names, e.g. `20240131T174502` (UTC).
+fn backup_timestamp(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time)
+        .to_string()
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == 'T')
+        .collect()
+}
+
+/// Returns the first free backup path for `source`: `<name>.bak.<timestamp>` next to the original,
+/// or `<name>.bak.<timestamp>.<n>` if that already exists.
+fn free_backup_path(source: &Path, time: SystemTime) -> PathBuf {
+    let file_name = source.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let base = format!("{}.bak.{}", file_name, backup_timestamp(time));
+    let mut path = source.with_file_name(&base);
+    let mut counter = 1;
+    while path.exists() {
+        path = source.with_file_name(format!("{}.{}", base, counter));
+        counter += 1;
+    }
+    path
+}
+
+/// Copies `source` to a timestamped backup next to it and returns the backup path.
+///
+/// An existing file is never overwritten; a counter is appended to the name instead.
+pub fn backup_original(source: &Path) -> io::Result<PathBuf> {
+    let path = free_backup_path(source, SystemTime::now());
+    fs::copy(source, &path)?;
+    Ok(path)
+}
+
+/// Replaces `current` with the contents of the backup at `backup_path`.
+///
+/// Backups are plain copies of the source file, so Intel HEX and S-record backups are decoded first.
+/// If the source was a ZIP archive, `zip_entry` names the file that was loaded from it, and that file
+/// is extracted from the backed-up archive instead.
+///
+/// The restored image must hold `expected_version`, so a backup of some other file is never taken for
+/// the loaded one. `current` is left unchanged if the backup cannot be read, decoded or does not match.
+pub fn restore_from_backup(
+    current: &mut Vec<u8>,
+    backup_path: &Path,
+    zip_entry: Option<&str>,
+    expected_version: &str,
+) -> Result<(), RestoreError> {
+    let contents = match zip_entry {
+        Some(name) => archive::load_from_zip(backup_path)?
+            .into_iter()
+            .find(|(entry, _)| entry == name)
+            .map(|(_, contents)| contents)
+            .ok_or_else(|| RestoreError::MissingEntry(name.to_string()))?,
+        None => fs::read(backup_path)?,
+    };
+    let decoded = ihex::decode(contents)?;
+    // Candidates rather than `detect`, so a version with several hardware variants is accepted too.
+    let found = match version::detect_all_candidates(&decoded.data) {
+        Ok(candidates) if candidates.iter().any(|set| set.version_string == expected_version) => None,
+        Ok(candidates) => Some(format!("it holds '{}'", candidates.first().map_or("", |set| set.version_string))),
+        Err(e) => Some(e.to_string()),
+    };
+    if let Some(found) = found {
+        return Err(RestoreError::WrongVersion { expected: expected_version.to_string(), found });
+    }
+    *current = decoded.data;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{build_synthetic_firmware, builtin_patch_set};
+    use std::io::Write;
+    use std::time::Duration;
+
+    #[test]
+    fn backups_never_overwrite_each_other() {
+        let dir = std::env::temp_dir().join(format!("ewsms43-backup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("dump.bin");
+        fs::write(&source, [1, 2, 3]).unwrap();
+
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_706_723_102);
+        let first = free_backup_path(&source, time);
+        assert_eq!(first, dir.join("dump.bin.bak.20240131T174502"));
+        fs::write(&first, []).unwrap();
+        assert_eq!(free_backup_path(&source, time), dir.join("dump.bin.bak.20240131T174502.1"));
+
+        let backup = backup_original(&source).unwrap();
+        let second = backup_original(&source).unwrap();
+        let contents = (fs::read(&backup).unwrap(), fs::read(&second).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+        assert_ne!(backup, second);
+        assert_eq!(contents, (vec![1, 2, 3], vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn restoring_replaces_the_whole_buffer() {
+        let dir = std::env::temp_dir().join(format!("ewsms43-restore-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("dump.bin");
+        let image = build_synthetic_firmware(&builtin_patch_set("ca430037"));
+        fs::write(&source, &image).unwrap();
+        let backup = backup_original(&source).unwrap();
+
+        let mut current = vec![0x55; 0x80];
+        let wrong_version = restore_from_backup(&mut current, &backup, None, "ca430069");
+        let unchanged = current.clone();
+        let restored = restore_from_backup(&mut current, &backup, None, "ca430037");
+        let missing = restore_from_backup(&mut current, &dir.join("missing.bak"), None, "ca430037");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(wrong_version, Err(RestoreError::WrongVersion { .. })));
+        assert_eq!(unchanged, vec![0x55; 0x80]);
+        assert!(restored.is_ok());
+        assert_eq!(current, image);
+        assert!(matches!(missing, Err(RestoreError::Io(_))));
+    }
+
+    #[test]
+    fn a_zip_backup_restores_the_loaded_entry() {
+        let dir = std::env::temp_dir().join(format!("ewsms43-restore-zip-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("dumps.zip");
+        let image = build_synthetic_firmware(&builtin_patch_set("ca430037"));
+        let mut writer = zip::ZipWriter::new(fs::File::create(&source).unwrap());
+        for (name, contents) in [("other.bin", &[1u8, 2, 3][..]), ("car/dump.bin", &image[..])] {
+            writer.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap();
+        let backup = backup_original(&source).unwrap();
+
+        let mut current = Vec::new();
+        let restored = restore_from_backup(&mut current, &backup, Some("car/dump.bin"), "ca430037");
+        let mut other = Vec::new();
+        let wrong_entry = restore_from_backup(&mut other, &backup, Some("other.bin"), "ca430037");
+        let missing = restore_from_backup(&mut other, &backup, Some("gone.bin"), "ca430037");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(restored.is_ok());
+        assert_eq!(current, image);
+        assert!(matches!(wrong_entry, Err(RestoreError::WrongVersion { .. })));
+        assert!(matches!(missing, Err(RestoreError::MissingEntry(_))));
+        assert!(other.is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+//! This module patches every recognized firmware file in a folder, e.g. for a fleet of cars.
+//!
+//! Each file is handled on its own: a file that cannot be read, detected or patched is reported in its
+//! result and the batch carries on with the next one.
+
+use crate::ihex::{self, ImageFormat};
+use crate::patcher::{self, PatchStatus};
+use crate::patches::PatchSet;
+use crate::verify;
+use crate::version;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What happened to one file of a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOutcome {
+    /// The patches of `patch_set` were applied and the result written to `output`. `statuses` holds
+    /// the name and status of every patch in the written image.
+    Patched { patch_set: &'static PatchSet, statuses: Vec<(String, PatchStatus)>, output: PathBuf },
+    /// The file is not a supported firmware image, so it was left alone.
+    Skipped(BatchError),
+    /// The file is a supported image, but patching or writing it failed.
+    Failed(BatchError),
+}
+
+/// Why a file of a batch was skipped or failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchError {
+    /// The `error_code` of the underlying error, e.g. "unsupported_version", or "io_error" if the file
+    /// could not be read or written.
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl BatchError {
+    fn new(code: &'static str, message: impl fmt::Display) -> Self {
+        BatchError { code, message: message.to_string() }
+    }
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// The result for one file of a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchResult {
+    pub input: PathBuf,
+    pub outcome: BatchOutcome,
+}
+
+/// Replaces every character that is not safe in a file name on all platforms with `_`.
+pub fn sanitize_file_name_part(part: &str) -> String {
+    part.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect()
+}
+
+/// Splits the file name of `input` into its sanitized stem and its last extension. Both `/` and `\`
+/// separate folders, so Windows paths are handled on every platform. An empty stem becomes "firmware".
+pub fn split_file_name(input: &str) -> (String, Option<&str>) {
+    let file_name = input.rsplit(['/', '\\']).next().unwrap_or_default();
+    let (stem, extension) = match file_name.rfind('.') {
+        Some(dot) if dot > 0 => (&file_name[..dot], Some(&file_name[dot + 1..])),
+        _ => (file_name, None),
+    };
+    let stem = if stem.is_empty() { "firmware".to_string() } else { sanitize_file_name_part(stem) };
+    (stem, extension)
+}
+
+/// Returns the default name of a firmware file written from `input`: its file name with `_<suffix>`
+/// before the extension, e.g. `dump.bin` -> `dump_patched.bin` or `dump_reverted.bin`.
+///
+/// Only the last extension is replaced, so `dump.2024.01.bin` keeps its dots. Intel HEX and S-record
+/// extensions are kept, since those files are written back in their format; anything else, such as
+/// `.dat`, `.zip` or no extension at all, becomes `.bin`. Used by the GUI save dialogs and by batches.
+pub fn default_output_name(input: &str, suffix: &str) -> String {
+    let (stem, extension) = split_file_name(input);
+    let extension = extension
+        .filter(|extension| ImageFormat::for_extension(extension, &ImageFormat::Binary) != ImageFormat::Binary)
+        .unwrap_or("bin");
+    format!("{}_{}.{}", stem, suffix, extension)
+}
+
+/// Patches one file and writes the result to `out_dir`.
+fn patch_one(input: &Path, out_dir: &Path) -> BatchOutcome {
+    let contents = match fs::read(input) {
+        Ok(contents) => contents,
+        Err(e) => return BatchOutcome::Failed(BatchError::new("io_error", format!("Could not read the file: {}", e))),
+    };
+    let mut decoded = match ihex::decode(contents) {
+        Ok(decoded) => decoded,
+        Err(e) => return BatchOutcome::Skipped(BatchError::new(e.error_code(), format!("Not a firmware image: {}", e))),
+    };
+    let patch_set = match version::detect_version(&decoded.data) {
+        Ok(patch_set) => patch_set,
+        Err(e) => return BatchOutcome::Skipped(BatchError::new(e.error_code(), &e)),
+    };
+    if let Err(e) = patcher::apply_patches(&mut decoded.data, patch_set) {
+        return BatchOutcome::Failed(BatchError::new(e.error_code(), &e));
+    }
+    let output = out_dir.join(default_output_name(&input.to_string_lossy(), "patched"));
+    let encoded = ihex::encode(&decoded.data, &decoded.format);
+    if let Err(e) = fs::write(&output, &encoded) {
+        return BatchOutcome::Failed(BatchError::new("io_error", format!("Could not write {}: {}", output.display(), e)));
+    }
+    match verify::verify_written(&output, &encoded) {
+        Ok(()) => BatchOutcome::Patched { patch_set, statuses: patcher::check_all_statuses(&decoded.data, patch_set), output },
+        Err(e) => {
+            BatchOutcome::Failed(BatchError::new(e.error_code(), format!("Verification of {} failed: {}", output.display(), e)))
+        }
+    }
+}
+
+/// Patches every file directly inside `dir` (not in subfolders) and writes the results to `out_dir`,
+/// which is created if needed. Files are handled in name order.
+///
+/// Only listing `dir` or creating `out_dir` can fail the whole batch; problems with single files are
+/// reported in their `BatchResult`.
+pub fn batch_apply(dir: &Path, out_dir: &Path) -> io::Result<Vec<BatchResult>> {
+    let mut inputs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            inputs.push(entry.path());
+        }
+    }
+    inputs.sort();
+    fs::create_dir_all(out_dir)?;
+    Ok(inputs.into_iter().map(|input| BatchResult { outcome: patch_one(&input, out_dir), input }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patcher::check_patch_status;
+    use crate::testutil::{build_synthetic_firmware, builtin_patch_set};
+
+    #[test]
+    fn every_recognized_file_is_patched_and_the_rest_reported() {
+        let root = std::env::temp_dir().join(format!("ewsms43-batch-{}", std::process::id()));
+        let (dir, out_dir) = (root.join("in"), root.join("out"));
+        fs::create_dir_all(&dir).unwrap();
+        let patch_set = builtin_patch_set("ca430037");
+        let data = build_synthetic_firmware(&patch_set);
+        fs::write(dir.join("car1.bin"), &data).unwrap();
+        fs::write(dir.join("notes.txt"), b"not firmware").unwrap();
+        let mut patched = data.clone();
+        patcher::apply_patches(&mut patched, &patch_set).unwrap();
+        fs::write(dir.join("car2.bin"), &patched).unwrap();
+
+        let results = batch_apply(&dir, &out_dir).unwrap();
+        let written = fs::read(out_dir.join("car1_patched.bin"));
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(results.len(), 3);
+        let BatchOutcome::Patched { patch_set: detected, statuses, output } = &results[0].outcome else {
+            panic!("car1.bin was not patched: {:?}", results[0].outcome);
+        };
+        assert_eq!((detected.version_string, output), ("ca430037", &out_dir.join("car1_patched.bin")));
+        assert!(statuses.iter().all(|(_, status)| *status == PatchStatus::Patched));
+        assert_eq!(check_patch_status(&written.unwrap(), &patch_set).0, PatchStatus::Patched);
+        // An already patched file fails validation; a text file is not detected.
+        assert!(matches!(&results[1].outcome, BatchOutcome::Failed(e) if e.code == "validation_mismatch"));
+        assert!(matches!(&results[2].outcome, BatchOutcome::Skipped(e) if e.code == "file_too_small"));
+    }
+
+    #[test]
+    fn output_names_keep_the_source_name() {
+        assert_eq!(default_output_name("/cars/E46 330i/foo.bin", "patched"), "foo_patched.bin");
+        assert_eq!(default_output_name("foo.bin", "reverted"), "foo_reverted.bin");
+        assert_eq!(default_output_name("C:\\flash\\read", "patched"), "read_patched.bin");
+        // Only the last extension is replaced.
+        assert_eq!(default_output_name("/cars/dump.2024.01.bin", "patched"), "dump.2024.01_patched.bin");
+        assert_eq!(default_output_name("dump.S28", "reverted"), "dump_reverted.S28");
+        assert_eq!(default_output_name("my dump #2.zip", "patched"), "my_dump__2_patched.bin");
+        assert_eq!(default_output_name("car.dat", "patched"), "car_patched.bin");
+        assert_eq!(default_output_name("", "patched"), "firmware_patched.bin");
+    }
+}
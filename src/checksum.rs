@@ -0,0 +1,364 @@
+//! This module contains checksum algorithms and a diagnostic that tries common MS43 checksum layouts.
+//!
+//! The diagnostic is a research aid: it computes several candidate checksums over well-known flash
+//! regions and compares each against the value stored in the last bytes of the region, so the
+//! checksum layout of a new firmware version can be identified.
+
+use std::fmt;
+
+/// Well-known regions of a 512KB MS43 flash image, as `(name, start, end)`.
+pub const CANDIDATE_REGIONS: &[(&str, usize, usize)] = &[
+    ("Boot", 0x00000, 0x10000),
+    ("Program", 0x10000, 0x70000),
+    ("Calibration", 0x70000, 0x80000),
+    ("Program+Calibration", 0x10000, 0x80000),
+];
+
+/// A checksum scheme that may be used to protect a flash region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum ChecksumScheme {
+    /// 16-bit sum of all bytes, the additive checksum of the known MS43 versions.
+    #[default]
+    ByteSum16,
+    /// 16-bit sum of all little-endian 16-bit words.
+    WordSum16,
+    /// CRC-16/CCITT-FALSE.
+    Crc16,
+    /// CRC-32 (IEEE 802.3).
+    Crc32,
+    /// 8-bit XOR of all bytes.
+    Xor8,
+}
+
+impl ChecksumScheme {
+    pub const ALL: [ChecksumScheme; 5] = [
+        ChecksumScheme::ByteSum16,
+        ChecksumScheme::WordSum16,
+        ChecksumScheme::Crc16,
+        ChecksumScheme::Crc32,
+        ChecksumScheme::Xor8,
+    ];
+
+    /// Returns the size of the stored checksum value in bytes.
+    pub fn width(self) -> usize {
+        match self {
+            ChecksumScheme::Crc32 => 4,
+            ChecksumScheme::Xor8 => 1,
+            _ => 2,
+        }
+    }
+
+    /// Computes the checksum of `data` with this scheme.
+    pub fn compute(self, data: &[u8]) -> u32 {
+        match self {
+            ChecksumScheme::ByteSum16 => byte_sum16(data) as u32,
+            ChecksumScheme::WordSum16 => word_sum16(data) as u32,
+            ChecksumScheme::Crc16 => crc16(data) as u32,
+            ChecksumScheme::Crc32 => crc32(data),
+            ChecksumScheme::Xor8 => xor8(data) as u32,
+        }
+    }
+}
+
+/// Byte order of a stored multi-byte checksum value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    /// Least significant byte first, as used by the C167 in the MS43.
+    Little,
+    Big,
+}
+
+impl Endian {
+    /// Reads an unsigned value of `bytes.len()` bytes (at most 4).
+    pub fn read(self, bytes: &[u8]) -> u32 {
+        match self {
+            Endian::Little => bytes.iter().rev().fold(0u32, |value, &b| (value << 8) | b as u32),
+            Endian::Big => bytes.iter().fold(0u32, |value, &b| (value << 8) | b as u32),
+        }
+    }
+
+    /// Encodes the low `width` bytes of `value` (at most 4).
+    pub fn write(self, value: u32, width: usize) -> Vec<u8> {
+        match self {
+            Endian::Little => value.to_le_bytes()[..width].to_vec(),
+            Endian::Big => value.to_be_bytes()[4 - width..].to_vec(),
+        }
+    }
+}
+
+/// Defines where a checksum is computed and where its value is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChecksumRegion {
+    /// Start of the checksummed bytes.
+    pub start: usize,
+    /// End (exclusive) of the checksummed bytes.
+    pub end: usize,
+    /// Offset of the stored checksum value. It must lie outside `start..end`.
+    pub checksum_offset: usize,
+    pub scheme: ChecksumScheme,
+    /// Byte order of the stored value.
+    pub endian: Endian,
+}
+
+impl ChecksumRegion {
+    /// Returns the range of the stored checksum value.
+    pub fn value_range(&self) -> std::ops::Range<usize> {
+        self.checksum_offset..self.checksum_offset + self.scheme.width()
+    }
+
+    /// Computes the checksum of the region with its scheme and returns it as stored, i.e. `width`
+    /// bytes in the region's byte order.
+    pub fn compute(&self, data: &[u8]) -> Result<Vec<u8>, ChecksumError> {
+        self.check_bounds(data.len())?;
+        Ok(self.endian.write(self.scheme.compute(&data[self.start..self.end]), self.scheme.width()))
+    }
+
+    fn check_bounds(&self, len: usize) -> Result<(), ChecksumError> {
+        let value = self.value_range();
+        if self.start > self.end || value.start < self.end && self.start < value.end {
+            return Err(ChecksumError::InvalidRegion { start: self.start, end: self.end, checksum_offset: self.checksum_offset });
+        }
+        if len < self.end.max(value.end) {
+            return Err(ChecksumError::OutOfBounds { end: self.end.max(value.end), len });
+        }
+        Ok(())
+    }
+}
+
+/// Errors raised when reading or writing a checksum.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ChecksumError {
+    /// The region or stored value extends past the end of the data.
+    #[error("Checksum region ends at {end:#X}, but the file is only {len} bytes.")]
+    OutOfBounds { end: usize, len: usize },
+    /// The region is empty/inverted, or the stored value overlaps the checksummed bytes.
+    #[error("Checksum region {start:#X}..{end:#X} with value at {checksum_offset:#X} is invalid.")]
+    InvalidRegion { start: usize, end: usize, checksum_offset: usize },
+}
+
+/// The stored and computed checksum of a region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChecksumVerification {
+    pub stored: u32,
+    pub computed: u32,
+}
+
+impl ChecksumVerification {
+    /// Returns true if the stored value is correct.
+    pub fn is_valid(&self) -> bool {
+        self.stored == self.computed
+    }
+}
+
+/// Reads the stored checksum of a region and compares it with the computed one.
+pub fn verify_checksum(data: &[u8], region: &ChecksumRegion) -> Result<ChecksumVerification, ChecksumError> {
+    region.check_bounds(data.len())?;
+    Ok(ChecksumVerification {
+        stored: region.endian.read(&data[region.value_range()]),
+        computed: region.scheme.compute(&data[region.start..region.end]),
+    })
+}
+
+/// Computes the checksum of a region and writes it in the region's byte order. Returns the old and new values.
+pub fn recalculate_checksum(data: &mut [u8], region: &ChecksumRegion) -> Result<ChecksumVerification, ChecksumError> {
+    let verification = verify_checksum(data, region)?;
+    data[region.value_range()].copy_from_slice(&region.endian.write(verification.computed, region.scheme.width()));
+    Ok(verification)
+}
+
+/// Computes the 16-bit sum of all bytes.
+pub fn byte_sum16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |sum, &b| sum.wrapping_add(b as u16))
+}
+
+/// Computes the 16-bit sum of all little-endian 16-bit words. A trailing odd byte is added as a low byte.
+pub fn word_sum16(data: &[u8]) -> u16 {
+    data.chunks(2).fold(0u16, |sum, chunk| {
+        let word = u16::from_le_bytes([chunk[0], chunk.get(1).copied().unwrap_or(0)]);
+        sum.wrapping_add(word)
+    })
+}
+
+/// Computes the CRC-16/CCITT-FALSE checksum (polynomial 0x1021, initial value 0xFFFF).
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Computes the CRC-32 (IEEE 802.3) checksum.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Computes the 8-bit XOR of all bytes.
+pub fn xor8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |xor, &b| xor ^ b)
+}
+
+/// The result of checking one checksum scheme against one region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumCandidate {
+    pub region_name: &'static str,
+    /// Start of the checksummed bytes.
+    pub start: usize,
+    /// End (exclusive) of the checksummed bytes. The stored value follows directly after.
+    pub end: usize,
+    pub scheme: ChecksumScheme,
+    pub computed: u32,
+    /// The stored value read as little-endian.
+    pub stored_le: u32,
+    /// The stored value read as big-endian.
+    pub stored_be: u32,
+}
+
+impl ChecksumCandidate {
+    /// Returns true if the computed value matches the stored value in either byte order.
+    pub fn matches(&self) -> bool {
+        self.computed == self.stored_le || self.computed == self.stored_be
+    }
+}
+
+impl fmt::Display for ChecksumCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.scheme.width() * 2;
+        write!(
+            f,
+            "{:<20} {:#07X}..{:#07X} {:<10} computed={:#0w$X} stored(LE)={:#0w$X} stored(BE)={:#0w$X} {}",
+            self.region_name,
+            self.start,
+            self.end,
+            format!("{:?}", self.scheme),
+            self.computed,
+            self.stored_le,
+            self.stored_be,
+            if self.matches() { "MATCH" } else { "-" },
+            w = digits + 2,
+        )
+    }
+}
+
+/// Tries every checksum scheme over every candidate region that fits in `data`.
+///
+/// For each region, the last `width` bytes are treated as the stored checksum and the bytes
+/// before them as the checksummed data.
+pub fn diagnose_checksums(data: &[u8]) -> Vec<ChecksumCandidate> {
+    let mut candidates = Vec::new();
+
+    for &(region_name, start, region_end) in CANDIDATE_REGIONS {
+        if data.len() < region_end {
+            continue;
+        }
+        for scheme in ChecksumScheme::ALL {
+            let end = region_end - scheme.width();
+            let stored = &data[end..region_end];
+            candidates.push(ChecksumCandidate {
+                region_name,
+                start,
+                end,
+                scheme,
+                computed: scheme.compute(&data[start..end]),
+                stored_le: Endian::Little.read(stored),
+                stored_be: Endian::Big.read(stored),
+            });
+        }
+    }
+
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn algorithms_match_known_vectors() {
+        let data = b"123456789";
+        assert_eq!(crc32(data), 0xCBF4_3926);
+        assert_eq!(crc16(data), 0x29B1);
+        assert_eq!(byte_sum16(data), 0x01DD);
+        assert_eq!(word_sum16(&[0x01, 0x02, 0x03]), 0x0204);
+        assert_eq!(xor8(data), 0x31);
+        assert_eq!(xor8(&[0xFF, 0x0F]), 0xF0);
+    }
+
+    #[test]
+    fn every_scheme_is_computed_in_its_width_and_byte_order() {
+        let data = b"123456789";
+        let region = |scheme, endian| ChecksumRegion { start: 0, end: 9, checksum_offset: 9, scheme, endian };
+        let padded = [data.as_slice(), &[0; 4]].concat();
+        assert_eq!(region(ChecksumScheme::ByteSum16, Endian::Little).compute(&padded), Ok(vec![0xDD, 0x01]));
+        assert_eq!(region(ChecksumScheme::Crc32, Endian::Little).compute(&padded), Ok(vec![0x26, 0x39, 0xF4, 0xCB]));
+        assert_eq!(region(ChecksumScheme::Crc32, Endian::Big).compute(&padded), Ok(vec![0xCB, 0xF4, 0x39, 0x26]));
+        assert_eq!(region(ChecksumScheme::Xor8, Endian::Little).compute(&padded), Ok(vec![0x31]));
+        assert_eq!(
+            region(ChecksumScheme::Crc32, Endian::Little).compute(data),
+            Err(ChecksumError::OutOfBounds { end: 13, len: 9 })
+        );
+    }
+
+    #[test]
+    fn diagnosis_finds_a_stored_crc32() {
+        // Uniform data would match the XOR of every odd-length region by chance.
+        let mut data: Vec<u8> = (0..0x80000).map(|i| (i % 251) as u8).collect();
+        let crc = crc32(&data[0x70000..0x7FFFC]);
+        data[0x7FFFC..0x80000].copy_from_slice(&crc.to_le_bytes());
+
+        let matches: Vec<_> = diagnose_checksums(&data).into_iter().filter(|c| c.matches()).collect();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].region_name, "Calibration");
+        assert_eq!(matches[0].scheme, ChecksumScheme::Crc32);
+        assert!(matches[0].to_string().ends_with("MATCH"));
+    }
+
+    #[test]
+    fn diagnosis_skips_regions_beyond_the_file() {
+        assert!(diagnose_checksums(&[0u8; 0x100]).is_empty());
+    }
+
+    #[test]
+    fn endian_reads_and_writes_in_the_right_order() {
+        assert_eq!(Endian::Little.read(&[0x34, 0x12]), 0x1234);
+        assert_eq!(Endian::Big.read(&[0x12, 0x34]), 0x1234);
+        assert_eq!(Endian::Little.write(0xCBF4_3926, 4), vec![0x26, 0x39, 0xF4, 0xCB]);
+        assert_eq!(Endian::Big.write(0xCBF4_3926, 4), vec![0xCB, 0xF4, 0x39, 0x26]);
+        assert_eq!(Endian::Big.write(0x29B1, 2), vec![0x29, 0xB1]);
+    }
+
+    #[test]
+    fn recalculate_writes_the_value_in_the_region_byte_order() {
+        for (endian, expected) in [(Endian::Little, [0xB1, 0x29]), (Endian::Big, [0x29, 0xB1])] {
+            let mut data = b"123456789\0\0".to_vec();
+            let region = ChecksumRegion { start: 0, end: 9, checksum_offset: 9, scheme: ChecksumScheme::Crc16, endian };
+            assert!(!verify_checksum(&data, &region).unwrap().is_valid());
+
+            let result = recalculate_checksum(&mut data, &region).unwrap();
+            assert_eq!(result, ChecksumVerification { stored: 0, computed: 0x29B1 });
+            assert_eq!(data[9..], expected);
+            assert!(verify_checksum(&data, &region).unwrap().is_valid());
+        }
+    }
+
+    #[test]
+    fn invalid_checksum_regions_are_rejected() {
+        let mut data = vec![0u8; 16];
+        let overlapping = ChecksumRegion { start: 0, end: 8, checksum_offset: 6, scheme: ChecksumScheme::Crc32, endian: Endian::Little };
+        assert!(matches!(recalculate_checksum(&mut data, &overlapping), Err(ChecksumError::InvalidRegion { .. })));
+
+        let too_long = ChecksumRegion { start: 0, end: 14, checksum_offset: 14, scheme: ChecksumScheme::Crc32, endian: Endian::Little };
+        assert_eq!(verify_checksum(&data, &too_long), Err(ChecksumError::OutOfBounds { end: 18, len: 16 }));
+        assert_eq!(data, vec![0u8; 16]);
+    }
+}
@@ -0,0 +1,746 @@
+//! This module handles command-line arguments. Without arguments the GUI is started.
+
+use crate::gui::loader::{self, LoadFailure};
+use crate::gui::log_panel::{LogEntry, LogLevel};
+use ewsms43::archive;
+use ewsms43::batch::{self, BatchOutcome, BatchResult};
+use ewsms43::ihex::{self, ImageFormat};
+use ewsms43::mapped;
+use ewsms43::patcher::{self, PatchStatus};
+use ewsms43::patches::{self, PatchSet};
+use ewsms43::selftest;
+use ewsms43::verify;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Number of leading bytes read to tell raw binaries from Intel HEX and S-record files.
+const FORMAT_PROBE_LEN: u64 = 4096;
+
+/// Raw binary images of at least this size are patched through a memory map. Real dumps are far
+/// smaller and go through the loader like in the GUI, which also extracts ZIP archives and handles
+/// hardware variants.
+const MAPPED_APPLY_MIN_SIZE: u64 = 16 * 1024 * 1024;
+
+const USAGE: &str = "Usage: ewsms43 [OPTIONS]
+
+Without options, the graphical patcher is started.
+
+Options:
+  --check FILE      Detect the version and patch status of FILE without modifying it
+  --input FILE --output FILE [--apply] [--json]
+                    Apply the patches to FILE and write the result to OUTPUT
+  --input FILE --status [--json]
+                    Print the status of the Jump, Code and DTC patches of FILE
+  --input FILE --device PATH [--yes]
+                    Apply the patches to FILE and write the raw image straight to PATH,
+                    e.g. /dev/sdX or a mounted EEPROM image (advanced; asks for confirmation
+                    unless --yes is given)
+  --batch DIR [--output OUT_DIR] [--json]
+                    Patch every supported file in DIR and write <name>_patched.bin files
+                    to OUT_DIR (default: DIR/patched)
+  --json            With --status, --apply or --batch: print a JSON report to stdout
+                    instead of text (one object, or an array with one object per file
+                    for --batch)
+  --list-versions   Print every supported firmware version and its patches
+  --self-test, --selftest
+                    Check the built-in patch data for definition mistakes and round-trip
+                    every patch set on a synthetic image
+  -h, --help        Print this help";
+
+/// A command requested on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Read-only inspection of a firmware file.
+    Check(PathBuf),
+    /// Apply the patches to `input` and write the result to `output`. `json` prints a `CliReport`.
+    Apply { input: PathBuf, output: PathBuf, json: bool },
+    /// Print the status of every patch of a firmware file. `json` prints a `CliReport`.
+    Status { input: PathBuf, json: bool },
+    /// Apply the patches to `input` and write the raw image to the start of the existing `device`.
+    /// Without `confirmed`, the user is asked first.
+    WriteDevice { input: PathBuf, device: PathBuf, confirmed: bool },
+    /// Patch every supported file in `dir` and write the results to `output`. `json` prints one
+    /// `CliReport` per file.
+    Batch { dir: PathBuf, output: PathBuf, json: bool },
+    ListVersions,
+    SelfTest,
+    Help,
+}
+
+/// Status of one patch in a `CliReport`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PatchReport {
+    pub name: String,
+    /// "Patched", "Unpatched" or "Unknown".
+    pub status: String,
+}
+
+/// An error in a `CliReport`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorReport {
+    /// Stable, machine-readable kind of the error, e.g. "unsupported_version" or "file_too_small", taken
+    /// from the `error_code` of the underlying error. "io_error" if a file could not be read or written.
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// Outcome of `--status`, `--apply` or one file of `--batch`. Printed as JSON with `--json`, so
+/// scripts do not have to parse the text output; the field names are stable.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CliReport {
+    pub input: String,
+    /// Detected version, e.g. "ca430037". `None` if the file was not recognized.
+    pub version: Option<String>,
+    /// Hardware variant of the detected patch set, if it has one.
+    pub variant: Option<String>,
+    /// Status of every patch, after patching for `--apply` and `--batch`. Empty if the file was not
+    /// recognized, or for batch files that were not patched.
+    pub patches: Vec<PatchReport>,
+    /// The written file, once it was saved.
+    pub output: Option<String>,
+    /// Why a `--batch` file was skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<ErrorReport>,
+    /// The lines printed to stdout without `--json`.
+    pub log: Vec<String>,
+    /// What went wrong; the command failed if this is not empty.
+    pub errors: Vec<ErrorReport>,
+}
+
+impl CliReport {
+    fn new(input: &Path) -> Self {
+        CliReport { input: input.display().to_string(), ..CliReport::default() }
+    }
+
+    /// Records the detected patch set and the status of its patches in `data`.
+    fn detected(&mut self, patch_set: &PatchSet, data: &[u8]) {
+        self.detected_with(patch_set, patcher::check_all_statuses(data, patch_set));
+    }
+
+    /// Records the detected patch set and the given patch statuses.
+    fn detected_with(&mut self, patch_set: &PatchSet, statuses: Vec<(String, PatchStatus)>) {
+        self.version = Some(patch_set.version_string.to_string());
+        self.variant = patch_set.hardware_variant.map(str::to_string);
+        self.patches = statuses.into_iter().map(|(name, status)| PatchReport { name, status: format!("{:?}", status) }).collect();
+    }
+
+    /// Records an error with the given code.
+    fn error(&mut self, code: &'static str, message: impl std::fmt::Display) {
+        self.errors.push(ErrorReport { code, message: message.to_string() });
+    }
+
+    /// Adds the loader log to `log`, except its error lines, and records why loading failed, if it did.
+    fn extend_from_loader(&mut self, entries: Vec<LogEntry>, failure: Option<LoadFailure>) {
+        self.log.extend(entries.into_iter().filter(|entry| entry.level != LogLevel::Error).map(|entry| entry.message));
+        if let Some(failure) = failure {
+            self.error(failure.error_code(), &failure);
+        }
+    }
+
+    /// Returns the process exit code for this report.
+    fn exit_code(&self) -> i32 {
+        i32::from(!self.errors.is_empty())
+    }
+}
+
+/// Prints `report` as JSON, or as text: the log to stdout and every error to stderr. Returns the exit code.
+fn emit(report: &CliReport, json: bool) -> i32 {
+    if json {
+        print_json(report);
+    } else {
+        print_lines(&report.log);
+        for error in &report.errors {
+            eprintln!("Error: {}", error.message);
+        }
+    }
+    report.exit_code()
+}
+
+/// Prints `value` as pretty-printed JSON.
+fn print_json(value: &impl Serialize) {
+    // The reports only hold strings and lists, which always serialize.
+    println!("{}", serde_json::to_string_pretty(value).expect("reports serialize to JSON"));
+}
+
+/// Parses the command-line arguments (without the program name).
+///
+/// `--input` and `--output` are options of the `--apply` and `--status` actions. Giving an output
+/// without an action implies `--apply`. `--json` is an option of `--status`, `--apply` and `--batch`.
+/// Returns `Ok(None)` if no arguments were given and the GUI should start.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Command>, String> {
+    let mut command = None;
+    let (mut input, mut output) = (None, None);
+    let (mut apply, mut status, mut json) = (false, false, false);
+    let mut batch_dir = None;
+    let (mut device, mut confirmed) = (None, false);
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "--check" => Command::Check(PathBuf::from(args.next().ok_or("--check requires a file name.")?)),
+            "--input" => {
+                input = Some(PathBuf::from(args.next().ok_or("--input requires a file name.")?));
+                continue;
+            }
+            "--output" => {
+                output = Some(PathBuf::from(args.next().ok_or("--output requires a file name.")?));
+                continue;
+            }
+            "--batch" => {
+                batch_dir = Some(PathBuf::from(args.next().ok_or("--batch requires a folder name.")?));
+                continue;
+            }
+            "--device" => {
+                device = Some(PathBuf::from(args.next().ok_or("--device requires a path.")?));
+                continue;
+            }
+            "--yes" => {
+                confirmed = true;
+                continue;
+            }
+            "--apply" => {
+                apply = true;
+                continue;
+            }
+            "--status" => {
+                status = true;
+                continue;
+            }
+            "--json" => {
+                json = true;
+                continue;
+            }
+            "--list-versions" => Command::ListVersions,
+            "--self-test" | "--selftest" => Command::SelfTest,
+            "-h" | "--help" => Command::Help,
+            other => return Err(format!("Unknown argument '{}'.", other)),
+        };
+        if command.replace(parsed).is_some() {
+            return Err("Only one command can be given at a time.".to_string());
+        }
+    }
+
+    if let Some(device) = device {
+        if output.is_some() || apply || status || json || batch_dir.is_some() || command.is_some() {
+            return Err("--device can only be combined with --input and --yes.".to_string());
+        }
+        let input = input.ok_or("--device requires --input.")?;
+        return Ok(Some(Command::WriteDevice { input, device, confirmed }));
+    }
+    if confirmed {
+        return Err("--yes is only used with --device.".to_string());
+    }
+
+    if let Some(dir) = batch_dir {
+        if input.is_some() || apply || status || command.is_some() {
+            return Err("--batch can only be combined with --output.".to_string());
+        }
+        let output = output.unwrap_or_else(|| dir.join("patched"));
+        return Ok(Some(Command::Batch { dir, output, json }));
+    }
+
+    let file_command = match (input, output, apply, status) {
+        (None, None, false, false) if json => return Err("--json is only used with --status, --apply and --batch.".to_string()),
+        (None, None, false, false) => return Ok(command),
+        (_, _, true, true) => return Err("--apply and --status cannot be combined.".to_string()),
+        (Some(input), Some(output), _, false) => Command::Apply { input, output, json },
+        (Some(input), None, false, true) => Command::Status { input, json },
+        (Some(_), Some(_), false, true) => return Err("--status does not write a file; remove --output.".to_string()),
+        (None, _, _, _) => return Err("--input is required with --output, --apply and --status.".to_string()),
+        (Some(_), None, true, false) => return Err("--apply requires --output.".to_string()),
+        (Some(_), None, false, false) => return Err("--input requires --status, or --output to apply the patches.".to_string()),
+    };
+    match command {
+        Some(_) => Err("Only one command can be given at a time.".to_string()),
+        None => Ok(Some(file_command)),
+    }
+}
+
+/// Runs a command and returns the process exit code.
+pub fn run(command: Command) -> i32 {
+    match command {
+        Command::Check(path) => check(&path),
+        Command::Apply { input, output, json } => emit(&apply(&input, &output), json),
+        Command::Status { input, json } => emit(&status(&input), json),
+        Command::WriteDevice { input, device, confirmed } => write_device(&input, &device, confirmed),
+        Command::Batch { dir, output, json } => run_batch(&dir, &output, json),
+        Command::ListVersions => {
+            print!("{}", patches::supported_versions_table(&patches::known_patch_sets()));
+            0
+        }
+        Command::SelfTest => match self_test() {
+            Ok(message) => {
+                println!("{}", message);
+                0
+            }
+            Err(message) => {
+                eprintln!("{}", message);
+                1
+            }
+        },
+        Command::Help => {
+            println!("{}", USAGE);
+            0
+        }
+    }
+}
+
+/// Prints the detected version and the status of every patch. The file is only ever read.
+fn check(path: &Path) -> i32 {
+    let result = loader::load_file(path);
+    print_entries(&result.log);
+    let Some(file) = result.file else {
+        return 1;
+    };
+    println!("Version: {}", file.patch_set.version_string);
+    println!("Variant: {}", file.patch_set.hardware_variant.unwrap_or("N/A"));
+    for patch in &file.patch_set.patches {
+        // A patch found by its signature is printed at the offset it was found at.
+        let offset = patcher::patch_offset(&file.data, patch);
+        println!("{:<6} {:#07X}  {:?}", patch.name, offset, patcher::get_patch_status(&file.data, patch));
+    }
+    0
+}
+
+/// Prints an error message and returns the exit code for a failed command.
+fn fail(message: impl std::fmt::Display) -> i32 {
+    eprintln!("Error: {}", message);
+    1
+}
+
+/// Applies the patches to `input` and writes the result to `output`.
+///
+/// Files are loaded like in the GUI, patched in memory and written back in their own format. Raw
+/// binary images of at least `MAPPED_APPLY_MIN_SIZE` are patched through a memory map instead, so
+/// they are never read into memory as a whole. Either way, the output is read back and verified.
+fn apply(input: &Path, output: &Path) -> CliReport {
+    let mut report = CliReport::new(input);
+    let mut head = Vec::new();
+    let probed = fs::File::open(input).and_then(|file| {
+        let size = file.metadata()?.len();
+        file.take(FORMAT_PROBE_LEN).read_to_end(&mut head)?;
+        Ok(size)
+    });
+    let size = match probed {
+        Ok(size) => size,
+        Err(e) => {
+            report.error("io_error", loader::describe_io_error(&e));
+            return report;
+        }
+    };
+    if size >= MAPPED_APPLY_MIN_SIZE && !archive::is_zip_path(input) && ihex::detect_format(&head) == ImageFormat::Binary {
+        match mapped::apply_patches_to_file(input, output) {
+            Ok(applied) => {
+                report.detected_with(applied.patch_set, applied.statuses);
+                report.log.push(format!("Detected version: {}", applied.patch_set.version_string));
+                report.log.extend(applied.log);
+                report.log.push(format!("Success: Patched file saved to {}", output.display()));
+                report.log.push(format!("Success: Verified {} bytes", applied.verified_len));
+                report.output = Some(output.display().to_string());
+            }
+            Err(e) => report.error(e.error_code(), &e),
+        }
+        return report;
+    }
+
+    let result = loader::load_file(input);
+    report.extend_from_loader(result.log, result.error);
+    let Some(mut file) = result.file else {
+        return report;
+    };
+    match patcher::apply_patches(&mut file.data, file.patch_set) {
+        Ok(logs) => report.log.extend(logs),
+        Err(e) => {
+            report.detected(file.patch_set, &file.data);
+            report.error(e.error_code(), &e);
+            return report;
+        }
+    }
+    report.detected(file.patch_set, &file.data);
+    report.log.extend(patcher::version_change_warning(file.patch_set, &file.data));
+    let encoded = ihex::encode(&file.data, &file.format);
+    if let Err(e) = fs::write(output, &encoded) {
+        report.error("io_error", loader::describe_io_error(&e));
+        return report;
+    }
+    report.log.push(format!("Success: Patched file saved to {} ({})", output.display(), file.format.name()));
+    report.output = Some(output.display().to_string());
+    match verify::verify_written(output, &encoded) {
+        Ok(()) => report.log.push(format!("Success: Verified {} bytes", encoded.len())),
+        Err(e) => report.error(e.error_code(), format!("Verification failed, do not flash this file: {}", e)),
+    }
+    report
+}
+
+/// Asks on the terminal whether `len` bytes may be written to `device`. Only "yes" confirms.
+fn confirm_device_write(device: &Path, len: usize) -> bool {
+    print!(
+        "This overwrites the first {} bytes of {} without a backup. Type 'yes' to continue: ",
+        len,
+        device.display()
+    );
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok() && answer.trim() == "yes"
+}
+
+/// Applies the patches to `input` and writes the raw image straight to `device`, a flash device or
+/// EEPROM image, bypassing any save dialog.
+///
+/// The device must exist, so a mistyped path does not silently create a file. The data is flushed
+/// and synced before it is read back and compared.
+fn write_device(input: &Path, device: &Path, confirmed: bool) -> i32 {
+    let result = loader::load_file(input);
+    print_entries(&result.log);
+    let Some(mut file) = result.file else {
+        return 1;
+    };
+    match patcher::apply_patches(&mut file.data, file.patch_set) {
+        Ok(logs) => print_lines(&logs),
+        Err(e) => return fail(e),
+    }
+    if let Some(warning) = patcher::version_change_warning(file.patch_set, &file.data) {
+        return fail(format!("{} Nothing was written to {}.", warning, device.display()));
+    }
+    if !confirmed && !confirm_device_write(device, file.data.len()) {
+        return fail(format!("Cancelled; nothing was written to {}.", device.display()));
+    }
+    let written = fs::OpenOptions::new().write(true).open(device).and_then(|mut target| {
+        target.write_all(&file.data)?;
+        target.flush()?;
+        target.sync_all()
+    });
+    if let Err(e) = written {
+        return fail(format!("Could not write to {}: {}", device.display(), loader::describe_io_error(&e)));
+    }
+    println!("Success: Wrote {} bytes to {}", file.data.len(), device.display());
+    match verify::verify_written_prefix(device, &file.data) {
+        Ok(()) => {
+            println!("Success: Verified {} bytes", file.data.len());
+            0
+        }
+        Err(e) => fail(format!("Verification failed, the device does not hold the patched image: {}", e)),
+    }
+}
+
+/// Reports the detected version and the status of every patch.
+fn status(path: &Path) -> CliReport {
+    let mut report = CliReport::new(path);
+    let result = loader::load_file(path);
+    let Some(file) = result.file else {
+        // Only the reason is reported; the rest of the loader log is noise here.
+        report.extend_from_loader(result.log, result.error);
+        report.log.clear();
+        return report;
+    };
+    report.detected(file.patch_set, &file.data);
+    let statuses: Vec<String> = report.patches.iter().map(|patch| format!("{}: {}", patch.name, patch.status)).collect();
+    report.log.push(format!("Version: {}", file.patch_set.version_string));
+    report.log.push(statuses.join(", "));
+    report
+}
+
+/// Converts the result of one batch file into a report.
+fn batch_report(result: &BatchResult) -> CliReport {
+    let mut report = CliReport::new(&result.input);
+    match &result.outcome {
+        BatchOutcome::Patched { patch_set, statuses, output } => {
+            report.detected_with(patch_set, statuses.clone());
+            report.output = Some(output.display().to_string());
+        }
+        BatchOutcome::Skipped(reason) => report.skipped = Some(ErrorReport { code: reason.code, message: reason.message.clone() }),
+        BatchOutcome::Failed(reason) => report.error(reason.code, reason),
+    }
+    report
+}
+
+/// Patches every supported file in `dir`, printing one line per file, or with `json` an array of
+/// one `CliReport` per file. Fails if any supported file could not be patched; unsupported files
+/// are only reported.
+fn run_batch(dir: &Path, output: &Path, json: bool) -> i32 {
+    let results = match batch::batch_apply(dir, output) {
+        Ok(results) => results,
+        Err(e) if json => {
+            let mut report = CliReport::new(dir);
+            report.error("io_error", loader::describe_io_error(&e));
+            print_json(&[&report]);
+            return 1;
+        }
+        Err(e) => return fail(loader::describe_io_error(&e)),
+    };
+    if json {
+        let reports: Vec<CliReport> = results.iter().map(batch_report).collect();
+        print_json(&reports);
+        return i32::from(reports.iter().any(|report| report.exit_code() != 0));
+    }
+    let mut failed = 0;
+    for result in &results {
+        let name = result.input.display();
+        match &result.outcome {
+            BatchOutcome::Patched { patch_set, output, .. } => {
+                println!("Patched  {} ({}) -> {}", name, patch_set.version_string, output.display())
+            }
+            BatchOutcome::Skipped(reason) => println!("Skipped  {}: {}", name, reason),
+            BatchOutcome::Failed(reason) => {
+                failed += 1;
+                println!("Failed   {}: {}", name, reason);
+            }
+        }
+    }
+    let patched = results.iter().filter(|result| matches!(result.outcome, BatchOutcome::Patched { .. })).count();
+    println!("{} file(s) patched, {} failed, {} skipped.", patched, failed, results.len() - patched - failed);
+    i32::from(failed > 0)
+}
+
+fn print_lines(lines: &[String]) {
+    for line in lines {
+        println!("{}", line);
+    }
+}
+
+/// Prints the messages of GUI log entries, e.g. those of the loader.
+fn print_entries(entries: &[LogEntry]) {
+    for entry in entries {
+        println!("{}", entry.message);
+    }
+}
+
+/// Validates the built-in patch data and round-trips every set on a synthetic image. On failure, the
+/// message lists every problem found.
+pub fn self_test() -> Result<String, String> {
+    let sets = patches::get_all_patch_sets();
+    let mut errors: Vec<String> = patches::validate_all_patch_sets(&sets).iter().map(ToString::to_string).collect();
+    errors.extend(selftest::selftest());
+    if errors.is_empty() {
+        return Ok(format!("Self-test passed: {} patch sets are valid and round-trip cleanly.", sets.len()));
+    }
+    let mut message = format!("Self-test failed: the built-in patch data has {} problem(s):", errors.len());
+    for error in errors {
+        message.push_str(&format!("\n  - {}", error));
+    }
+    Err(message)
+}
+
+/// Parses the process arguments and runs the requested command.
+///
+/// `startup_log` holds messages from before the arguments were parsed; they are printed to stderr
+/// when a command runs. Returns `None` if the GUI should start, otherwise the exit code of the command.
+pub fn run_from_env(startup_log: &[LogEntry]) -> Option<i32> {
+    match parse_args(std::env::args().skip(1)) {
+        Ok(Some(command)) => {
+            for entry in startup_log {
+                eprintln!("{}", entry.message);
+            }
+            Some(run(command))
+        }
+        Ok(None) => None,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            Some(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_commands_and_rejects_unknown_arguments() {
+        assert_eq!(parse_args(args(&[])), Ok(None));
+        assert_eq!(parse_args(args(&["--list-versions"])), Ok(Some(Command::ListVersions)));
+        assert_eq!(parse_args(args(&["--self-test"])), Ok(Some(Command::SelfTest)));
+        assert_eq!(parse_args(args(&["--selftest"])), Ok(Some(Command::SelfTest)));
+        assert_eq!(parse_args(args(&["-h"])), Ok(Some(Command::Help)));
+        assert_eq!(parse_args(args(&["--check", "dump.bin"])), Ok(Some(Command::Check(PathBuf::from("dump.bin")))));
+        assert!(parse_args(args(&["--check"])).is_err());
+        assert!(parse_args(args(&["--bogus"])).is_err());
+        assert!(parse_args(args(&["--help", "--list-versions"])).is_err());
+        assert_eq!(
+            parse_args(args(&["--output", "out.bin", "--input", "in.bin"])),
+            Ok(Some(Command::Apply { input: PathBuf::from("in.bin"), output: PathBuf::from("out.bin"), json: false }))
+        );
+        assert!(parse_args(args(&["--input", "in.bin"])).is_err());
+        assert!(parse_args(args(&["--input", "in.bin", "--output", "out.bin", "--self-test"])).is_err());
+    }
+
+    #[test]
+    fn parses_the_apply_and_status_actions() {
+        let apply = Command::Apply { input: PathBuf::from("in.bin"), output: PathBuf::from("out.bin"), json: false };
+        assert_eq!(parse_args(args(&["--input", "in.bin", "--output", "out.bin", "--apply"])), Ok(Some(apply)));
+        assert_eq!(
+            parse_args(args(&["--status", "--input", "in.bin"])),
+            Ok(Some(Command::Status { input: PathBuf::from("in.bin"), json: false }))
+        );
+        assert!(parse_args(args(&["--input", "in.bin", "--apply"])).is_err());
+        assert!(parse_args(args(&["--apply", "--output", "out.bin"])).is_err());
+        assert!(parse_args(args(&["--input", "in.bin", "--output", "out.bin", "--status"])).is_err());
+        assert!(parse_args(args(&["--input", "in.bin", "--output", "out.bin", "--apply", "--status"])).is_err());
+        assert_eq!(
+            parse_args(args(&["--json", "--status", "--input", "in.bin"])),
+            Ok(Some(Command::Status { input: PathBuf::from("in.bin"), json: true }))
+        );
+        assert!(parse_args(args(&["--json"])).is_err());
+        assert!(parse_args(args(&["--list-versions", "--json"])).is_err());
+        assert!(parse_args(args(&["--input", "in.bin", "--device", "/dev/sdx", "--json"])).is_err());
+    }
+
+    #[test]
+    fn status_and_apply_reports_carry_the_version_statuses_and_errors() {
+        let dir = std::env::temp_dir().join(format!("ewsms43-cli-report-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let set = patches::known_patch_sets().into_iter().find(|set| set.version_string == "ca430037").unwrap();
+        let input = dir.join("dump.bin");
+        let output = dir.join("patched.bin");
+        fs::write(&input, selftest::build_synthetic_image(set).unwrap()).unwrap();
+
+        let before = status(&input);
+        let applied = apply(&input, &output);
+        let after = status(&output);
+        let missing = status(&dir.join("missing.bin"));
+        let reapplied = apply(&output, &dir.join("twice.bin"));
+        fs::write(dir.join("short.bin"), [0xFF; 0x100]).unwrap();
+        let short = status(&dir.join("short.bin"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(before.version.as_deref(), Some("ca430037"));
+        assert!(before.patches.iter().all(|patch| patch.status == "Unpatched"));
+        assert_eq!(before.log[1], "Jump: Unpatched, Code: Unpatched, DTC: Unpatched");
+        assert_eq!(applied.output, Some(output.display().to_string()));
+        assert_eq!((applied.exit_code(), after.exit_code()), (0, 0));
+        assert!(after.patches.iter().all(|patch| patch.status == "Patched"));
+        assert_eq!(missing.exit_code(), 1);
+        assert!(missing.log.is_empty() && missing.version.is_none());
+
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&after).unwrap()).unwrap();
+        assert_eq!(json["version"], "ca430037");
+        assert_eq!(json["patches"][0], serde_json::json!({ "name": "Jump", "status": "Patched" }));
+        assert!(json.get("skipped").is_none());
+
+        // Pipelines tell the failures apart by their code, not by the message.
+        let codes = |report: &CliReport| serde_json::to_value(report).unwrap()["errors"][0]["code"].clone();
+        assert_eq!(codes(&missing), "io_error");
+        assert_eq!(codes(&reapplied), "validation_mismatch");
+        assert_eq!(codes(&short), "file_too_small");
+        assert!(missing.errors[0].message.starts_with("Failed to read file: The file or folder does not exist."));
+        assert_eq!(json["errors"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn dumps_with_a_header_or_inside_a_zip_are_applied_like_in_the_gui() {
+        use std::io::Write;
+        let dir = std::env::temp_dir().join(format!("ewsms43-cli-loader-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let set = patches::known_patch_sets().into_iter().find(|set| set.version_string == "ca430037").unwrap();
+        let image = selftest::build_synthetic_image(set).unwrap();
+        let headered = dir.join("headered.bin");
+        let mut with_header = vec![0x5A; 0x200];
+        with_header.extend(&image);
+        fs::write(&headered, &with_header).unwrap();
+        let zipped = dir.join("dump.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&zipped).unwrap());
+        writer.start_file("dump.bin", zip::write::SimpleFileOptions::default()).unwrap();
+        writer.write_all(&image).unwrap();
+        writer.finish().unwrap();
+
+        let headered_applied = apply(&headered, &dir.join("headered_patched.bin"));
+        let headered_after = status(&dir.join("headered_patched.bin"));
+        let headered_output = fs::read(dir.join("headered_patched.bin")).unwrap();
+        let zipped_applied = apply(&zipped, &dir.join("zipped_patched.bin"));
+        let zipped_after = status(&dir.join("zipped_patched.bin"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        for (applied, after) in [(&headered_applied, &headered_after), (&zipped_applied, &zipped_after)] {
+            assert_eq!(applied.errors, Vec::<ErrorReport>::new());
+            assert!(applied.log.iter().any(|line| line.starts_with("Success: Verified")), "{:?}", applied.log);
+            assert!(applied.patches.iter().all(|patch| patch.status == "Patched"));
+            assert!(after.patches.iter().all(|patch| patch.status == "Patched"), "{:?}", after.patches);
+        }
+        assert_eq!(headered_output[..0x200], with_header[..0x200]);
+        assert_eq!(headered_output.len(), with_header.len());
+    }
+
+    #[test]
+    fn parses_the_batch_command() {
+        assert_eq!(
+            parse_args(args(&["--batch", "dumps"])),
+            Ok(Some(Command::Batch { dir: PathBuf::from("dumps"), output: PathBuf::from("dumps").join("patched"), json: false }))
+        );
+        assert_eq!(
+            parse_args(args(&["--batch", "dumps", "--output", "out", "--json"])),
+            Ok(Some(Command::Batch { dir: PathBuf::from("dumps"), output: PathBuf::from("out"), json: true }))
+        );
+        assert!(parse_args(args(&["--batch"])).is_err());
+        assert!(parse_args(args(&["--batch", "dumps", "--input", "in.bin"])).is_err());
+        assert!(parse_args(args(&["--batch", "dumps", "--list-versions"])).is_err());
+    }
+
+    #[test]
+    fn batch_json_reports_carry_the_variant_and_patch_statuses() {
+        let root = std::env::temp_dir().join(format!("ewsms43-cli-batch-{}", std::process::id()));
+        fs::create_dir_all(root.join("in")).unwrap();
+        let set = patches::known_patch_sets().into_iter().find(|set| set.hardware_variant == Some("5WK90017")).unwrap();
+        fs::write(root.join("in").join("car.bin"), selftest::build_synthetic_image(set).unwrap()).unwrap();
+        fs::write(root.join("in").join("notes.txt"), b"not firmware").unwrap();
+        let results = batch::batch_apply(&root.join("in"), &root.join("out")).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let reports: Vec<CliReport> = results.iter().map(batch_report).collect();
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&reports).unwrap()).unwrap();
+        assert_eq!(json[0]["version"], "ca430056");
+        assert_eq!(json[0]["variant"], "5WK90017");
+        let statuses: Vec<&serde_json::Value> = json[0]["patches"].as_array().unwrap().iter().map(|patch| &patch["status"]).collect();
+        assert_eq!(statuses, ["Patched", "Patched", "Patched"]);
+        assert_eq!(json[0]["patches"][2]["name"], "DTC");
+        assert_eq!(json[1]["skipped"]["code"], "file_too_small");
+        assert_eq!(json[1]["errors"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn parses_the_device_command() {
+        assert_eq!(
+            parse_args(args(&["--input", "in.bin", "--device", "/dev/sdx"])),
+            Ok(Some(Command::WriteDevice { input: PathBuf::from("in.bin"), device: PathBuf::from("/dev/sdx"), confirmed: false }))
+        );
+        assert_eq!(
+            parse_args(args(&["--device", "/dev/sdx", "--yes", "--input", "in.bin"])),
+            Ok(Some(Command::WriteDevice { input: PathBuf::from("in.bin"), device: PathBuf::from("/dev/sdx"), confirmed: true }))
+        );
+        assert!(parse_args(args(&["--device", "/dev/sdx"])).is_err());
+        assert!(parse_args(args(&["--input", "in.bin", "--device", "/dev/sdx", "--output", "out.bin"])).is_err());
+        assert!(parse_args(args(&["--input", "in.bin", "--output", "out.bin", "--yes"])).is_err());
+    }
+
+    #[test]
+    fn writing_to_a_device_needs_an_existing_target_and_verifies_it() {
+        let dir = std::env::temp_dir().join(format!("ewsms43-device-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let set = patches::known_patch_sets().into_iter().find(|set| set.version_string == "ca430037").unwrap();
+        let input = dir.join("dump.bin");
+        fs::write(&input, selftest::build_synthetic_image(set).unwrap()).unwrap();
+        // A device larger than the image keeps its tail.
+        let device = dir.join("eeprom.img");
+        fs::write(&device, vec![0xEE; 0x80010]).unwrap();
+
+        let missing = write_device(&input, &dir.join("missing"), true);
+        let written = write_device(&input, &device, true);
+        let contents = fs::read(&device).unwrap();
+        let created = dir.join("missing").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!((missing, created), (1, false));
+        assert_eq!(written, 0);
+        assert_eq!(patcher::check_patch_status(&contents, set).0, patcher::PatchStatus::Patched);
+        assert_eq!(contents[0x80000..], [0xEE; 16]);
+    }
+
+    #[test]
+    fn self_test_passes_on_the_builtin_data() {
+        assert_eq!(self_test(), Ok("Self-test passed: 5 patch sets are valid and round-trip cleanly.".to_string()));
+    }
+}
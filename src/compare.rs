@@ -0,0 +1,76 @@
+//! This module compares two firmware images byte by byte, e.g. a stock dump against a modified one,
+//! to find changes outside the known patch regions.
+
+/// How a run of bytes differs between the two images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    /// Both images hold the run, with different bytes.
+    Changed,
+    /// Only the second image holds the run; it is longer than the first.
+    Added,
+    /// Only the first image holds the run; it is longer than the second.
+    Removed,
+}
+
+/// A run of contiguous differing bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffRun {
+    pub offset: usize,
+    pub len: usize,
+    pub kind: DiffKind,
+}
+
+/// Returns the runs of bytes that differ between `a` and `b`, in ascending offset order.
+///
+/// Adjacent differing bytes are coalesced into one run. If the images differ in length, the tail of
+/// the longer one is reported as a single `Added` or `Removed` run.
+pub fn diff_buffers(a: &[u8], b: &[u8]) -> Vec<DiffRun> {
+    let common = a.len().min(b.len());
+    let mut runs = Vec::new();
+    let mut offset = 0;
+    while offset < common {
+        if a[offset] == b[offset] {
+            offset += 1;
+            continue;
+        }
+        let start = offset;
+        while offset < common && a[offset] != b[offset] {
+            offset += 1;
+        }
+        runs.push(DiffRun { offset: start, len: offset - start, kind: DiffKind::Changed });
+    }
+    if a.len() != b.len() {
+        let kind = if b.len() > a.len() { DiffKind::Added } else { DiffKind::Removed };
+        runs.push(DiffRun { offset: common, len: a.len().max(b.len()) - common, kind });
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn differing_bytes_are_coalesced_into_runs() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [0, 9, 9, 3, 4, 9, 6, 7];
+        assert_eq!(
+            diff_buffers(&a, &b),
+            vec![
+                DiffRun { offset: 1, len: 2, kind: DiffKind::Changed },
+                DiffRun { offset: 5, len: 1, kind: DiffKind::Changed },
+            ]
+        );
+        assert!(diff_buffers(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn the_tail_of_a_longer_image_is_added_or_removed() {
+        let short = [1, 2, 3];
+        let long = [1, 2, 4, 5, 6];
+        let changed = DiffRun { offset: 2, len: 1, kind: DiffKind::Changed };
+        assert_eq!(diff_buffers(&short, &long), vec![changed, DiffRun { offset: 3, len: 2, kind: DiffKind::Added }]);
+        assert_eq!(diff_buffers(&long, &short), vec![changed, DiffRun { offset: 3, len: 2, kind: DiffKind::Removed }]);
+        assert_eq!(diff_buffers(&[], &[7]), vec![DiffRun { offset: 0, len: 1, kind: DiffKind::Added }]);
+    }
+}
@@ -0,0 +1,145 @@
+//! This module persists user preferences between sessions as a JSON file in the user's config directory.
+
+use crate::gui::log_panel::LogEntry;
+use crate::gui::theme::Theme;
+use ewsms43::{patch_file, patches};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const CONFIG_DIR_NAME: &str = "ews-immo-patcher";
+const CONFIG_FILE_NAME: &str = "config.json";
+/// External patch set files looked up in the config directory, in load order.
+const PATCH_SET_FILE_NAMES: &[&str] = &["patch_sets.toml", "patch_sets.json"];
+/// Number of recently opened files remembered.
+pub const MAX_RECENT_FILES: usize = 10;
+
+/// Size and position of the main window, in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub width: u32,
+    pub height: u32,
+    /// Outer position of the window. Not available on every platform.
+    pub position: Option<(i32, i32)>,
+}
+
+/// Which modification time a saved firmware file gets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SavedFileTime {
+    /// The time of saving, as set by the operating system.
+    #[default]
+    Now,
+    /// The modification time of the file the data was loaded from.
+    MatchSource,
+}
+
+/// User preferences that survive restarts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub theme: Theme,
+    pub window: Option<WindowGeometry>,
+    pub saved_file_time: SavedFileTime,
+    /// Copy the loaded file to a timestamped backup before the first save.
+    pub create_backup: bool,
+    /// Rebuild the patched image in a separate buffer and compare it with the applied data before saving.
+    pub paranoid_mode: bool,
+    /// Recently loaded firmware files, most recent first.
+    pub recent_files: Vec<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            theme: Theme::default(),
+            window: None,
+            saved_file_time: SavedFileTime::default(),
+            create_backup: true,
+            paranoid_mode: false,
+            recent_files: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Moves `path` to the front of the recent files, keeping at most `MAX_RECENT_FILES`.
+    pub fn remember_recent_file(&mut self, path: &Path) {
+        self.recent_files.retain(|recent| recent != path);
+        self.recent_files.insert(0, path.to_path_buf());
+        self.recent_files.truncate(MAX_RECENT_FILES);
+    }
+}
+
+/// Returns the path of the config file, if the platform has a config directory.
+pub fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME))
+}
+
+/// Loads the config file, falling back to defaults if it is missing or unreadable.
+pub fn load() -> Config {
+    config_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// Writes the config file, creating the config directory if needed.
+pub fn save(config: &Config) -> io::Result<()> {
+    let path = config_path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No config directory available"))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let contents = serde_json::to_string_pretty(config).map_err(io::Error::other)?;
+    fs::write(path, contents)
+}
+
+/// Loads the external patch set files from the config directory and registers them for detection.
+///
+/// Missing files are skipped. Returns log lines describing what was loaded or why a file was rejected.
+pub fn load_external_patch_sets() -> Vec<LogEntry> {
+    let Some(dir) = dirs::config_dir().map(|dir| dir.join(CONFIG_DIR_NAME)) else {
+        return Vec::new();
+    };
+    let mut log = Vec::new();
+    for path in PATCH_SET_FILE_NAMES.iter().map(|name| dir.join(name)).filter(|path| path.exists()) {
+        let sets = match patch_file::load_patch_sets_from_file(&path) {
+            Ok(sets) => sets,
+            Err(e) => {
+                log.push(LogEntry::error(format!("Error: Could not load {}: {}", path.display(), e)));
+                continue;
+            }
+        };
+        match patches::register_patch_sets(sets) {
+            Ok(count) => log.push(LogEntry::info(format!("Loaded {} external patch set(s) from {}", count, path.display()))),
+            Err(errors) => {
+                log.push(LogEntry::error(format!("Error: Rejected the patch sets in {}:", path.display())));
+                log.extend(errors.iter().map(|e| LogEntry::error(format!("  - {}", e))));
+            }
+        }
+    }
+    log
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_files_are_deduplicated_and_capped() {
+        let mut config = Config::default();
+        for i in 0..MAX_RECENT_FILES + 2 {
+            config.remember_recent_file(Path::new(&format!("dump{}.bin", i)));
+        }
+        config.remember_recent_file(Path::new("dump5.bin"));
+        assert_eq!(config.recent_files.len(), MAX_RECENT_FILES);
+        assert_eq!(config.recent_files[0], Path::new("dump5.bin"));
+        assert_eq!(config.recent_files.iter().filter(|path| *path == Path::new("dump5.bin")).count(), 1);
+        assert_eq!(config.recent_files[1], Path::new("dump11.bin"));
+
+        // Older config files without the new fields keep the backup on.
+        let old: Config = serde_json::from_str(r#"{"saved_file_time": "MatchSource"}"#).unwrap();
+        assert!(old.create_backup);
+        assert!(old.recent_files.is_empty());
+    }
+}
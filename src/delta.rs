@@ -0,0 +1,215 @@
+//! This module reads and writes delta patch files, which carry only the changed regions of an image.
+//!
+//! A delta file starts with the magic bytes `EWSDELTA`, a format version byte and the target version
+//! string, followed by one record per changed region. Each record stores the offset, the length, the
+//! bytes expected before the change and the bytes written by it, so a delta is only ever applied to the
+//! firmware it was made from. All integers are little-endian.
+
+use crate::patcher::changed_patches;
+use crate::patches::PatchSet;
+use crate::version::{self, VersionError};
+
+/// Magic bytes at the start of every delta file.
+pub const DELTA_MAGIC: &[u8; 8] = b"EWSDELTA";
+/// Version of the delta file layout written by `encode_delta`.
+pub const DELTA_FORMAT_VERSION: u8 = 1;
+
+/// Errors that can occur while reading or applying a delta patch.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum DeltaError {
+    #[error("Not a delta patch file (missing EWSDELTA header).")]
+    BadMagic,
+    #[error("Unsupported delta format version {0}.")]
+    UnsupportedFormat(u8),
+    #[error("The delta patch file is truncated or corrupt.")]
+    Truncated,
+    #[error("The delta patch targets version {expected}, but the file is version {found}.")]
+    WrongVersion { expected: String, found: String },
+    #[error(transparent)]
+    Version(#[from] VersionError),
+    #[error("Region at offset {offset:#X} extends past the end of the file.")]
+    OutOfBounds { offset: usize },
+    #[error("Bytes at offset {offset:#X} do not match the delta's original bytes.")]
+    OriginalMismatch { offset: usize },
+}
+
+/// One changed region of a delta patch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeltaRecord {
+    pub offset: usize,
+    /// Bytes expected at `offset` before applying.
+    pub original: Vec<u8>,
+    /// Bytes written at `offset`. Always as long as `original`.
+    pub patched: Vec<u8>,
+}
+
+/// A delta patch for one firmware version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delta {
+    pub version_string: String,
+    pub records: Vec<DeltaRecord>,
+}
+
+impl Delta {
+    /// Builds a delta from the patch regions that differ between `before` and `after`, plus the
+    /// stored checksum if the patch set has a known checksum layout.
+    pub fn from_buffers(before: &[u8], after: &[u8], patch_set: &PatchSet) -> Delta {
+        let mut regions: Vec<(usize, usize)> =
+            changed_patches(before, after, patch_set).into_iter().map(|change| (change.offset, change.len)).collect();
+        if let Some(layout) = patch_set.checksum_layout() {
+            let value = layout.value_range();
+            if before.get(value.clone()) != after.get(value.clone()) {
+                regions.push((value.start, value.len()));
+            }
+        }
+        let records = regions
+            .into_iter()
+            .map(|(offset, len)| {
+                let end = (offset + len).min(before.len()).min(after.len());
+                DeltaRecord { offset, original: before[offset..end].to_vec(), patched: after[offset..end].to_vec() }
+            })
+            .collect();
+        Delta { version_string: patch_set.version_string.to_string(), records }
+    }
+}
+
+/// Serializes a delta patch to its binary file format.
+pub fn encode_delta(delta: &Delta) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(DELTA_MAGIC);
+    out.push(DELTA_FORMAT_VERSION);
+    out.push(delta.version_string.len() as u8);
+    out.extend_from_slice(delta.version_string.as_bytes());
+    out.extend_from_slice(&(delta.records.len() as u32).to_le_bytes());
+    for record in &delta.records {
+        out.extend_from_slice(&(record.offset as u32).to_le_bytes());
+        out.extend_from_slice(&(record.original.len() as u32).to_le_bytes());
+        out.extend_from_slice(&record.original);
+        out.extend_from_slice(&record.patched);
+    }
+    out
+}
+
+/// Reads a delta patch from its binary file format.
+pub fn decode_delta(bytes: &[u8]) -> Result<Delta, DeltaError> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(DELTA_MAGIC.len()).map_err(|_| DeltaError::BadMagic)? != DELTA_MAGIC {
+        return Err(DeltaError::BadMagic);
+    }
+    let format = reader.take(1)?[0];
+    if format != DELTA_FORMAT_VERSION {
+        return Err(DeltaError::UnsupportedFormat(format));
+    }
+    let version_len = reader.take(1)?[0] as usize;
+    let version_string = String::from_utf8(reader.take(version_len)?.to_vec()).map_err(|_| DeltaError::Truncated)?;
+
+    let count = reader.u32()?;
+    let mut records = Vec::new();
+    for _ in 0..count {
+        let offset = reader.u32()? as usize;
+        let len = reader.u32()? as usize;
+        let original = reader.take(len)?.to_vec();
+        let patched = reader.take(len)?.to_vec();
+        records.push(DeltaRecord { offset, original, patched });
+    }
+    if reader.pos != bytes.len() {
+        return Err(DeltaError::Truncated);
+    }
+    Ok(Delta { version_string, records })
+}
+
+/// Applies a delta patch to `data` and returns one log line per record.
+///
+/// The firmware version and the original bytes of every record are checked before anything is
+/// written, so `data` is left untouched on error.
+pub fn apply_delta(data: &mut [u8], delta: &Delta) -> Result<Vec<String>, DeltaError> {
+    let found = version::detect_version(data)?.version_string;
+    if found != delta.version_string {
+        return Err(DeltaError::WrongVersion { expected: delta.version_string.clone(), found: found.to_string() });
+    }
+    for record in &delta.records {
+        match data.get(record.offset..record.offset + record.original.len()) {
+            None => return Err(DeltaError::OutOfBounds { offset: record.offset }),
+            Some(region) if region != record.original.as_slice() => {
+                return Err(DeltaError::OriginalMismatch { offset: record.offset })
+            }
+            Some(_) => {}
+        }
+    }
+
+    let mut logs = Vec::new();
+    for record in &delta.records {
+        data[record.offset..record.offset + record.patched.len()].copy_from_slice(&record.patched);
+        logs.push(format!("Wrote {} byte(s) at offset {:#X}.", record.patched.len(), record.offset));
+    }
+    Ok(logs)
+}
+
+/// Sequential reader over the bytes of a delta file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DeltaError> {
+        let slice = self.bytes.get(self.pos..self.pos + len).ok_or(DeltaError::Truncated)?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, DeltaError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patcher::apply_patches;
+    use crate::testutil::{build_synthetic_firmware, builtin_patch_set};
+
+    #[test]
+    fn delta_round_trips_and_reproduces_the_patched_image() {
+        let patch_set = builtin_patch_set("ca430056");
+        let before = build_synthetic_firmware(&patch_set);
+        let mut after = before.clone();
+        apply_patches(&mut after, &patch_set).unwrap();
+
+        let delta = Delta::from_buffers(&before, &after, &patch_set);
+        assert_eq!(delta.records.len(), patch_set.patches.len());
+        let decoded = decode_delta(&encode_delta(&delta)).unwrap();
+        assert_eq!(decoded, delta);
+
+        let mut data = before.clone();
+        assert_eq!(apply_delta(&mut data, &decoded).unwrap().len(), delta.records.len());
+        assert_eq!(data, after);
+        // The bytes now differ from the originals, so a second apply is refused.
+        assert!(matches!(apply_delta(&mut data, &decoded), Err(DeltaError::OriginalMismatch { .. })));
+    }
+
+    #[test]
+    fn delta_is_refused_for_other_versions_and_corrupt_files() {
+        let patch_set = builtin_patch_set("ca430056");
+        let before = build_synthetic_firmware(&patch_set);
+        let mut after = before.clone();
+        apply_patches(&mut after, &patch_set).unwrap();
+        let delta = Delta::from_buffers(&before, &after, &patch_set);
+
+        let mut other = build_synthetic_firmware(&builtin_patch_set("ca430037"));
+        let untouched = other.clone();
+        assert_eq!(
+            apply_delta(&mut other, &delta),
+            Err(DeltaError::WrongVersion { expected: "ca430056".to_string(), found: "ca430037".to_string() })
+        );
+        assert_eq!(other, untouched);
+
+        let encoded = encode_delta(&delta);
+        assert_eq!(decode_delta(b"NOTADELTA"), Err(DeltaError::BadMagic));
+        assert_eq!(decode_delta(&encoded[..encoded.len() - 1]), Err(DeltaError::Truncated));
+        let mut future = encoded.clone();
+        future[DELTA_MAGIC.len()] = 9;
+        assert_eq!(decode_delta(&future), Err(DeltaError::UnsupportedFormat(9)));
+    }
+}
@@ -0,0 +1,65 @@
+//! This module identifies firmware files by their SHA-256 hash.
+//!
+//! Files whose hash is in `KNOWN_FINGERPRINTS` are identified even when the version string cannot be
+//! read at its usual offset, e.g. because the dump has a prepended header. A hash that does not match
+//! is not an error; most dumps differ from the stock files in their calibration data.
+
+use crate::patches::{self, PatchSet};
+use sha2::{Digest, Sha256};
+
+/// A known stock firmware file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownFingerprint {
+    /// SHA-256 of the whole file, as lowercase hex.
+    pub sha256: &'static str,
+    pub version_string: &'static str,
+    pub hardware_variant: Option<&'static str>,
+}
+
+/// Known stock firmware files, checked before the version string is read.
+///
+/// Only add hashes of dumps that were verified on a real ECU.
+pub const KNOWN_FINGERPRINTS: &[KnownFingerprint] = &[];
+
+/// Returns the SHA-256 hash of `data` as lowercase hex.
+pub fn sha256(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Returns the patch set of the fingerprint in `table` matching `hash`, if any.
+fn lookup(hash: &str, table: &[KnownFingerprint]) -> Option<&'static PatchSet> {
+    let known = table.iter().find(|known| known.sha256.eq_ignore_ascii_case(hash))?;
+    patches::known_patch_sets()
+        .into_iter()
+        .find(|set| set.version_string == known.version_string && set.hardware_variant == known.hardware_variant)
+}
+
+/// Identifies `data` by its hash. Returns `None` if the file is not a known stock file.
+pub fn identify(data: &[u8]) -> Option<&'static PatchSet> {
+    if KNOWN_FINGERPRINTS.is_empty() {
+        return None;
+    }
+    lookup(&sha256(data), KNOWN_FINGERPRINTS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{build_synthetic_firmware, builtin_patch_set};
+
+    #[test]
+    fn known_hashes_identify_the_patch_set() {
+        assert_eq!(sha256(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+        let data = build_synthetic_firmware(&builtin_patch_set("ca430037"));
+        let hash = sha256(&data).leak();
+        let table = [KnownFingerprint { sha256: hash, version_string: "ca430037", hardware_variant: None }];
+        assert_eq!(lookup(hash, &table).unwrap().version_string, "ca430037");
+        assert_eq!(lookup(&hash.to_ascii_uppercase(), &table).unwrap().version_string, "ca430037");
+        assert!(lookup(&sha256(b"other"), &table).is_none());
+
+        // A fingerprint whose patch set is not known does not identify anything.
+        let table = [KnownFingerprint { sha256: hash, version_string: "ca439999", hardware_variant: None }];
+        assert!(lookup(hash, &table).is_none());
+    }
+}
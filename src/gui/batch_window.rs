@@ -0,0 +1,87 @@
+//! This module runs the batch folder mode from the GUI and shows its per-file results.
+
+use crate::gui::loader::describe_io_error;
+use crate::gui::log_panel::LogEntry;
+use ewsms43::batch::{self, BatchOutcome, BatchResult};
+use imgui::{Condition, TableFlags, Ui};
+
+/// UI state of the batch results window.
+#[derive(Debug, Default)]
+pub struct BatchState {
+    pub open: bool,
+    pub results: Vec<BatchResult>,
+}
+
+/// Asks for an input and an output folder, patches every supported file and opens the results window.
+pub fn run_batch(state: &mut BatchState, log: &mut Vec<LogEntry>) {
+    let Some(dir) = rfd::FileDialog::new().set_title("Folder with the firmware files to patch").pick_folder() else {
+        return;
+    };
+    let Some(out_dir) = rfd::FileDialog::new()
+        .set_title("Folder for the patched files")
+        .set_directory(&dir)
+        .pick_folder()
+    else {
+        log.push(LogEntry::info("Batch patching cancelled."));
+        return;
+    };
+    match batch::batch_apply(&dir, &out_dir) {
+        Ok(results) => {
+            let patched = results.iter().filter(|result| matches!(result.outcome, BatchOutcome::Patched { .. })).count();
+            log.push(LogEntry::info(format!(
+                "Batch: patched {} of {} file(s) in {} into {}",
+                patched,
+                results.len(),
+                dir.display(),
+                out_dir.display()
+            )));
+            state.results = results;
+            state.open = true;
+        }
+        Err(e) => log.push(LogEntry::error(format!("Error: Batch patching failed: {}", describe_io_error(&e)))),
+    }
+}
+
+/// Renders the results of the last batch while the window is open.
+pub fn render_batch_results(ui: &Ui, state: &mut BatchState) {
+    if !state.open {
+        return;
+    }
+    let mut open = state.open;
+    ui.window("Batch Results")
+        .opened(&mut open)
+        .size([640.0, 300.0], Condition::FirstUseEver)
+        .build(|| {
+            let flags = TableFlags::BORDERS | TableFlags::ROW_BG | TableFlags::SIZING_FIXED_FIT;
+            if let Some(_table) = ui.begin_table_with_flags("BatchResultsTable", 3, flags) {
+                ui.table_setup_column("File");
+                ui.table_setup_column("Result");
+                ui.table_setup_column("Details");
+                ui.table_headers_row();
+                for result in &state.results {
+                    ui.table_next_row();
+                    ui.table_next_column();
+                    ui.text(result.input.file_name().map(|name| name.to_string_lossy()).unwrap_or_default());
+                    ui.table_next_column();
+                    match &result.outcome {
+                        BatchOutcome::Patched { patch_set, output, .. } => {
+                            ui.text_colored([0.1, 0.9, 0.1, 1.0], "Patched");
+                            ui.table_next_column();
+                            ui.text(format!("{} -> {}", patch_set.version_string, output.display()));
+                        }
+                        BatchOutcome::Skipped(reason) => {
+                            ui.text_disabled("Skipped");
+                            ui.table_next_column();
+                            ui.text(&reason.message);
+                        }
+                        BatchOutcome::Failed(reason) => {
+                            ui.text_colored([0.9, 0.1, 0.1, 1.0], "Failed");
+                            ui.table_next_column();
+                            ui.text(&reason.message);
+                        }
+                    }
+                }
+            }
+        });
+    state.open = open;
+}
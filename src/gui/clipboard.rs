@@ -0,0 +1,25 @@
+//! This module connects imgui's clipboard to the system clipboard, so text copied in the patcher can be
+//! pasted into other programs, e.g. a forum post.
+
+use imgui::ClipboardBackend;
+
+/// The system clipboard, as an imgui clipboard backend.
+pub struct SystemClipboard(arboard::Clipboard);
+
+impl SystemClipboard {
+    /// Opens the system clipboard. Returns `None` if there is none, e.g. on a headless Linux system.
+    pub fn new() -> Option<Self> {
+        arboard::Clipboard::new().ok().map(SystemClipboard)
+    }
+}
+
+impl ClipboardBackend for SystemClipboard {
+    fn get(&mut self) -> Option<String> {
+        self.0.get_text().ok()
+    }
+
+    fn set(&mut self, value: &str) {
+        // imgui has no way to report a failure; the text is simply not copied then.
+        let _ = self.0.set_text(value.to_owned());
+    }
+}
@@ -0,0 +1,120 @@
+//! This module renders the Compare tab, which lists every difference between the loaded firmware and a
+//! second file, such as a stock dump and a modified one.
+
+use crate::gui::loader::describe_io_error;
+use crate::gui::log_panel::LogEntry;
+use crate::gui::main_window::AppState;
+use ewsms43::compare::{self, DiffKind};
+use ewsms43::ihex;
+use ewsms43::report::bytes_to_hex_string;
+use imgui::{ListClipper, TableFlags, Ui};
+use std::fs;
+
+/// Bytes of a run shown per side. Longer runs are cut off with "...".
+const MAX_SHOWN_BYTES: usize = 16;
+
+/// Opens a file dialog and reads the chosen file into the compare slot.
+fn load_compare_file(app_state: &mut AppState) {
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter("Firmware files", &["bin", "dat", "hex", "s19", "s28", "s37", "srec", "mot"])
+        .pick_file()
+    else {
+        return;
+    };
+    let decoded = match fs::read(&path) {
+        Ok(contents) => ihex::decode(contents),
+        Err(e) => {
+            app_state.log.push(LogEntry::error(format!("Error: Failed to read the file to compare: {}", describe_io_error(&e))));
+            return;
+        }
+    };
+    match decoded {
+        Ok(decoded) => {
+            app_state.log.push(LogEntry::info(format!("Loaded {} ({} bytes) for comparison.", path.display(), decoded.data.len())));
+            app_state.compare_data = Some(decoded.data);
+            app_state.compare_path = path.display().to_string();
+        }
+        Err(e) => app_state.log.push(LogEntry::error(format!("Error: Failed to decode the file to compare: {}", e))),
+    }
+}
+
+/// Formats up to `MAX_SHOWN_BYTES` of `data` starting at `offset`, or "-" if there are none.
+fn run_hex(data: &[u8], offset: usize, len: usize) -> String {
+    let end = (offset + len).min(data.len());
+    let Some(bytes) = data.get(offset..end).filter(|bytes| !bytes.is_empty()) else {
+        return "-".to_string();
+    };
+    match bytes.get(..MAX_SHOWN_BYTES) {
+        Some(shown) if bytes.len() > MAX_SHOWN_BYTES => format!("{} ...", bytes_to_hex_string(shown)),
+        _ => bytes_to_hex_string(bytes),
+    }
+}
+
+/// Renders the Compare tab.
+pub fn render_compare_panel(ui: &Ui, app_state: &mut AppState) {
+    if ui.button("Load File to Compare...") {
+        load_compare_file(app_state);
+    }
+    ui.same_line();
+    ui.disabled(app_state.compare_data.is_none(), || {
+        if ui.button("Clear") {
+            app_state.compare_data = None;
+            app_state.compare_path.clear();
+        }
+    });
+
+    let (Some(loaded), Some(other)) = (app_state.file_data.as_ref(), app_state.compare_data.as_ref()) else {
+        ui.text("Load a firmware file in the 'Patcher' tab and a second file here to list their differences.");
+        return;
+    };
+    ui.text(format!("Loaded: {} ({} bytes)", app_state.file_path, loaded.len()));
+    ui.text(format!("Compared: {} ({} bytes)", app_state.compare_path, other.len()));
+
+    let runs = compare::diff_buffers(loaded, other);
+    let differing: usize = runs.iter().map(|run| run.len).sum();
+    ui.text(format!("{} differing run(s), {} byte(s) in total.", runs.len(), differing));
+    if runs.is_empty() {
+        return;
+    }
+
+    let flags = TableFlags::BORDERS | TableFlags::ROW_BG | TableFlags::SCROLL_Y | TableFlags::SIZING_FIXED_FIT;
+    if let Some(_table) = ui.begin_table_with_sizing("CompareRunsTable", 4, flags, [0.0, 0.0], 0.0) {
+        ui.table_setup_scroll_freeze(0, 1);
+        ui.table_setup_column("Offset");
+        ui.table_setup_column("Length");
+        ui.table_setup_column("Loaded");
+        ui.table_setup_column("Compared");
+        ui.table_headers_row();
+
+        let clipper = ListClipper::new(runs.len() as i32).begin(ui);
+        for index in clipper.iter() {
+            let run = runs[index as usize];
+            ui.table_next_row();
+            ui.table_next_column();
+            ui.text(format!("{:#07X}", run.offset));
+            ui.table_next_column();
+            match run.kind {
+                DiffKind::Changed => ui.text(format!("{}", run.len)),
+                DiffKind::Added => ui.text_colored([0.2, 0.8, 0.2, 1.0], format!("{} (added)", run.len)),
+                DiffKind::Removed => ui.text_colored([1.0, 0.2, 0.2, 1.0], format!("{} (removed)", run.len)),
+            }
+            ui.table_next_column();
+            ui.text(run_hex(loaded, run.offset, run.len));
+            ui.table_next_column();
+            ui.text(run_hex(other, run.offset, run.len));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_runs_are_cut_off_and_missing_sides_shown_as_dashes() {
+        let data: Vec<u8> = (0..32).collect();
+        assert_eq!(run_hex(&data, 0, 2), "00 01");
+        assert_eq!(run_hex(&data, 0, 20), format!("{} ...", bytes_to_hex_string(&data[..16])));
+        assert_eq!(run_hex(&data, 32, 4), "-");
+    }
+}
@@ -0,0 +1,73 @@
+//! This module renders a window comparing two patch-set revisions patch by patch.
+
+use ewsms43::patches::{self, PatchDiff};
+use ewsms43::PatchSet;
+use imgui::{Condition, Ui};
+
+/// UI state of the patch-set comparison window.
+#[derive(Debug, Default)]
+pub struct CompareSetsState {
+    pub open: bool,
+    /// Index of the old and new patch set in `known_patch_sets()`.
+    pub old_index: usize,
+    pub new_index: usize,
+}
+
+impl CompareSetsState {
+    /// Opens the window, comparing `loaded` (if any) against the other sets.
+    pub fn open_for(&mut self, loaded: Option<&PatchSet>) {
+        let sets = patches::known_patch_sets();
+        if let Some(index) = loaded.and_then(|loaded| sets.iter().position(|&set| set == loaded)) {
+            self.old_index = index;
+        }
+        self.open = true;
+    }
+}
+
+/// Returns a label such as "ca430056 (5WK90015)".
+fn set_label(set: &PatchSet) -> String {
+    match set.hardware_variant {
+        Some(variant) => format!("{} ({})", set.version_string, variant),
+        None => set.version_string.to_string(),
+    }
+}
+
+/// Renders the comparison window while it is open.
+pub fn render_compare_sets(ui: &Ui, state: &mut CompareSetsState) {
+    if !state.open {
+        return;
+    }
+    let sets = patches::known_patch_sets();
+    let labels: Vec<String> = sets.iter().map(|set| set_label(set)).collect();
+
+    let mut open = state.open;
+    ui.window("Compare Patch Sets")
+        .opened(&mut open)
+        .size([560.0, 260.0], Condition::FirstUseEver)
+        .collapsible(false)
+        .build(|| {
+            ui.set_next_item_width(180.0);
+            ui.combo_simple_string("Old", &mut state.old_index, &labels);
+            ui.same_line();
+            ui.set_next_item_width(180.0);
+            ui.combo_simple_string("New", &mut state.new_index, &labels);
+            ui.separator();
+
+            let (Some(old), Some(new)) = (sets.get(state.old_index), sets.get(state.new_index)) else {
+                return;
+            };
+            let diffs = patches::diff_patch_sets(old, new);
+            if diffs.is_empty() {
+                ui.text("The patch sets define identical patches.");
+            }
+            for diff in &diffs {
+                let color = match diff {
+                    PatchDiff::Added(_) => [0.2, 0.8, 0.2, 1.0],
+                    PatchDiff::Removed(_) => [1.0, 0.2, 0.2, 1.0],
+                    PatchDiff::Changed { .. } => [1.0, 0.7, 0.1, 1.0],
+                };
+                ui.text_colored(color, diff.to_string());
+            }
+        });
+    state.open = open;
+}
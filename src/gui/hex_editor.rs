@@ -0,0 +1,226 @@
+//! This module renders the editable hex view of the loaded firmware image.
+
+use crate::gui::log_panel::LogEntry;
+use crate::gui::main_window::{push_undo_snapshot, refuse_if_read_only, AppState};
+use ewsms43::{check_all_statuses, PatchSet};
+use imgui::{ListClipper, StyleColor, Ui};
+
+const BYTES_PER_ROW: usize = 16;
+
+/// UI state of the hex editor.
+#[derive(Debug, Default)]
+pub struct HexEditorState {
+    /// Offset of the byte currently being edited.
+    pub editing_offset: Option<usize>,
+    /// Text typed for the new byte value.
+    pub input: String,
+    /// Offset to scroll into view on the next frame.
+    pub scroll_to: Option<usize>,
+    /// `(offset, length)` of a byte range to highlight, e.g. a search match.
+    pub highlight: Option<(usize, usize)>,
+    /// Text typed into the "Go to offset" field.
+    pub goto_input: String,
+}
+
+/// Text color of bytes inside a patch region.
+const PATCH_REGION_COLOR: [f32; 4] = [0.3, 0.7, 1.0, 1.0];
+
+/// Parses a "Go to offset" target: a hex file offset, or a mapped address if `patch_set` is known
+/// and the value lies above its base address.
+fn parse_goto_target(input: &str, len: usize, patch_set: Option<&PatchSet>) -> Result<usize, String> {
+    let trimmed = input.trim();
+    let digits = trimmed.strip_prefix("0x").or_else(|| trimmed.strip_prefix("0X")).unwrap_or(trimmed);
+    let value = usize::from_str_radix(digits, 16).map_err(|_| format!("'{}' is not a hex offset.", trimmed))?;
+    if value < len {
+        return Ok(value);
+    }
+    match patch_set.and_then(|set| set.address_to_offset(value)).filter(|&offset| offset < len) {
+        Some(offset) => Ok(offset),
+        None => Err(format!("{:#X} is outside the file ({} bytes).", value, len)),
+    }
+}
+
+/// Returns true if `offset` lies inside the region of any patch of `patch_set`.
+fn in_patch_region(patch_set: Option<&PatchSet>, offset: usize) -> bool {
+    patch_set.is_some_and(|set| {
+        set.patches
+            .iter()
+            .any(|patch| (patch.offset..patch.offset + patch.original.len().max(patch.patched.len())).contains(&offset))
+    })
+}
+
+/// Formats bytes as printable ASCII, with `.` for everything else.
+pub fn ascii_gutter(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect()
+}
+
+/// Parses a single byte written as one or two hex digits.
+fn parse_hex_byte(input: &str) -> Option<u8> {
+    let trimmed = input.trim();
+    if trimmed.is_empty() || trimmed.len() > 2 {
+        return None;
+    }
+    u8::from_str_radix(trimmed, 16).ok()
+}
+
+/// Writes a single byte into the loaded data and marks it as modified. Each edit is its own undo step.
+pub fn write_byte(app_state: &mut AppState, offset: usize, input: &str) -> Result<(), String> {
+    if refuse_if_read_only(app_state, "edit bytes") {
+        return Err("Inspect mode is on.".to_string());
+    }
+    let value = parse_hex_byte(input).ok_or_else(|| format!("'{}' is not a hex byte (00-FF).", input.trim()))?;
+    let data = app_state.file_data.as_ref().ok_or("No file is loaded.")?;
+    let old = *data
+        .get(offset)
+        .ok_or_else(|| format!("Offset {:#X} is outside the file ({} bytes).", offset, data.len()))?;
+
+    let before = data.clone();
+    push_undo_snapshot(app_state, before);
+    let data = app_state.file_data.as_mut().ok_or("No file is loaded.")?;
+    data[offset] = value;
+    app_state.dirty_offsets.insert(offset);
+    app_state.dirty = true;
+    app_state.log.push(LogEntry::info(format!("Edited byte at offset {:#X}: {:02X} -> {:02X}", offset, old, value)));
+    if let Some(patch_set) = app_state.patch_set {
+        app_state.patch_status = check_all_statuses(data, patch_set);
+    }
+    Ok(())
+}
+
+/// Renders the hex editor: an input for the selected byte and a scrollable grid of the whole file.
+pub fn render_hex_editor(ui: &Ui, app_state: &mut AppState) {
+    let Some(len) = app_state.file_data.as_ref().map(|data| data.len()) else {
+        ui.text("Load a file to edit its bytes.");
+        return;
+    };
+
+    if app_state.read_only {
+        ui.text("Hex View (read-only: inspect mode is on)");
+        app_state.hex_editor.editing_offset = None;
+    } else {
+        ui.text("Hex Editor (click a byte to edit it, modified bytes are shown in orange, patch regions in blue)");
+    }
+
+    ui.set_next_item_width(100.0);
+    let go = ui
+        .input_text("##GotoOffset", &mut app_state.hex_editor.goto_input)
+        .hint("0x70040")
+        .enter_returns_true(true)
+        .build();
+    ui.same_line();
+    if ui.button("Go to offset") || go {
+        match parse_goto_target(&app_state.hex_editor.goto_input, len, app_state.patch_set) {
+            Ok(offset) => {
+                app_state.hex_editor.scroll_to = Some(offset);
+                app_state.hex_editor.highlight = Some((offset, 1));
+            }
+            Err(e) => app_state.log.push(LogEntry::error(format!("Error: {}", e))),
+        }
+    }
+    if let Some(offset) = app_state.hex_editor.editing_offset {
+        ui.set_next_item_width(60.0);
+        let committed = ui
+            .input_text(format!("New value at {:#X} (Enter to write)", offset), &mut app_state.hex_editor.input)
+            .chars_hexadecimal(true)
+            .enter_returns_true(true)
+            .build();
+        if committed {
+            let input = app_state.hex_editor.input.clone();
+            match write_byte(app_state, offset, &input) {
+                Ok(()) => app_state.hex_editor.editing_offset = None,
+                Err(e) => app_state.log.push(LogEntry::error(format!("Error: {}", e))),
+            }
+        }
+    }
+
+    ui.child_window("HexEditorGrid").size([0.0, 0.0]).border(true).build(|| {
+        if let Some(offset) = app_state.hex_editor.scroll_to.take() {
+            let row = offset / BYTES_PER_ROW;
+            ui.set_scroll_y(row as f32 * ui.text_line_height_with_spacing());
+        }
+        let highlight = app_state.hex_editor.highlight;
+        let patch_set = app_state.patch_set;
+        let rows = len.div_ceil(BYTES_PER_ROW);
+        let clipper = ListClipper::new(rows as i32).begin(ui);
+        for row in clipper.iter() {
+            let row_offset = row as usize * BYTES_PER_ROW;
+            ui.text_disabled(format!("{:06X}", row_offset));
+            for offset in row_offset..(row_offset + BYTES_PER_ROW).min(len) {
+                ui.same_line();
+                let value = app_state.file_data.as_ref().map_or(0, |data| data[offset]);
+                let color = if app_state.dirty_offsets.contains(&offset) {
+                    Some([1.0, 0.6, 0.1, 1.0])
+                } else {
+                    in_patch_region(patch_set, offset).then_some(PATCH_REGION_COLOR)
+                };
+                let _byte_color = color.map(|color| ui.push_style_color(StyleColor::Text, color));
+                if ui
+                    .selectable_config(format!("{:02X}##{}", value, offset))
+                    .selected(
+                        app_state.hex_editor.editing_offset == Some(offset)
+                            || highlight.is_some_and(|(start, len)| (start..start + len).contains(&offset)),
+                    )
+                    .size([18.0, 0.0])
+                    .build()
+                    && !app_state.read_only
+                {
+                    app_state.hex_editor.editing_offset = Some(offset);
+                    app_state.hex_editor.input = format!("{:02X}", value);
+                }
+            }
+            if let Some(data) = app_state.file_data.as_ref() {
+                ui.same_line_with_spacing(0.0, 12.0);
+                ui.text_disabled(ascii_gutter(&data[row_offset..(row_offset + BYTES_PER_ROW).min(len)]));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_byte_parsing_rejects_invalid_input() {
+        assert_eq!(parse_hex_byte("a5"), Some(0xA5));
+        assert_eq!(parse_hex_byte(" F "), Some(0x0F));
+        assert_eq!(parse_hex_byte(""), None);
+        assert_eq!(parse_hex_byte("100"), None);
+        assert_eq!(parse_hex_byte("zz"), None);
+    }
+
+    #[test]
+    fn goto_targets_accept_offsets_and_mapped_addresses() {
+        let patch_set = ewsms43::patches::get_all_patch_sets().remove(0);
+        assert_eq!(parse_goto_target("0x70040", 0x80000, None), Ok(0x70040));
+        assert_eq!(parse_goto_target("70040", 0x80000, None), Ok(0x70040));
+        assert_eq!(parse_goto_target("0x870040", 0x80000, Some(&patch_set)), Ok(0x70040));
+        assert!(parse_goto_target("0x870040", 0x80000, None).is_err());
+        assert!(parse_goto_target("0xZZ", 0x80000, None).is_err());
+
+        assert!(in_patch_region(Some(&patch_set), patch_set.patches[0].offset + 3));
+        assert!(!in_patch_region(Some(&patch_set), patch_set.patches[0].offset + 4));
+        assert_eq!(ascii_gutter(b"ca43\x00\xFF "), "ca43.. ");
+    }
+
+    #[test]
+    fn write_byte_marks_the_byte_dirty_and_guards_the_range() {
+        let mut app_state = AppState { file_data: Some(vec![0x00; 4]), ..AppState::default() };
+        write_byte(&mut app_state, 2, "7f").unwrap();
+        assert_eq!(app_state.file_data.as_deref(), Some(&[0x00, 0x00, 0x7F, 0x00][..]));
+        assert!(app_state.dirty_offsets.contains(&2));
+
+        assert!(write_byte(&mut app_state, 4, "00").is_err());
+        assert!(write_byte(&mut app_state, 0, "xyz").is_err());
+        assert_eq!(app_state.dirty_offsets.len(), 1);
+    }
+
+    #[test]
+    fn write_byte_is_refused_in_inspect_mode() {
+        let mut app_state = AppState { file_data: Some(vec![0x00; 4]), read_only: true, ..AppState::default() };
+        assert!(write_byte(&mut app_state, 0, "ff").is_err());
+        assert_eq!(app_state.file_data.as_deref(), Some(&[0x00; 4][..]));
+        assert!(app_state.dirty_offsets.is_empty());
+        assert!(app_state.log.last().unwrap().message.contains("inspect mode"));
+    }
+}
@@ -0,0 +1,458 @@
+//! This module loads firmware files on a worker thread so the UI stays responsive while reading from slow drives.
+
+use crate::gui::log_panel::LogEntry;
+use ewsms43::archive::{self, LoadError};
+use ewsms43::fingerprint;
+use ewsms43::ihex::{self, FormatError, ImageFormat};
+use ewsms43::patcher::{self, PatchStatus};
+use ewsms43::version::{self, Detection, VersionProbe};
+use ewsms43::{patches, PatchSet, VersionError};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// A firmware image that was read, decoded and matched to a patch set.
+pub struct LoadedFile {
+    pub data: Vec<u8>,
+    pub format: ImageFormat,
+    pub patch_set: &'static PatchSet,
+    /// Hardware variant read from the file, if it holds a readable one.
+    pub hardware_variant: Option<String>,
+    /// SHA-256 of the decoded image, as lowercase hex.
+    pub sha256: String,
+    /// Every patch set of the detected version, one per hardware variant. Includes `patch_set`.
+    pub candidates: Vec<&'static PatchSet>,
+    /// Length of the header in front of the flash image, which the offsets of `patch_set` and
+    /// `candidates` are shifted by. Zero for plain dumps.
+    pub base_offset: usize,
+}
+
+/// A decoded image whose version exists on several hardware variants that the file does not tell apart.
+pub struct VariantChoice {
+    pub data: Vec<u8>,
+    pub format: ImageFormat,
+    /// The patch sets the user can pick from, one per hardware variant, already shifted by `base_offset`.
+    pub candidates: Vec<&'static PatchSet>,
+    /// Length of the header in front of the flash image, as in `LoadedFile::base_offset`.
+    pub base_offset: usize,
+}
+
+/// The firmware files of a ZIP archive holding more than one, for the user to pick from.
+pub struct ZipChoice {
+    /// `(entry name, contents)` of every `.bin` and `.dat` file, in archive order.
+    pub entries: Vec<(String, Vec<u8>)>,
+}
+
+/// The outcome of loading a file, sent back from the worker thread.
+pub struct LoadResult {
+    pub path: PathBuf,
+    /// Log lines produced while loading, in order.
+    pub log: Vec<LogEntry>,
+    /// The loaded file, or `None` if reading, decoding or version detection failed.
+    pub file: Option<LoadedFile>,
+    /// What each version location held, if the file could be decoded.
+    pub probes: Vec<VersionProbe>,
+    /// Set instead of `file` when the user has to pick the hardware variant.
+    pub variant_choice: Option<VariantChoice>,
+    /// Set instead of `file` when the user has to pick a file out of a ZIP archive.
+    pub zip_choice: Option<ZipChoice>,
+    /// Name of the archive entry that was loaded, if `path` is a ZIP archive.
+    pub zip_entry: Option<String>,
+    /// Why the file could not be loaded, as also logged. An ambiguous hardware variant is reported here
+    /// as well as in `variant_choice`; a ZIP archive holding several files is not an error.
+    pub error: Option<LoadFailure>,
+}
+
+/// Why a file could not be read, decoded or detected.
+#[derive(Debug, thiserror::Error)]
+pub enum LoadFailure {
+    #[error("Failed to read file: {}", describe_io_error(.0))]
+    Io(io::Error),
+    #[error("{0}")]
+    Archive(LoadError),
+    #[error("Failed to decode file: {0}")]
+    Format(FormatError),
+    #[error("Version detection failed: {0}")]
+    Version(VersionError),
+}
+
+impl LoadFailure {
+    /// Returns a stable, machine-readable code identifying the kind of error, e.g. the code of the
+    /// version error it wraps.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            LoadFailure::Io(_) => "io_error",
+            LoadFailure::Archive(e) => e.error_code(),
+            LoadFailure::Format(e) => e.error_code(),
+            LoadFailure::Version(e) => e.error_code(),
+        }
+    }
+}
+
+/// Turns an I/O error into a message that tells the user what to do about it.
+pub fn describe_io_error(e: &io::Error) -> String {
+    // Windows reports files locked by another program as sharing (32) or lock (33) violations.
+    if cfg!(windows) && matches!(e.raw_os_error(), Some(32 | 33)) {
+        return "The file is locked by another program. Close that program and try again.".to_string();
+    }
+    match e.kind() {
+        io::ErrorKind::PermissionDenied => {
+            "Permission denied. The file or folder is read-only, locked by another program, or you lack access rights.".to_string()
+        }
+        io::ErrorKind::NotFound => "The file or folder does not exist. It may have been moved or deleted.".to_string(),
+        _ => e.to_string(),
+    }
+}
+
+/// Reads, decodes and detects the version of a firmware file.
+///
+/// A ZIP archive holding a single `.bin` or `.dat` file loads that file. If it holds several, none is
+/// loaded and the result carries a `ZipChoice` instead.
+pub fn load_file(path: &Path) -> LoadResult {
+    let mut log = Vec::new();
+    let mut probes = Vec::new();
+    let mut variant_choice = None;
+    let mut zip_choice = None;
+    let mut zip_entry = None;
+    let loaded = match read_contents(path, &mut log, &mut zip_choice, &mut zip_entry) {
+        Ok(Some(contents)) => decode_and_detect(contents, &mut log, &mut probes, &mut variant_choice).map(Some),
+        Ok(None) => Ok(None),
+        Err(e) => Err(e),
+    };
+    let (file, error) = match loaded {
+        Ok(file) => (file, None),
+        Err(e) => (None, Some(e)),
+    };
+    LoadResult { path: path.to_path_buf(), log, file, probes, variant_choice, zip_choice, zip_entry, error }
+}
+
+/// Decodes and detects the version of `contents`, which were already read from `path`, e.g. a file the
+/// user picked out of a ZIP archive. `zip_entry` names that file inside the archive.
+pub fn load_contents(path: &Path, contents: Vec<u8>, zip_entry: Option<String>) -> LoadResult {
+    let mut log = Vec::new();
+    let mut probes = Vec::new();
+    let mut variant_choice = None;
+    let (file, error) = match decode_and_detect(contents, &mut log, &mut probes, &mut variant_choice) {
+        Ok(file) => (Some(file), None),
+        Err(e) => (None, Some(e)),
+    };
+    LoadResult { path: path.to_path_buf(), log, file, probes, variant_choice, zip_choice: None, zip_entry, error }
+}
+
+/// Logs `failure` and returns it as the error of a load.
+fn fail<T>(log: &mut Vec<LogEntry>, failure: LoadFailure) -> Result<T, LoadFailure> {
+    log.push(LogEntry::error(format!("Error: {}", failure)));
+    Err(failure)
+}
+
+/// Reads the file at `path`, or the only firmware file inside it if it is a ZIP archive. The name of
+/// that file is stored in `zip_entry`. Returns `Ok(None)` if the archive holds several firmware files.
+fn read_contents(
+    path: &Path,
+    log: &mut Vec<LogEntry>,
+    zip_choice: &mut Option<ZipChoice>,
+    zip_entry: &mut Option<String>,
+) -> Result<Option<Vec<u8>>, LoadFailure> {
+    if !archive::is_zip_path(path) {
+        return match fs::read(path) {
+            Ok(contents) => {
+                log.push(LogEntry::success(format!("Successfully read {} bytes.", contents.len())));
+                Ok(Some(contents))
+            }
+            Err(e) => fail(log, LoadFailure::Io(e)),
+        };
+    }
+    let mut entries = match archive::load_from_zip(path) {
+        Ok(entries) => entries,
+        Err(LoadError::Io(e)) => return fail(log, LoadFailure::Io(e)),
+        Err(e) => return fail(log, LoadFailure::Archive(e)),
+    };
+    if entries.len() > 1 {
+        log.push(LogEntry::info(format!("The archive holds {} firmware files; pick the one to load.", entries.len())));
+        *zip_choice = Some(ZipChoice { entries });
+        return Ok(None);
+    }
+    let (name, contents) = entries.remove(0);
+    log.push(LogEntry::success(format!("Extracted {} ({} bytes) from the archive.", name, contents.len())));
+    *zip_entry = Some(name);
+    Ok(Some(contents))
+}
+
+fn decode_and_detect(
+    contents: Vec<u8>,
+    log: &mut Vec<LogEntry>,
+    probes: &mut Vec<VersionProbe>,
+    variant_choice: &mut Option<VariantChoice>,
+) -> Result<LoadedFile, LoadFailure> {
+    let decoded = match ihex::decode(contents) {
+        Ok(decoded) => decoded,
+        Err(e) => return fail(log, LoadFailure::Format(e)),
+    };
+    if decoded.format != ImageFormat::Binary {
+        log.push(LogEntry::info(format!("Decoded {} file into a {} byte image.", decoded.format.name(), decoded.data.len())));
+    }
+    for warning in &decoded.warnings {
+        log.push(LogEntry::warning(format!("Warning: {}", warning)));
+    }
+    let data = decoded.data;
+    *probes = version::probe_versions(&data);
+    let sha256 = fingerprint::sha256(&data);
+
+    let detection = match version::detect(&data) {
+        Ok(detection) => detection,
+        Err(e) => {
+            log.push(LogEntry::error(format!("Error: {}", LoadFailure::Version(e.clone()))));
+            if let VersionError::UnsupportedVersion(found) = &e {
+                match version::closest_known_version(found) {
+                    Some(closest) => log.push(LogEntry::info(format!(
+                        "Closest supported version is '{}'. The patch offsets are likely different, so do not use its patch data without verifying it.",
+                        closest
+                    ))),
+                    None => log.push(LogEntry::info("No supported version is similar to this one.")),
+                }
+            }
+            if let VersionError::AmbiguousVariant { .. } = &e {
+                log.push(LogEntry::info("Select the hardware variant printed on the ECU label to continue."));
+                // A header is accounted for as for detected files, if it fits the patches of any variant.
+                let shift = version::offset_shift(&data);
+                let candidates = version::variant_candidates(&data);
+                let base_offset = match candidates.iter().any(|set| patcher::header_shifted(&data, set, shift).is_some()) {
+                    true => shift.unsigned_abs(),
+                    false => 0,
+                };
+                let candidates = candidates.into_iter().map(|set| patches::with_header(set, base_offset)).collect();
+                *variant_choice = Some(VariantChoice { data, format: decoded.format, candidates, base_offset });
+            }
+            return Err(LoadFailure::Version(e));
+        }
+    };
+    let shift = detection.offset_shift();
+    let Detection { mut patch_set, version_offset, hardware_variant, version_suffix } = detection;
+    match version_offset {
+        Some(offset) => {
+            log.push(LogEntry::success(format!(
+                "Success: Detected version '{}' (version string at offset {:#X})",
+                patch_set.version_string, offset
+            )));
+            log.push(LogEntry::info(format!("SHA-256: {} (not a known stock file)", sha256)));
+        }
+        None => {
+            log.push(LogEntry::success(format!("Success: Detected version '{}' (known stock file)", patch_set.version_string)));
+            log.push(LogEntry::info(format!("SHA-256: {} (matches a known stock file)", sha256)));
+        }
+    }
+    // A version string found after its usual offset usually means a header, e.g. the 512 bytes some
+    // reading tools prepend. The shift is only accounted for if every patch is then in a known state.
+    let mut base_offset = 0;
+    if let Some(shifted) = patcher::header_shifted(&data, patch_set, shift) {
+        base_offset = shift.unsigned_abs();
+        patch_set = shifted;
+        log.push(LogEntry::warning(format!(
+            "Warning: Detected a {:#X} byte header before the firmware image. Patch offsets are shifted by the same amount, and the header is kept when saving.",
+            base_offset
+        )));
+    }
+    if shift != 0 && base_offset == 0 {
+        log.push(LogEntry::warning(format!(
+            "Warning: The version string is {:#X} bytes {} its usual offset, so the dump may have a header or be cut. Patch offsets are likely shifted by the same amount.",
+            shift.unsigned_abs(),
+            if shift > 0 { "after" } else { "before" }
+        )));
+    }
+    match (&hardware_variant, patch_set.hardware_variant) {
+        (Some(found), Some(expected)) if found != expected => log.push(LogEntry::warning(format!(
+            "Warning: The file holds hardware variant '{}', but the patch set is for '{}'.",
+            found, expected
+        ))),
+        (Some(found), _) => log.push(LogEntry::info(format!("Hardware variant: {}", found))),
+        (None, _) => {}
+    }
+    if let Some(suffix) = version_suffix {
+        log.push(LogEntry::info(format!("Version suffix: {}", suffix)));
+    }
+    if let Some(expected) = patch_set.expected_size.filter(|&size| size != data.len()) {
+        log.push(LogEntry::warning(format!(
+            "Warning: File size is {} bytes, but a full {} dump is {} bytes. The dump may be truncated or contain extra data.",
+            data.len(),
+            patch_set.version_string,
+            expected
+        )));
+    }
+    for patch in &patch_set.patches {
+        if patcher::get_patch_status(&data, patch) == PatchStatus::Unknown {
+            let kind = patcher::classify_unknown(&data, patch);
+            log.push(LogEntry::warning(format!(
+                "Warning: {} Looks {}: {}",
+                patcher::describe_patch_mismatch(&data, patch),
+                kind.label(),
+                kind.explanation()
+            )));
+        }
+    }
+    log.push(describe_patch_state(&data, patch_set));
+
+    let candidates = match version::detect_all_candidates(&data) {
+        Ok(candidates) => candidates.into_iter().map(|set| patches::with_header(set, base_offset)).collect(),
+        Err(_) => vec![patch_set],
+    };
+    Ok(LoadedFile { data, format: decoded.format, patch_set, hardware_variant, sha256, candidates, base_offset })
+}
+
+/// Summarizes the status of all patches in one message, so it is clear why Apply or Revert is disabled.
+/// A partially patched file lists which patches are in which state and suggests what to do next.
+pub fn describe_patch_state(data: &[u8], patch_set: &PatchSet) -> LogEntry {
+    let statuses: Vec<PatchStatus> = patch_set.patches.iter().map(|patch| patcher::get_patch_status(data, patch)).collect();
+    if statuses.iter().all(|&status| status == PatchStatus::Unpatched) {
+        return LogEntry::info("All patches are in their stock state, so the file is ready to be patched.");
+    }
+    if statuses.iter().all(|&status| status == PatchStatus::Patched) {
+        return LogEntry::info(
+            "This file is already fully patched, so there is nothing to apply. Revert restores the original bytes.",
+        );
+    }
+    let states: Vec<String> = [(PatchStatus::Patched, "patched"), (PatchStatus::Unpatched, "stock"), (PatchStatus::Unknown, "unknown")]
+        .into_iter()
+        .filter_map(|(wanted, label)| {
+            let names: Vec<&str> =
+                patch_set.patches.iter().zip(&statuses).filter(|&(_, &status)| status == wanted).map(|(patch, _)| patch.name).collect();
+            (!names.is_empty()).then(|| format!("{}: {}", label, names.join(", ")))
+        })
+        .collect();
+    let next = if statuses.contains(&PatchStatus::Unknown) {
+        "Patches in an unknown state can be neither applied nor reverted. Restore a backup of the original file, \
+         or use Force apply only if you know why the bytes differ."
+    } else {
+        "Apply the remaining patches one at a time from the Hex Viewer tab, or Revert the applied ones to get back to stock."
+    };
+    LogEntry::warning(format!("Warning: This file is partially patched ({}). {}", states.join("; "), next))
+}
+
+/// Starts loading a file on a worker thread. The result arrives on the returned channel.
+pub fn spawn_load(path: PathBuf) -> Receiver<LoadResult> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        // The receiver may be gone if a newer load replaced this one; the result is simply dropped then.
+        let _ = sender.send(load_file(&path));
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::log_panel::LogLevel;
+
+    #[test]
+    fn missing_file_is_reported_through_the_channel() {
+        let path = std::env::temp_dir().join("ewsms43-loader-test-missing.bin");
+        let result = spawn_load(path.clone()).recv().unwrap();
+        assert_eq!(result.path, path);
+        assert!(result.file.is_none());
+        assert_eq!(result.log[0].level, LogLevel::Error);
+        assert!(result.log[0].message.starts_with("Error: Failed to read file: The file or folder does not exist."));
+    }
+
+    #[test]
+    fn unknown_firmware_fails_version_detection() {
+        let path = std::env::temp_dir().join(format!("ewsms43-loader-test-{}.bin", std::process::id()));
+        fs::write(&path, vec![0xFF; 0x80000]).unwrap();
+        let result = load_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.file.is_none());
+        assert_eq!(result.log[0].message, "Successfully read 524288 bytes.");
+        assert_eq!(result.log[1].level, LogLevel::Error);
+        assert!(result.log[1].message.starts_with("Error: Version detection failed"));
+        assert_eq!(result.probes[0].raw, vec![0xFF; 16]);
+    }
+
+    #[test]
+    fn zip_archives_load_their_only_firmware_file_or_offer_a_choice() {
+        use std::io::Write;
+        let patch_set = ewsms43::patches::known_patch_sets().into_iter().find(|set| set.version_string == "ca430037").unwrap();
+        let data = ewsms43::selftest::build_synthetic_image(patch_set).unwrap();
+        let path = std::env::temp_dir().join(format!("ewsms43-loader-test-{}.zip", std::process::id()));
+        let write_zip = |names: &[&str]| {
+            let mut writer = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+            for name in names {
+                writer.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+                writer.write_all(&data).unwrap();
+            }
+            writer.finish().unwrap();
+        };
+
+        write_zip(&["dump.bin"]);
+        let single = load_file(&path);
+        write_zip(&["car1.bin", "car2.dat"]);
+        let several = load_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(single.file.unwrap().patch_set.version_string, "ca430037");
+        assert_eq!(single.zip_entry.as_deref(), Some("dump.bin"));
+        assert_eq!(single.log[0].message, "Extracted dump.bin (524288 bytes) from the archive.");
+        assert!(several.file.is_none());
+        let names: Vec<String> = several.zip_choice.unwrap().entries.into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["car1.bin", "car2.dat"]);
+
+        let picked = load_contents(&path, data.clone(), Some("car2.dat".to_string()));
+        assert_eq!(picked.zip_entry.as_deref(), Some("car2.dat"));
+        assert_eq!(picked.file.unwrap().data, data);
+    }
+
+    #[test]
+    fn a_dump_with_a_header_is_patched_at_shifted_offsets_and_keeps_the_header() {
+        let patch_set = ewsms43::patches::known_patch_sets().into_iter().find(|set| set.version_string == "ca430037").unwrap();
+        let mut original = vec![0x5A; 0x200];
+        original.extend(ewsms43::selftest::build_synthetic_image(patch_set).unwrap());
+        let path = std::env::temp_dir().join(format!("ewsms43-loader-test-header-{}.bin", std::process::id()));
+        fs::write(&path, &original).unwrap();
+        let result = load_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        let file = result.file.unwrap();
+        assert_eq!(file.base_offset, 0x200);
+        assert_eq!(file.patch_set.patches[0].offset, patch_set.patches[0].offset + 0x200);
+        assert_eq!(file.candidates, [file.patch_set]);
+        assert!(result.log.iter().any(|entry| entry.message.starts_with("Warning: Detected a 0x200 byte header")));
+        assert_eq!(patcher::check_patch_status(&file.data, file.patch_set).0, PatchStatus::Unpatched);
+
+        let mut data = file.data;
+        patcher::apply_patches(&mut data, file.patch_set).unwrap();
+        assert_eq!(data[..0x200], original[..0x200]);
+        let jump = &patch_set.patches[0];
+        assert_eq!(data[0x200 + jump.offset..0x200 + jump.offset + jump.patched.len()], jump.patched[..]);
+        patcher::revert_patches(&mut data, file.patch_set).unwrap();
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn the_patch_state_is_summarized_with_a_next_step() {
+        let patch_set = ewsms43::patches::known_patch_sets().into_iter().find(|set| set.version_string == "ca430037").unwrap();
+        let mut data = ewsms43::selftest::build_synthetic_image(patch_set).unwrap();
+        assert!(describe_patch_state(&data, patch_set).message.contains("ready to be patched"));
+
+        let jump = &patch_set.patches[0];
+        data[jump.offset..jump.offset + jump.patched.len()].copy_from_slice(&jump.patched);
+        let partial = describe_patch_state(&data, patch_set);
+        assert_eq!(partial.level, LogLevel::Warning);
+        assert!(partial
+            .message
+            .starts_with("Warning: This file is partially patched (patched: Jump; stock: Code, DTC). Apply the remaining"));
+
+        data[jump.offset] ^= 0xFF;
+        assert!(describe_patch_state(&data, patch_set).message.contains("unknown: Jump"));
+
+        patcher::force_apply_patches(&mut data, patch_set).unwrap();
+        assert!(describe_patch_state(&data, patch_set).message.contains("already fully patched"));
+    }
+
+    #[test]
+    fn io_errors_get_friendly_messages() {
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert!(describe_io_error(&denied).starts_with("Permission denied."));
+        let other = io::Error::other("disk on fire");
+        assert_eq!(describe_io_error(&other), "disk on fire");
+    }
+}
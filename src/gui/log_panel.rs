@@ -0,0 +1,164 @@
+//! This module renders the session log with a search box and per-level filters.
+
+use imgui::Ui;
+use std::time::{Duration, Instant};
+
+/// Severity of a log entry, which decides its color and filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 4] = [LogLevel::Info, LogLevel::Success, LogLevel::Warning, LogLevel::Error];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Info => "Info",
+            LogLevel::Success => "Success",
+            LogLevel::Warning => "Warning",
+            LogLevel::Error => "Error",
+        }
+    }
+
+    /// Returns the text color used for lines of this level.
+    pub fn color(self) -> [f32; 4] {
+        match self {
+            LogLevel::Info => [0.7, 0.7, 0.7, 1.0],
+            LogLevel::Success => [0.2, 0.8, 0.2, 1.0],
+            LogLevel::Warning => [1.0, 0.7, 0.1, 1.0],
+            LogLevel::Error => [1.0, 0.2, 0.2, 1.0],
+        }
+    }
+}
+
+/// One line of the session log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub level: LogLevel,
+    pub message: String,
+    pub timestamp: Instant,
+}
+
+impl LogEntry {
+    pub fn new(level: LogLevel, message: impl Into<String>) -> Self {
+        LogEntry { level, message: message.into(), timestamp: Instant::now() }
+    }
+
+    pub fn info(message: impl Into<String>) -> Self {
+        LogEntry::new(LogLevel::Info, message)
+    }
+
+    pub fn success(message: impl Into<String>) -> Self {
+        LogEntry::new(LogLevel::Success, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        LogEntry::new(LogLevel::Warning, message)
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        LogEntry::new(LogLevel::Error, message)
+    }
+
+    /// Wraps a line returned by the library, which logs plain strings. Its only non-informational lines
+    /// are marked with a "Warning:" prefix, e.g. for forced patches.
+    pub fn from_library(line: String) -> Self {
+        let level = if line.starts_with("Warning") { LogLevel::Warning } else { LogLevel::Info };
+        LogEntry::new(level, line)
+    }
+}
+
+/// Which log lines are shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    /// Case-insensitive text that shown lines must contain.
+    pub query: String,
+    /// Visibility per level, indexed like `LogLevel::ALL`.
+    pub levels: [bool; 4],
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter { query: String::new(), levels: [true; 4] }
+    }
+}
+
+impl LogFilter {
+    /// Returns true if any line could be hidden by this filter.
+    pub fn is_active(&self) -> bool {
+        !self.query.trim().is_empty() || self.levels.contains(&false)
+    }
+
+    /// Returns true if `entry` passes the filter.
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        let index = LogLevel::ALL.iter().position(|&l| l == entry.level).unwrap_or(0);
+        if !self.levels[index] {
+            return false;
+        }
+        let query = self.query.trim();
+        query.is_empty() || entry.message.to_lowercase().contains(&query.to_lowercase())
+    }
+}
+
+/// Renders the filter controls and the filtered log lines.
+///
+/// The view follows new lines only while no filter is active, so a search result does not scroll away.
+pub fn render_log_panel(ui: &Ui, log: &[LogEntry], filter: &mut LogFilter) {
+    ui.set_next_item_width(200.0);
+    ui.input_text("##log_search", &mut filter.query).hint("Search log").build();
+    for (level, shown) in LogLevel::ALL.iter().zip(filter.levels.iter_mut()) {
+        ui.same_line();
+        let _color = ui.push_style_color(imgui::StyleColor::Text, level.color());
+        ui.checkbox(level.name(), shown);
+    }
+    if filter.is_active() {
+        ui.same_line();
+        if ui.small_button("Clear Filter") {
+            *filter = LogFilter::default();
+        }
+    }
+
+    ui.child_window("LogContent").build(|| {
+        for entry in log.iter().filter(|entry| filter.matches(entry)) {
+            ui.text_colored(entry.level.color(), &entry.message);
+            if ui.is_item_hovered() {
+                let age = Duration::from_secs(entry.timestamp.elapsed().as_secs());
+                ui.tooltip_text(format!("{} ago", humantime::format_duration(age)));
+            }
+        }
+        if !filter.is_active() && ui.cursor_pos()[1] > ui.window_content_region_max()[1] {
+            ui.set_scroll_here_y_with_ratio(1.0);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_matches_by_level_and_text() {
+        let loading = LogEntry::info("Loading file: dump.bin");
+        let mut filter = LogFilter::default();
+        assert!(!filter.is_active());
+        assert!(filter.matches(&loading));
+
+        filter.query = "DUMP".to_string();
+        assert!(filter.is_active());
+        assert!(filter.matches(&loading));
+        assert!(!filter.matches(&LogEntry::success("Success: Patches applied.")));
+
+        filter.query.clear();
+        filter.levels = [false, false, true, true];
+        assert!(filter.matches(&LogEntry::error("Failed to decode")));
+        assert!(filter.matches(&LogEntry::warning("Warning: File size is 100 bytes")));
+        assert!(!filter.matches(&LogEntry::success("Success: Detected version")));
+        assert!(!filter.matches(&loading));
+        // The level decides, not the wording of the message.
+        assert!(!filter.matches(&LogEntry::info("Error codes were not changed")));
+    }
+}
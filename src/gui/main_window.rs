@@ -1,18 +1,121 @@
-use crate::patcher::{self, check_patch_status, PatchStatus};
-use crate::patches::PatchSet;
-use crate::version::detect_version;
-use imgui::{Condition, StyleVar, TabBar, TabItem, Ui};
+use crate::config::{Config, SavedFileTime};
+use crate::gui::batch_window::{self, render_batch_results, BatchState};
+use crate::gui::compare_panel::render_compare_panel;
+use crate::gui::compare_sets::{render_compare_sets, CompareSetsState};
+use crate::gui::hex_editor::{ascii_gutter, render_hex_editor, HexEditorState};
+use crate::gui::loader::{self, describe_io_error, LoadResult, LoadedFile, VariantChoice, ZipChoice};
+use crate::gui::log_panel::{render_log_panel, LogEntry, LogFilter};
+use crate::gui::reveal;
+use crate::gui::search_panel::{render_search_panel, SearchState};
+use crate::gui::theme::Theme;
+use ewsms43::patcher::{self, check_all_statuses, PatchStatus, PatcherError};
+use ewsms43::ihex::{self, ImageFormat};
+use ewsms43::patch_file::{self, PatchFileFormat};
+use ewsms43::report::{self, bytes_to_hex_string};
+use ewsms43::version::{self, VersionProbe};
+use ewsms43::{backup, batch, checksum, delta, fingerprint, patches, verify, Patch, PatchSet, PostPatchHooks, RiskLevel};
+use imgui::{Condition, Key, ProgressBar, StyleVar, TabBar, TabItem, TableFlags, Ui};
+use std::collections::BTreeSet;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, TryRecvError};
 
 pub struct AppState {
     pub file_path: String,
     pub file_data: Option<Vec<u8>>,
+    /// The image exactly as it was loaded, before any in-memory changes.
+    pub loaded_data: Option<Vec<u8>>,
+    /// Format the file was loaded from, used to write it back the same way.
+    pub file_format: ImageFormat,
     pub patch_set: Option<&'static PatchSet>,
     pub selected_patch_index: Option<usize>,
     pub detected_version: String,
     pub hardware_variant: String,
-    pub patch_status: (PatchStatus, PatchStatus, PatchStatus), // Jump, Code, DTC
-    pub log: Vec<String>,
+    /// SHA-256 of the loaded image, shown in the status area.
+    pub file_sha256: Option<String>,
+    /// Length of the header in front of the flash image of the loaded file. The offsets of `patch_set`
+    /// are already shifted by it, and the header is saved along with the image.
+    pub base_offset: usize,
+    /// Every patch set of the detected version. The status area offers a choice if there is more than one.
+    pub patch_set_candidates: Vec<&'static PatchSet>,
+    /// Name and status of every patch of `patch_set`, in patch order.
+    pub patch_status: Vec<(String, PatchStatus)>,
+    pub log: Vec<LogEntry>,
+    pub config: Config,
+    pub calc_offset_input: String,
+    pub calc_address_input: String,
+    pub hex_editor: HexEditorState,
+    /// Apply patches without validating the original bytes. Dangerous; off by default.
+    pub force_apply: bool,
+    /// Also clear the `zeroize_regions` of the patch set, e.g. a stored VIN, when applying. Off by default.
+    pub zeroize: bool,
+    /// Offsets of bytes edited by hand since the file was loaded or last saved.
+    pub dirty_offsets: BTreeSet<usize>,
+    pub show_supported_versions: bool,
+    /// Receives the result of a file load running on a worker thread.
+    pub pending_load: Option<Receiver<LoadResult>>,
+    /// Path of the most recently saved firmware file.
+    pub last_saved_path: Option<PathBuf>,
+    pub log_filter: LogFilter,
+    /// Inspect mode: every action that would modify the data or write a file is refused.
+    pub read_only: bool,
+    /// Safe mode: patches are only applied to a clearly detected, fully unpatched file that was backed
+    /// up first. See `refuse_if_unsafe`.
+    pub safe_mode: bool,
+    pub search: SearchState,
+    pub compare_sets: CompareSetsState,
+    pub batch: BatchState,
+    /// What each version location of the last loaded file held, kept even when detection failed.
+    pub version_probes: Vec<VersionProbe>,
+    /// Export every row in the side-by-side hex dump instead of only the changed regions.
+    pub full_hex_dump: bool,
+    /// Transformations run after the patches are applied. Empty unless a frontend registers some.
+    pub post_patch_hooks: PostPatchHooks,
+    /// Backup made of the loaded file, if any. Only one backup is made per loaded file.
+    pub backup_path: Option<PathBuf>,
+    /// Patch the high-risk confirmation applies on its own. `None` applies the whole set.
+    pub pending_single_patch: Option<usize>,
+    /// A loaded image waiting for the user to pick its hardware variant.
+    pub variant_choice: Option<VariantChoice>,
+    /// The firmware files of a loaded ZIP archive, waiting for the user to pick one.
+    pub zip_choice: Option<ZipChoice>,
+    /// Name of the file loaded out of a ZIP archive; `file_path` is then the archive itself.
+    pub zip_entry: Option<String>,
+    /// Data before each saved patch operation, oldest first. Capped at `UNDO_HISTORY_LIMIT` entries.
+    pub undo_history: Vec<Vec<u8>>,
+    /// Progress of the last apply or revert: the verb, patches done and patches in total.
+    pub patch_progress: Option<(&'static str, usize, usize)>,
+    /// Second image shown in the Compare tab. Kept when another file is loaded.
+    pub compare_data: Option<Vec<u8>>,
+    pub compare_path: String,
+    /// Set when the shown state changed without user input, e.g. because a background load finished,
+    /// so the window is redrawn although it is idle. Cleared by the event loop.
+    pub needs_redraw: bool,
+    /// Set when the in-memory data was changed since it was loaded or last saved, e.g. by a hex edit or
+    /// an undo. Loading another file or closing the window asks for confirmation first.
+    pub dirty: bool,
+    /// Action waiting for the user to confirm that the unsaved changes may be discarded.
+    pub pending_discard: Option<DiscardAction>,
+    /// Set once the user confirmed closing the window with unsaved changes. Read by the event loop.
+    pub exit_requested: bool,
+}
+
+/// Actions that throw away the in-memory data and therefore ask first while it has unsaved changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiscardAction {
+    /// Show the file dialog and load the chosen file.
+    Browse,
+    /// Load this file, e.g. one picked from the recent files.
+    Load(PathBuf),
+    /// Close the window.
+    Close,
+}
+
+impl AppState {
+    /// Returns true while work runs on another thread whose results have to be polled every frame.
+    pub fn has_background_work(&self) -> bool {
+        self.pending_load.is_some() || self.search.is_running()
+    }
 }
 
 impl Default for AppState {
@@ -20,45 +123,1614 @@ impl Default for AppState {
         AppState {
             file_path: String::new(),
             file_data: None,
+            loaded_data: None,
+            file_format: ImageFormat::Binary,
             patch_set: None,
             selected_patch_index: None,
             detected_version: "N/A".to_string(),
             hardware_variant: "N/A".to_string(),
-            patch_status: (PatchStatus::Unknown, PatchStatus::Unknown, PatchStatus::Unknown),
-            log: vec!["Welcome to EWS IMMO Patcher MS43!".to_string()],
+            base_offset: 0,
+            file_sha256: None,
+            patch_set_candidates: Vec::new(),
+            patch_status: Vec::new(),
+            log: vec![LogEntry::info("Welcome to EWS IMMO Patcher MS43!")],
+            config: Config::default(),
+            calc_offset_input: String::new(),
+            calc_address_input: String::new(),
+            hex_editor: HexEditorState::default(),
+            force_apply: false,
+            zeroize: false,
+            dirty_offsets: BTreeSet::new(),
+            show_supported_versions: false,
+            pending_load: None,
+            last_saved_path: None,
+            log_filter: LogFilter::default(),
+            read_only: false,
+            safe_mode: false,
+            search: SearchState::default(),
+            compare_sets: CompareSetsState::default(),
+            batch: BatchState::default(),
+            version_probes: Vec::new(),
+            full_hex_dump: false,
+            post_patch_hooks: PostPatchHooks::default(),
+            backup_path: None,
+            pending_single_patch: None,
+            variant_choice: None,
+            zip_choice: None,
+            zip_entry: None,
+            undo_history: Vec::new(),
+            patch_progress: None,
+            compare_data: None,
+            compare_path: String::new(),
+            needs_redraw: false,
+            dirty: false,
+            pending_discard: None,
+            exit_requested: false,
         }
     }
 }
 
 fn reset_state(app_state: &mut AppState) {
+    app_state.undo_history.clear();
+    app_state.variant_choice = None;
+    app_state.zip_choice = None;
+    app_state.zip_entry = None;
+    app_state.version_probes.clear();
+    app_state.search.cancel();
+    app_state.search = SearchState::default();
     app_state.file_data = None;
+    app_state.loaded_data = None;
+    app_state.hex_editor = HexEditorState::default();
+    app_state.dirty_offsets.clear();
+    app_state.backup_path = None;
+    app_state.file_format = ImageFormat::Binary;
     app_state.patch_set = None;
     app_state.selected_patch_index = None;
     app_state.detected_version = "N/A".to_string();
     app_state.hardware_variant = "N/A".to_string();
-    app_state.patch_status = (PatchStatus::Unknown, PatchStatus::Unknown, PatchStatus::Unknown);
+    app_state.base_offset = 0;
+    app_state.file_sha256 = None;
+    app_state.patch_set_candidates.clear();
+    app_state.patch_progress = None;
+    app_state.patch_status.clear();
+    app_state.dirty = false;
 }
 
-fn log_color(message: &str) -> [f32; 4] {
-    if message.starts_with("Success") {
-        [0.2, 0.8, 0.2, 1.0]
-    } else if message.starts_with("Error") || message.starts_with("Failed") {
-        [1.0, 0.2, 0.2, 1.0]
-    } else {
-        [0.7, 0.7, 0.7, 1.0]
+/// Parses a hex number with or without a `0x` prefix.
+fn parse_hex_usize(input: &str) -> Option<usize> {
+    let trimmed = input.trim();
+    let digits = trimmed.strip_prefix("0x").or_else(|| trimmed.strip_prefix("0X")).unwrap_or(trimmed);
+    usize::from_str_radix(digits, 16).ok()
+}
+
+/// Opens the file dialog and starts loading the chosen firmware file in the background.
+fn browse_for_file(app_state: &mut AppState) {
+    if is_loading(app_state) || ask_before_discarding(app_state, DiscardAction::Browse) {
+        return;
+    }
+    pick_and_load_file(app_state);
+}
+
+/// Opens the file dialog and loads the chosen file, without asking about unsaved changes.
+fn pick_and_load_file(app_state: &mut AppState) {
+    if let Some(path) = rfd::FileDialog::new()
+        .add_filter("Firmware files", &["bin", "dat", "hex", "s19", "s28", "s37", "srec", "mot", "zip"])
+        .add_filter("Binary firmware files", &["bin", "dat"])
+        .add_filter("Intel HEX / S-record files", &["hex", "s19", "s28", "s37", "srec", "mot"])
+        .add_filter("ZIP archives", &["zip"])
+        .pick_file()
+    {
+        start_load(app_state, path);
+    }
+}
+
+/// Starts loading `path` in the background, replacing the current file.
+fn start_load(app_state: &mut AppState, path: PathBuf) {
+    let file_path_str = path.display().to_string();
+    app_state.log.push(LogEntry::info(format!("Loading file: {}", file_path_str)));
+    reset_state(app_state); // Reset state before loading new file
+    app_state.file_path = file_path_str; // Keep file path after reset
+    app_state.pending_load = Some(loader::spawn_load(path));
+}
+
+/// Returns true, and asks the user to confirm `action` first, if the in-memory data has unsaved changes.
+///
+/// The action is carried out by `render_discard_confirmation` once the user confirms it.
+pub fn ask_before_discarding(app_state: &mut AppState, action: DiscardAction) -> bool {
+    if !app_state.dirty {
+        return false;
     }
+    app_state.pending_discard = Some(action);
+    app_state.needs_redraw = true;
+    true
+}
+
+/// Title of the popup asking whether unsaved changes may be discarded.
+const DISCARD_POPUP: &str = "Unsaved Changes";
+
+/// Asks whether the unsaved in-memory changes may be thrown away for the pending action.
+fn render_discard_confirmation(ui: &Ui, app_state: &mut AppState) {
+    let Some(action) = app_state.pending_discard.clone() else {
+        return;
+    };
+    ui.open_popup(DISCARD_POPUP);
+    let mut confirmed = false;
+    let mut cancelled = false;
+    ui.modal_popup_config(DISCARD_POPUP).always_auto_resize(true).build(|| {
+        ui.text("The loaded file was changed in memory, but the changes have not been saved.");
+        ui.text(match action {
+            DiscardAction::Close => "Close the patcher and discard them?",
+            DiscardAction::Browse | DiscardAction::Load(_) => "Load another file and discard them?",
+        });
+        ui.spacing();
+        if ui.button("Discard Changes") {
+            confirmed = true;
+            ui.close_current_popup();
+        }
+        ui.same_line();
+        if ui.button("Cancel") {
+            cancelled = true;
+            ui.close_current_popup();
+        }
+    });
+
+    if confirmed {
+        app_state.pending_discard = None;
+        app_state.log.push(LogEntry::warning("Warning: Unsaved in-memory changes were discarded."));
+        match action {
+            DiscardAction::Browse => pick_and_load_file(app_state),
+            DiscardAction::Load(path) => start_load(app_state, path),
+            DiscardAction::Close => app_state.exit_requested = true,
+        }
+    } else if cancelled {
+        app_state.pending_discard = None;
+    }
+}
+
+/// Returns true while a file is being loaded on the worker thread.
+fn is_loading(app_state: &AppState) -> bool {
+    app_state.pending_load.is_some()
+}
+
+/// Checks whether the worker thread has finished loading and adopts its result. Called once per frame.
+fn poll_pending_load(app_state: &mut AppState) {
+    let Some(receiver) = &app_state.pending_load else {
+        return;
+    };
+    let result = match receiver.try_recv() {
+        Ok(result) => result,
+        Err(TryRecvError::Empty) => return,
+        Err(TryRecvError::Disconnected) => {
+            app_state.pending_load = None;
+            app_state.log.push(LogEntry::error("Error: Loading the file failed unexpectedly."));
+            reset_state(app_state);
+            app_state.file_path.clear();
+            app_state.needs_redraw = true;
+            return;
+        }
+    };
+    app_state.pending_load = None;
+    adopt_load_result(app_state, result);
 }
 
-/// Converts a byte slice to a formatted, spaced-out hex string.
-fn bytes_to_hex_string(bytes: &[u8]) -> String {
-    bytes
+/// Makes the file of a finished load the current one, or clears the state if it failed.
+fn adopt_load_result(app_state: &mut AppState, result: LoadResult) {
+    app_state.needs_redraw = true;
+    app_state.log.extend(result.log);
+    let Some(file) = result.file else {
+        // Leave nothing from the failed load behind, not even the path. The probes stay for diagnosis.
+        reset_state(app_state);
+        app_state.version_probes = result.probes;
+        // Only an unclear hardware variant or archive entry keeps the data, until the user picks one.
+        if result.variant_choice.is_some() || result.zip_choice.is_some() {
+            app_state.file_path = result.path.display().to_string();
+            app_state.variant_choice = result.variant_choice;
+            app_state.zip_choice = result.zip_choice;
+            app_state.zip_entry = result.zip_entry;
+        } else {
+            app_state.file_path.clear();
+        }
+        return;
+    };
+    app_state.version_probes = result.probes;
+    app_state.file_path = result.path.display().to_string();
+    app_state.zip_entry = result.zip_entry;
+    adopt_loaded_file(app_state, file);
+}
+
+/// Makes a loaded and detected file the current one.
+fn adopt_loaded_file(
+    app_state: &mut AppState,
+    LoadedFile { data, format, patch_set, hardware_variant, sha256, candidates, base_offset }: LoadedFile,
+) {
+    app_state.base_offset = base_offset;
+    app_state.detected_version = patch_set.version_string.to_string();
+    app_state.hardware_variant = hardware_variant.unwrap_or_else(|| "N/A".to_string());
+    app_state.file_sha256 = Some(sha256);
+    app_state.patch_set_candidates = candidates;
+    app_state.config.remember_recent_file(Path::new(&app_state.file_path));
+    app_state.patch_status = check_all_statuses(&data, patch_set);
+    app_state.patch_set = Some(patch_set);
+    app_state.file_format = format;
+    app_state.loaded_data = Some(data.clone());
+    app_state.file_data = Some(data);
+}
+
+/// Lets the user switch between the patch sets of the detected version, e.g. when the hardware variant
+/// read from the file is wrong. Only shown if the version has more than one patch set.
+fn render_patch_set_choice(ui: &Ui, app_state: &mut AppState) {
+    let (Some(current), Some(data)) = (app_state.patch_set, app_state.file_data.as_ref()) else {
+        return;
+    };
+    if app_state.patch_set_candidates.len() < 2 {
+        return;
+    }
+    let labels: Vec<String> = app_state
+        .patch_set_candidates
         .iter()
-        .map(|b| format!("{:02X}", b))
-        .collect::<Vec<String>>()
-        .join(" ")
+        .map(|set| format!("{} ({})", set.version_string, set.hardware_variant.unwrap_or("no variant")))
+        .collect();
+    let mut index = app_state.patch_set_candidates.iter().position(|&set| set == current).unwrap_or(0);
+    ui.set_next_item_width(220.0);
+    if ui.combo_simple_string("Patch Set", &mut index, &labels) {
+        let chosen = app_state.patch_set_candidates[index];
+        if chosen != current {
+            app_state.patch_status = check_all_statuses(data, chosen);
+            app_state.patch_set = Some(chosen);
+            app_state.log.push(LogEntry::info(format!("Patch set {} selected manually.", labels[index])));
+        }
+    }
+    if ui.is_item_hovered() {
+        ui.tooltip_text("This version exists on several hardware variants. Pick the one printed on the ECU label.");
+    }
+}
+
+/// Returns true if the user forced a patch set that detection did not offer for the loaded file.
+fn is_manual_override(app_state: &AppState) -> bool {
+    app_state.patch_set.is_some_and(|set| !app_state.patch_set_candidates.contains(&set))
+}
+
+/// Lets an expert force any known patch set onto the loaded file when detection picked the wrong one.
+/// "Auto-detect" goes back to the detected set.
+fn render_version_override(ui: &Ui, app_state: &mut AppState) {
+    let (Some(current), Some(data)) = (app_state.patch_set, app_state.file_data.as_ref()) else {
+        return;
+    };
+    // Forced sets are shifted by the header of the loaded file like the detected ones.
+    let sets: Vec<&'static PatchSet> =
+        patches::known_patch_sets().into_iter().map(|set| patches::with_header(set, app_state.base_offset)).collect();
+    let mut labels = vec!["Auto-detect".to_string()];
+    labels.extend(sets.iter().map(|set| format!("{} ({})", set.version_string, set.hardware_variant.unwrap_or("no variant"))));
+    let overridden = is_manual_override(app_state);
+    let mut index = if overridden { sets.iter().position(|&set| set == current).map_or(0, |i| i + 1) } else { 0 };
+    ui.set_next_item_width(220.0);
+    if ui.combo_simple_string("Version Override", &mut index, &labels) {
+        let chosen = match index {
+            0 => app_state.patch_set_candidates.first().copied(),
+            _ => sets.get(index - 1).copied(),
+        };
+        if let Some(chosen) = chosen.filter(|&chosen| chosen != current) {
+            app_state.patch_status = check_all_statuses(data, chosen);
+            app_state.patch_set = Some(chosen);
+            if index == 0 {
+                app_state.log.push(LogEntry::info(format!(
+                    "Manual override removed; using the detected version {}.",
+                    chosen.version_string
+                )));
+            } else {
+                app_state.log.push(LogEntry::warning(format!(
+                    "Warning: Manual override: using patch set {} instead of the detected version {}.",
+                    labels[index], app_state.detected_version
+                )));
+            }
+        }
+    }
+    if ui.is_item_hovered() {
+        ui.tooltip_text(
+            "Forces a patch set when detection picked the wrong one.\n\
+             Wrong offsets on the wrong version can brick the ECU, so applying asks for confirmation.",
+        );
+    }
+    if overridden {
+        ui.same_line();
+        ui.text_colored([1.0, 0.7, 0.1, 1.0], "MANUAL OVERRIDE");
+    }
+}
+
+/// Title of the popup asking which hardware variant an ambiguous file is.
+const VARIANT_POPUP: &str = "Select Hardware Variant";
+
+/// Asks the user to pick the hardware variant of a file whose version exists on several variants.
+fn render_variant_choice(ui: &Ui, app_state: &mut AppState) {
+    if app_state.variant_choice.is_none() {
+        return;
+    }
+    // Opening an already open popup keeps it as it is.
+    ui.open_popup(VARIANT_POPUP);
+    let mut picked = None;
+    let mut cancelled = false;
+    ui.modal_popup_config(VARIANT_POPUP).always_auto_resize(true).build(|| {
+        ui.text("This firmware version exists on several hardware variants, and the file does not say which.");
+        ui.text("Pick the variant printed on the ECU label:");
+        ui.spacing();
+        if let Some(choice) = &app_state.variant_choice {
+            for (index, set) in choice.candidates.iter().enumerate() {
+                if ui.button(format!("{} {}", set.version_string, set.hardware_variant.unwrap_or("(no variant)"))) {
+                    picked = Some(index);
+                    ui.close_current_popup();
+                }
+            }
+        }
+        ui.spacing();
+        if ui.button("Cancel") {
+            cancelled = true;
+            ui.close_current_popup();
+        }
+    });
+
+    if let Some(index) = picked {
+        pick_variant(app_state, index);
+    } else if cancelled {
+        reset_state(app_state);
+        app_state.file_path.clear();
+        app_state.log.push(LogEntry::info("Loading cancelled; no hardware variant was selected."));
+    }
+}
+
+/// Loads the pending variant choice with the candidate at `index`.
+fn pick_variant(app_state: &mut AppState, index: usize) {
+    let Some(VariantChoice { data, format, candidates, base_offset }) = app_state.variant_choice.take() else {
+        return;
+    };
+    let patch_set = candidates[index];
+    let variant = patch_set.hardware_variant.map(str::to_string);
+    app_state.log.push(LogEntry::info(format!(
+        "Hardware variant {} selected manually.",
+        variant.as_deref().unwrap_or("(none)")
+    )));
+    app_state.log.push(loader::describe_patch_state(&data, patch_set));
+    let sha256 = fingerprint::sha256(&data);
+    adopt_loaded_file(app_state, LoadedFile {
+        data,
+        format,
+        patch_set,
+        hardware_variant: variant,
+        sha256,
+        candidates,
+        base_offset,
+    });
+}
+
+/// Title of the popup asking which file of a ZIP archive to load.
+const ZIP_POPUP: &str = "Select File from Archive";
+
+/// Asks the user which firmware file to load from a ZIP archive holding several.
+///
+/// The picked file is decoded and detected like a file opened directly; patched files are later saved
+/// as normal files next to the archive, never back into it.
+fn render_zip_choice(ui: &Ui, app_state: &mut AppState) {
+    if app_state.zip_choice.is_none() {
+        return;
+    }
+    ui.open_popup(ZIP_POPUP);
+    let mut picked = None;
+    let mut cancelled = false;
+    ui.modal_popup_config(ZIP_POPUP).always_auto_resize(true).build(|| {
+        ui.text("This archive holds several firmware files. Pick the one to load:");
+        ui.spacing();
+        if let Some(choice) = &app_state.zip_choice {
+            for (index, (name, contents)) in choice.entries.iter().enumerate() {
+                if ui.button(format!("{} ({} bytes)", name, contents.len())) {
+                    picked = Some(index);
+                    ui.close_current_popup();
+                }
+            }
+        }
+        ui.spacing();
+        if ui.button("Cancel") {
+            cancelled = true;
+            ui.close_current_popup();
+        }
+    });
+
+    if let Some(index) = picked {
+        let Some((name, contents)) = app_state.zip_choice.take().and_then(|choice| choice.entries.into_iter().nth(index)) else {
+            return;
+        };
+        app_state.log.push(LogEntry::info(format!("Loading {} ({} bytes) from the archive.", name, contents.len())));
+        let result = loader::load_contents(Path::new(&app_state.file_path), contents, Some(name));
+        adopt_load_result(app_state, result);
+    } else if cancelled {
+        reset_state(app_state);
+        app_state.file_path.clear();
+        app_state.log.push(LogEntry::info("Loading cancelled; no file was picked from the archive."));
+    }
+}
+
+/// Returns true, and logs every reason, if safe mode forbids applying patches to the loaded file.
+///
+/// Safe mode only applies to a file detected with exactly one candidate patch set whose patches are
+/// all unpatched, without force apply, and only once a backup of it was written. The backup is made
+/// here, before anything is applied. Does nothing while safe mode is off.
+fn refuse_if_unsafe(app_state: &mut AppState) -> bool {
+    if !app_state.safe_mode {
+        return false;
+    }
+    let mut reasons = Vec::new();
+    if app_state.patch_set_candidates.len() != 1 || is_manual_override(app_state) {
+        reasons.push("the version was not detected with exactly one matching patch set");
+    }
+    if app_state.patch_status.is_empty() || app_state.patch_status.iter().any(|(_, status)| *status != PatchStatus::Unpatched) {
+        reasons.push("not every patch is Unpatched, so the file may already be (partly) patched");
+    }
+    if app_state.force_apply {
+        reasons.push("force apply is enabled");
+    }
+    if !app_state.config.create_backup {
+        reasons.push("backups are turned off (\"Back up original\")");
+    } else if !ensure_backup(app_state) {
+        reasons.push("no backup of the original file could be written");
+    }
+    for reason in &reasons {
+        app_state.log.push(LogEntry::error(format!("Error: Safe mode refused to apply patches: {}.", reason)));
+    }
+    !reasons.is_empty()
+}
+
+/// Returns true, and logs why, if inspect mode forbids `action`.
+///
+/// Every function that modifies the data or writes a file calls this first, so inspect mode does not
+/// depend on the buttons being disabled.
+pub fn refuse_if_read_only(app_state: &mut AppState, action: &str) -> bool {
+    if app_state.read_only {
+        app_state.log.push(LogEntry::error(format!("Error: Cannot {} in inspect mode (read-only).", action)));
+    }
+    app_state.read_only
+}
+
+/// Maximum number of patch operations and byte edits that can be undone.
+const UNDO_HISTORY_LIMIT: usize = 10;
+/// Files larger than this are not snapshotted for undo, to bound memory use. Full MS43 dumps are 512KB.
+const UNDO_MAX_FILE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Records the data from before a patch operation or byte edit so it can be undone.
+pub fn push_undo_snapshot(app_state: &mut AppState, before: Vec<u8>) {
+    if before.len() > UNDO_MAX_FILE_SIZE {
+        app_state.log.push(LogEntry::info(format!(
+            "Note: Undo is not available for files larger than {} bytes.",
+            UNDO_MAX_FILE_SIZE
+        )));
+        return;
+    }
+    if app_state.undo_history.len() == UNDO_HISTORY_LIMIT {
+        app_state.undo_history.remove(0);
+        app_state.log.push(LogEntry::info(format!(
+            "Note: The oldest undo step was dropped (at most {} are kept).",
+            UNDO_HISTORY_LIMIT
+        )));
+    }
+    app_state.undo_history.push(before);
+}
+
+/// Restores the in-memory data from before the last patch operation or byte edit.
+///
+/// The file on disk is not changed; save again to keep the undone state.
+fn undo(app_state: &mut AppState) {
+    let Some(snapshot) = app_state.undo_history.pop() else {
+        return;
+    };
+    if let Some(current) = app_state.file_data.as_ref() {
+        // Bytes changed back by the undo no longer count as edited by hand.
+        app_state.dirty_offsets.retain(|&offset| current.get(offset) == snapshot.get(offset));
+    }
+    if let Some(patch_set) = app_state.patch_set {
+        app_state.patch_status = check_all_statuses(&snapshot, patch_set);
+    }
+    app_state.file_data = Some(snapshot);
+    app_state.dirty = true;
+    app_state.hex_editor.editing_offset = None;
+    app_state.log.push(LogEntry::info(
+        "Undid the last change in memory. The saved file is unchanged; save again to keep this state.",
+    ));
+}
+
+/// Prompts the user to save `candidate`, the result of an apply, revert or restore made on a copy of
+/// the in-memory data, and makes it the in-memory data only once it was written.
+///
+/// Operations never change `file_data` before the save: if the dialog is cancelled or writing fails,
+/// the data and the displayed status stay exactly as they were, matching the file on disk. On success
+/// the previous data becomes an undo step. Returns true if the candidate was saved and adopted.
+fn save_candidate(app_state: &mut AppState, candidate: Vec<u8>, suffix: &str, label: &str) -> bool {
+    if refuse_if_read_only(app_state, "save files") {
+        return false;
+    }
+    let save_path = prompt_save_path(app_state, suffix);
+    adopt_if_saved(app_state, candidate, save_path, label)
+}
+
+/// Writes `candidate` to `save_path` (`None` if the dialog was cancelled) and adopts it if that worked.
+/// See `save_candidate`.
+fn adopt_if_saved(app_state: &mut AppState, candidate: Vec<u8>, save_path: Option<PathBuf>, label: &str) -> bool {
+    if !write_saved_data(app_state, save_path, &candidate, label) {
+        app_state.log.push(LogEntry::warning("Warning: The changes were not saved, so the in-memory data was left unchanged."));
+        return false;
+    }
+    if let Some(before) = app_state.file_data.replace(candidate) {
+        push_undo_snapshot(app_state, before);
+    }
+    true
+}
+
+/// Applies all patches to the loaded data and prompts the user to save the result.
+fn apply_and_save(app_state: &mut AppState) {
+    if refuse_if_read_only(app_state, "apply patches") || refuse_if_unsafe(app_state) {
+        return;
+    }
+    if let (Some(data), Some(patch_set)) = (app_state.file_data.as_ref(), app_state.patch_set) {
+        // Patch a copy; the in-memory data only changes once the result was saved.
+        let mut patched = data.clone();
+        // Forced patches skip validation on purpose, so there is no expected image to compare them with.
+        let cross_check = app_state.config.paranoid_mode && !app_state.force_apply;
+        let zeroize = app_state.zeroize;
+        // Forced and partial operations do not report progress; the bar is hidden for them.
+        let progress = &mut app_state.patch_progress;
+        *progress = None;
+        let result = if app_state.force_apply {
+            app_state.log.push(LogEntry::warning(
+                "Warning: Force apply is enabled. Original bytes are NOT validated; this can brick the ECU.",
+            ));
+            patcher::force_apply_patches(&mut patched, patch_set)
+        } else {
+            *progress = Some(("Applied", 0, patch_set.patches.len()));
+            patcher::apply_patches_with_progress(&mut patched, patch_set, &mut |done, total| {
+                *progress = Some(("Applied", done, total))
+            })
+        }
+        .and_then(|mut logs| {
+            if cross_check {
+                patcher::cross_check_applied(data, &patched, patch_set)?;
+                logs.push("Paranoid mode: the patched data matches the independently built image.".to_string());
+            }
+            if !app_state.post_patch_hooks.is_empty() {
+                logs.extend(app_state.post_patch_hooks.run(&mut patched, patch_set)?);
+                patcher::recalculate_checksum(&mut patched, patch_set)?;
+            }
+            if zeroize {
+                logs.extend(patcher::zeroize(&mut patched, patch_set)?);
+            }
+            Ok(logs)
+        });
+        match result {
+            Ok(logs) => {
+                let summary = patcher::summarize_changes("Applied", data, &patched, patch_set);
+                let version_warning = version_change_after_patching(data, &patched);
+                app_state.log.push(LogEntry::success(format!("Success: {}", summary)));
+                app_state.log.extend(logs.into_iter().map(LogEntry::from_library));
+                app_state.log.extend(version_warning.map(LogEntry::warning));
+                save_candidate(app_state, patched, "patched", "Patched");
+            }
+            // A failed hook may have left the copy half-done; it is simply dropped.
+            Err(e) => log_patcher_error(app_state, "Error applying patches", &e),
+        }
+    }
+}
+
+/// Returns a warning if patching changed the version detected in `before`.
+///
+/// The comparison is against the detection of `before`, not the active patch set, since a manual
+/// override may name a different version than the file holds.
+fn version_change_after_patching(before: &[u8], after: &[u8]) -> Option<String> {
+    let detected = version::detect_version(before).ok()?;
+    patcher::version_change_warning(detected, after)
+}
+
+/// Logs a patcher error after `context`. A validation or cross-check mismatch is followed by the differing bytes,
+/// one line each, since those tell a wrong version (one byte off) from a modified file (many bytes off).
+fn log_patcher_error(app_state: &mut AppState, context: &str, error: &PatcherError) {
+    app_state.log.push(LogEntry::error(format!("{}: {}", context, error)));
+    if let PatcherError::ValidationMismatch { offset, expected, found }
+    | PatcherError::CrossCheckMismatch { offset, expected, found } = error
+    {
+        app_state.log.extend(
+            patcher::format_byte_diff(expected, found, *offset).lines().map(|line| LogEntry::error(format!("  {}", line))),
+        );
+    }
+}
+
+/// Applies or reverts only the patch at `index`, recalculates the checksum and prompts the user to save.
+fn change_single_patch_and_save(app_state: &mut AppState, index: usize, apply: bool) {
+    if refuse_if_read_only(app_state, if apply { "apply patches" } else { "revert patches" }) {
+        return;
+    }
+    if apply && refuse_if_unsafe(app_state) {
+        return;
+    }
+    let (Some(data), Some(patch_set)) = (app_state.file_data.as_ref(), app_state.patch_set) else {
+        return;
+    };
+    let Some(patch) = patch_set.patches.get(index) else {
+        return;
+    };
+    let mut changed = data.clone();
+    let result = if apply { patcher::apply_patch(&mut changed, patch) } else { patcher::revert_patch(&mut changed, patch) }
+        .and_then(|log| {
+            patcher::recalculate_checksum(&mut changed, patch_set)?;
+            Ok(log)
+        });
+    match result {
+        Ok(log) => {
+            let verb = if apply { "Applied" } else { "Reverted" };
+            let summary = patcher::summarize_changes(verb, data, &changed, patch_set);
+            app_state.log.push(LogEntry::success(format!("Success: {}", summary)));
+            app_state.log.push(LogEntry::from_library(log));
+            if apply {
+                save_candidate(app_state, changed, "patched", "Patched");
+            } else {
+                save_candidate(app_state, changed, "reverted", "Reverted");
+            }
+        }
+        Err(e) => {
+            let verb = if apply { "applying" } else { "reverting" };
+            log_patcher_error(app_state, &format!("Error {} the {} patch", verb, patch.name), &e);
+        }
+    }
+}
+
+/// Logs what applying the patches would change, without modifying the data.
+fn preview_patches(app_state: &mut AppState) {
+    let (Some(data), Some(patch_set)) = (app_state.file_data.as_ref(), app_state.patch_set) else {
+        return;
+    };
+    match patcher::preview_patches(data, patch_set) {
+        Ok(previews) => {
+            let total: usize = previews.iter().map(|preview| preview.bytes_changed).sum();
+            app_state.log.push(LogEntry::info(format!("Preview: applying would change {} byte(s). Nothing was modified.", total)));
+            for preview in previews {
+                app_state.log.push(LogEntry::info(format!(
+                    "  {} at offset {:#X}: {} byte(s) change, {} -> {}",
+                    preview.name,
+                    preview.offset,
+                    preview.bytes_changed,
+                    bytes_to_hex_string(&preview.original),
+                    bytes_to_hex_string(&preview.patched)
+                )));
+                if let Some(warning) = preview.warning {
+                    app_state.log.push(LogEntry::warning(format!("Warning: {}", warning)));
+                }
+            }
+        }
+        Err(e) => app_state.log.push(LogEntry::error(format!("Error previewing patches: {}", e))),
+    }
+}
+
+/// Builds the default name of an exported file, `<source stem>_<tag>_<version>.<extension>`, e.g.
+/// `dump_EWSdelete_ca430037.ewsdelta`. Missing parts are left out.
+fn default_save_name(source_path: &str, tag: &str, version: Option<&str>, extension: &str) -> String {
+    let (stem, _) = batch::split_file_name(source_path);
+    let mut name = format!("{}_{}", stem, tag);
+    if let Some(version) = version {
+        name.push('_');
+        name.push_str(&batch::sanitize_file_name_part(version));
+    }
+    format!("{}.{}", name, extension)
+}
+
+/// Backs up the loaded file before its first save, if backups are enabled. Returns false, and logs
+/// why, if the backup failed and nothing should be written.
+fn ensure_backup(app_state: &mut AppState) -> bool {
+    if !app_state.config.create_backup || app_state.backup_path.is_some() {
+        return true;
+    }
+    match backup::backup_original(Path::new(&app_state.file_path)) {
+        Ok(path) => {
+            app_state.log.push(LogEntry::info(format!("Backup of the original file saved to {}", path.display())));
+            app_state.backup_path = Some(path);
+            true
+        }
+        Err(e) => {
+            app_state.log.push(LogEntry::error(format!(
+                "Error: Could not back up the original file, so nothing was saved: {}",
+                describe_io_error(&e)
+            )));
+            false
+        }
+    }
+}
+
+/// Save dialog filters for the firmware formats, as `(name, extensions)`.
+const SAVE_FORMAT_FILTERS: &[(&str, &[&str])] = &[
+    ("Binary firmware", &["bin", "dat"]),
+    ("Intel HEX", &["hex"]),
+    ("Motorola S-record", &["s19", "s28", "s37", "srec", "mot"]),
+];
+
+/// Returns a save dialog offering every firmware format, with the format of `source` first so it
+/// is preselected.
+fn save_dialog_with_formats(source: &ImageFormat) -> rfd::FileDialog {
+    let (preferred, others): (Vec<_>, Vec<_>) =
+        SAVE_FORMAT_FILTERS.iter().partition(|(_, extensions)| extensions.contains(&source.extension()));
+    preferred
+        .into_iter()
+        .chain(others)
+        .fold(rfd::FileDialog::new(), |dialog, &(name, extensions)| dialog.add_filter(name, extensions))
+}
+
+/// Prompts for a location and saves the in-memory data to it.
+///
+/// The file is written in the format matching the chosen extension, which defaults to the format
+/// the file was loaded in. `suffix` is put in the default file name, e.g. "patched", and `label`
+/// names the kind of file in the log, e.g. "Patched". Returns true if the file was written.
+fn save_file(app_state: &mut AppState, suffix: &str, label: &str) -> bool {
+    if refuse_if_read_only(app_state, "save files") {
+        return false;
+    }
+    let Some(data) = app_state.file_data.clone() else {
+        return false;
+    };
+    let save_path = prompt_save_path(app_state, suffix);
+    write_saved_data(app_state, save_path, &data, label)
+}
+
+/// Shows the save dialog, proposing the same name a batch would use, e.g. `dump_patched.bin` for
+/// `suffix` "patched". Returns `None` if the dialog was cancelled.
+fn prompt_save_path(app_state: &AppState, suffix: &str) -> Option<PathBuf> {
+    let default_name = batch::default_output_name(&app_state.file_path, suffix);
+    save_dialog_with_formats(&app_state.file_format).set_file_name(default_name).save_file()
+}
+
+/// Backs up the loaded file if needed, then writes `data` to `save_path` and verifies it. `None` means
+/// the save dialog was cancelled. Returns true if the file was written and verified.
+///
+/// On success, the displayed status is updated from `data`, which the caller keeps as the in-memory data.
+fn write_saved_data(app_state: &mut AppState, save_path: Option<PathBuf>, data: &[u8], label: &str) -> bool {
+    let Some(save_path) = save_path else {
+        app_state.log.push(LogEntry::info("Save operation cancelled."));
+        return false;
+    };
+    if !ensure_backup(app_state) {
+        return false;
+    }
+    let extension = save_path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let format = ImageFormat::for_extension(extension, &app_state.file_format);
+    // Read the source time before writing, in case the file is saved over its source.
+    let source_time = match app_state.config.saved_file_time {
+        SavedFileTime::Now => None,
+        SavedFileTime::MatchSource => Some(fs::metadata(&app_state.file_path).and_then(|m| m.modified())),
+    };
+    let encoded = ihex::encode(data, &format);
+    if let Err(e) = fs::write(&save_path, &encoded) {
+        app_state.log.push(LogEntry::error(format!("Error: Failed to save file: {}", describe_io_error(&e))));
+        return false;
+    }
+    if let Some(source_time) = source_time {
+        let result = source_time.and_then(|time| fs::File::options().write(true).open(&save_path)?.set_modified(time));
+        if let Err(e) = result {
+            app_state.log.push(LogEntry::warning(format!(
+                "Warning: Could not copy the modification time of the source file: {}",
+                describe_io_error(&e)
+            )));
+        }
+    }
+    app_state.log.push(LogEntry::success(format!(
+        "Success: {} file saved to {} ({})",
+        label,
+        save_path.display(),
+        format.name()
+    )));
+    if let Err(e) = verify::verify_written(&save_path, &encoded) {
+        app_state.log.push(LogEntry::error(format!(
+            "Error: Verification of {} failed, do not flash this file: {}",
+            save_path.display(),
+            e
+        )));
+        return false;
+    }
+    app_state.log.push(LogEntry::success(format!("Success: Verified {} bytes", encoded.len())));
+    app_state.dirty_offsets.clear();
+    app_state.dirty = false;
+    app_state.last_saved_path = Some(save_path);
+    if let Some(patch_set) = app_state.patch_set {
+        app_state.patch_status = check_all_statuses(data, patch_set);
+    }
+    true
+}
+
+/// Prompts for a location and saves the in-memory (patched) data to it. Returns true if the file was written.
+fn save_patched_file(app_state: &mut AppState) -> bool {
+    save_file(app_state, "patched", "Patched")
+}
+
+/// Returns true if there is something to save: unsaved in-memory changes, e.g. hand edits, or applied
+/// patches.
+fn can_save(app_state: &AppState) -> bool {
+    app_state.file_data.is_some() && (app_state.dirty || has_patched_data(app_state))
+}
+
+/// Prompts for a location and saves the in-memory data to it, named as patched if any patch is
+/// applied and as edited otherwise. Returns true if the file was written.
+fn save_as(app_state: &mut AppState) -> bool {
+    if has_patched_data(app_state) {
+        save_patched_file(app_state)
+    } else {
+        save_file(app_state, "edited", "Edited")
+    }
+}
+
+/// Returns true if the in-memory data has at least one patch applied.
+fn has_patched_data(app_state: &AppState) -> bool {
+    match (app_state.file_data.as_ref(), app_state.patch_set) {
+        (Some(data), Some(patch_set)) => {
+            patch_set.patches.iter().any(|patch| patcher::get_patch_status(data, patch) == PatchStatus::Patched)
+        }
+        _ => false,
+    }
+}
+
+/// Reverts the applied patches and prompts the user to save the result.
+///
+/// A fully patched file is reverted strictly; a mixed state only reverts the applied patches.
+fn revert_and_save(app_state: &mut AppState, fully_patched: bool) {
+    if refuse_if_read_only(app_state, "revert patches") {
+        return;
+    }
+    if let (Some(data), Some(patch_set)) = (app_state.file_data.as_ref(), app_state.patch_set) {
+        let mut reverted = data.clone();
+        let progress = &mut app_state.patch_progress;
+        *progress = None;
+        let result = if fully_patched {
+            *progress = Some(("Reverted", 0, patch_set.patches.len()));
+            patcher::revert_patches_with_progress(&mut reverted, patch_set, &mut |done, total| {
+                *progress = Some(("Reverted", done, total))
+            })
+        } else {
+            patcher::revert_patches_partial(&mut reverted, patch_set)
+        };
+        match result {
+            Ok(logs) => {
+                let summary = patcher::summarize_changes("Reverted", data, &reverted, patch_set);
+                app_state.log.push(LogEntry::success(format!("Success: {}", summary)));
+                app_state.log.extend(logs.into_iter().map(LogEntry::from_library));
+                save_candidate(app_state, reverted, "reverted", "Reverted");
+            }
+            Err(e) => log_patcher_error(app_state, "Error reverting patches", &e),
+        }
+    }
+}
+
+/// Returns the session backup if it still exists, so it can replace a file in the Unknown state.
+fn restorable_backup(app_state: &AppState) -> Option<&Path> {
+    app_state.backup_path.as_deref().filter(|path| path.exists())
+}
+
+/// Replaces the in-memory data with the session backup and prompts the user to save the result.
+///
+/// This rescues files that cannot be reverted byte by byte, e.g. because an external tool only
+/// half-applied the patches.
+fn restore_backup_and_save(app_state: &mut AppState) {
+    if refuse_if_read_only(app_state, "restore backups") {
+        return;
+    }
+    if let Some(restored) = read_backup(app_state) {
+        save_candidate(app_state, restored, "restored", "Restored");
+    }
+}
+
+/// Reads the image held by the session backup, logging why if it cannot be used.
+///
+/// For a file loaded from a ZIP archive the backup is a copy of the archive, so the loaded entry is
+/// extracted from it. The image must hold the active version.
+fn read_backup(app_state: &mut AppState) -> Option<Vec<u8>> {
+    let backup_path = restorable_backup(app_state)?.to_path_buf();
+    let (mut restored, patch_set) = (app_state.file_data.clone()?, app_state.patch_set?);
+    let zip_entry = app_state.zip_entry.as_deref();
+    if let Err(e) = backup::restore_from_backup(&mut restored, &backup_path, zip_entry, patch_set.version_string) {
+        app_state.log.push(LogEntry::error(format!("Error: Could not restore {}: {}", backup_path.display(), e)));
+        return None;
+    }
+    app_state.log.push(LogEntry::info(format!("Read the backup {}", backup_path.display())));
+    let statuses: Vec<String> =
+        check_all_statuses(&restored, patch_set).iter().map(|(name, status)| format!("{} {:?}", name, status)).collect();
+    app_state.log.push(LogEntry::info(format!("Status after restoring: {}", statuses.join(", "))));
+    Some(restored)
+}
+
+/// Logs a report of every candidate checksum scheme and region for the loaded file.
+fn diagnose_checksum(app_state: &mut AppState) {
+    let Some(data) = app_state.file_data.as_ref() else {
+        return;
+    };
+    let candidates = checksum::diagnose_checksums(data);
+    app_state.log.push(LogEntry::info(format!(
+        "Checksum diagnosis for '{}' ({}, {} bytes):",
+        app_state.file_path, app_state.detected_version, data.len()
+    )));
+    if candidates.is_empty() {
+        app_state.log.push(LogEntry::info("  No candidate regions fit in this file."));
+        return;
+    }
+    for candidate in &candidates {
+        app_state.log.push(LogEntry::info(format!("  {}", candidate)));
+    }
+    let matches = candidates.iter().filter(|c| c.matches()).count();
+    if matches > 0 {
+        app_state.log.push(LogEntry::success(format!("Success: {} candidate(s) match the stored value.", matches)));
+    } else {
+        app_state.log.push(LogEntry::info("No candidate matches the stored value."));
+    }
+}
+
+/// Returns true if the working buffer differs from the file as it was loaded.
+fn has_unsaved_edits(app_state: &AppState) -> bool {
+    app_state.file_data.is_some() && app_state.file_data != app_state.loaded_data
+}
+
+/// Throws away all in-memory changes by restoring the data exactly as it was loaded.
+///
+/// Unlike reverting, this also undoes manual hex edits and anything else outside the patch regions.
+fn discard_changes(app_state: &mut AppState) {
+    let (Some(loaded), Some(data)) = (app_state.loaded_data.as_ref(), app_state.file_data.as_mut()) else {
+        return;
+    };
+    data.copy_from_slice(loaded);
+    app_state.dirty_offsets.clear();
+    // The data is the loaded file again, which is still on disk.
+    app_state.dirty = false;
+    app_state.hex_editor.editing_offset = None;
+    if let Some(patch_set) = app_state.patch_set {
+        app_state.patch_status = check_all_statuses(data, patch_set);
+    }
+    app_state.log.push(LogEntry::info("Discarded all in-memory changes."));
+}
+
+/// Builds the audit report of the changes made to the loaded file.
+fn build_report(app_state: &AppState) -> Option<String> {
+    let (Some(before), Some(after), Some(patch_set)) =
+        (app_state.loaded_data.as_ref(), app_state.file_data.as_ref(), app_state.patch_set)
+    else {
+        return None;
+    };
+    let log: Vec<String> = app_state.log.iter().map(|entry| entry.message.clone()).collect();
+    Some(report::generate_report(&report::ReportInput {
+        file_name: &app_state.file_path,
+        patch_set,
+        hardware_variant: Some(app_state.hardware_variant.as_str()).filter(|variant| *variant != "N/A"),
+        before,
+        after,
+        log: &log,
+    }))
+}
+
+/// Returns where "Save Report" writes the report for a saved firmware file: next to it, as `.txt`.
+fn report_path_for(saved_path: &Path) -> PathBuf {
+    saved_path.with_extension("txt")
+}
+
+/// Writes the audit report next to the last saved firmware file, without asking for a location.
+fn save_report_next_to_output(app_state: &mut AppState) {
+    let Some(path) = app_state.last_saved_path.as_deref().map(report_path_for) else {
+        return;
+    };
+    let Some(contents) = build_report(app_state) else {
+        return;
+    };
+    match fs::write(&path, contents) {
+        Ok(()) => app_state.log.push(LogEntry::success(format!("Success: Report saved to {}", path.display()))),
+        Err(e) => app_state.log.push(LogEntry::error(format!("Error: Failed to save report: {}", describe_io_error(&e)))),
+    }
+}
+
+/// Writes an audit report of the changes made to the loaded file.
+fn export_report(app_state: &mut AppState) {
+    let Some(contents) = build_report(app_state) else {
+        return;
+    };
+    if let Some(path) = rfd::FileDialog::new()
+        .add_filter("Markdown", &["md"])
+        .add_filter("Text", &["txt"])
+        .set_file_name("ews_patch_report.md")
+        .save_file()
+    {
+        match fs::write(&path, contents) {
+            Ok(()) => app_state.log.push(LogEntry::success(format!("Success: Report saved to {}", path.display()))),
+            Err(e) => app_state.log.push(LogEntry::error(format!("Error: Failed to save report: {}", describe_io_error(&e)))),
+        }
+    } else {
+        app_state.log.push(LogEntry::info("Report export cancelled."));
+    }
+}
+
+/// Saves the active patch set as a TOML or JSON patch set file, which can be loaded as an external set.
+fn export_patch_set(app_state: &mut AppState) {
+    let Some(patch_set) = app_state.patch_set else {
+        return;
+    };
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter("TOML", &["toml"])
+        .add_filter("JSON", &["json"])
+        .set_file_name(format!("patch_sets_{}.toml", patch_set.version_string))
+        .save_file()
+    else {
+        app_state.log.push(LogEntry::info("Patch set export cancelled."));
+        return;
+    };
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let contents = match PatchFileFormat::for_extension(extension) {
+        Some(PatchFileFormat::Json) => patch_file::serialize_patch_sets(&[patch_set], PatchFileFormat::Json),
+        _ => patch_file::serialize_patch_set(patch_set),
+    };
+    match fs::write(&path, contents) {
+        Ok(()) => app_state.log.push(LogEntry::success(format!(
+            "Success: Patch set {} saved to {}",
+            patch_set.version_string,
+            path.display()
+        ))),
+        Err(e) => app_state.log.push(LogEntry::error(format!("Error: Failed to save patch set: {}", describe_io_error(&e)))),
+    }
+}
+
+/// Saves the patch regions changed relative to the unpatched firmware as a delta patch file.
+///
+/// Works for patches applied in memory as well as for files loaded already patched, since the
+/// unpatched bytes are rebuilt by reverting a copy.
+fn export_delta(app_state: &mut AppState) {
+    let (Some(data), Some(patch_set)) = (app_state.file_data.as_ref(), app_state.patch_set) else {
+        return;
+    };
+    let mut unpatched = data.clone();
+    if let Err(e) = patcher::revert_patches_partial(&mut unpatched, patch_set) {
+        app_state.log.push(LogEntry::error(format!("Error: Cannot build the delta patch: {}", e)));
+        return;
+    }
+    let delta = delta::Delta::from_buffers(&unpatched, data, patch_set);
+    let default_name = default_save_name(&app_state.file_path, "EWSdelete", Some(patch_set.version_string), "ewsdelta");
+    if let Some(path) = rfd::FileDialog::new()
+        .add_filter("Delta Patch", &["ewsdelta"])
+        .set_file_name(default_name)
+        .save_file()
+    {
+        match fs::write(&path, delta::encode_delta(&delta)) {
+            Ok(()) => app_state.log.push(LogEntry::success(format!(
+                "Success: Delta patch with {} region(s) saved to {}",
+                delta.records.len(),
+                path.display()
+            ))),
+            Err(e) => app_state.log.push(LogEntry::error(format!("Error: Failed to save delta patch: {}", describe_io_error(&e)))),
+        }
+    } else {
+        app_state.log.push(LogEntry::info("Delta patch export cancelled."));
+    }
+}
+
+/// Applies a delta patch file to the loaded data and prompts the user to save the result.
+fn apply_delta_file(app_state: &mut AppState) {
+    if refuse_if_read_only(app_state, "apply delta patches") {
+        return;
+    }
+    let Some(path) = rfd::FileDialog::new().add_filter("Delta Patch", &["ewsdelta"]).pick_file() else {
+        return;
+    };
+    let Some(mut patched) = app_state.file_data.clone() else {
+        return;
+    };
+    let result = fs::read(&path)
+        .map_err(|e| describe_io_error(&e))
+        .and_then(|bytes| delta::decode_delta(&bytes).map_err(|e| e.to_string()))
+        .and_then(|delta| delta::apply_delta(&mut patched, &delta).map_err(|e| e.to_string()));
+    match result {
+        Ok(logs) => {
+            app_state.log.push(LogEntry::success(format!("Success: Applied delta patch {}", path.display())));
+            app_state.log.extend(logs.into_iter().map(LogEntry::from_library));
+            save_candidate(app_state, patched, "patched", "Patched");
+        }
+        Err(e) => app_state.log.push(LogEntry::error(format!("Error applying delta patch: {}", e))),
+    }
+}
+
+/// Returns the image to compare the working data against: the loaded file if it was changed in
+/// memory, otherwise the loaded file with its patches reverted, i.e. the original from the patch set.
+fn comparison_baseline(app_state: &AppState) -> Option<Vec<u8>> {
+    let data = app_state.file_data.as_ref()?;
+    if has_unsaved_edits(app_state) {
+        return app_state.loaded_data.clone();
+    }
+    let mut unpatched = data.clone();
+    patcher::revert_patches_partial(&mut unpatched, app_state.patch_set?).ok()?;
+    Some(unpatched)
+}
+
+/// Saves a side-by-side hex dump of the original and the working data to a text file.
+fn export_hex_dump(app_state: &mut AppState) {
+    let Some(after) = app_state.file_data.as_ref() else {
+        return;
+    };
+    let Some(before) = comparison_baseline(app_state) else {
+        app_state.log.push(LogEntry::error(
+            "Error: Cannot export a hex dump: the patch regions match neither their original nor patched bytes.",
+        ));
+        return;
+    };
+    let range = if app_state.full_hex_dump {
+        report::DumpRange::Full
+    } else {
+        report::DumpRange::ChangedWithContext(2)
+    };
+    let contents = format!(
+        "Hex dump of {} ({}), before | after. '>' marks changed rows, '*' changed bytes.\n\n{}",
+        app_state.file_path,
+        app_state.detected_version,
+        report::side_by_side_dump(&before, after, range)
+    );
+    let default_name = default_save_name(&app_state.file_path, "hexdump", app_state.patch_set.map(|set| set.version_string), "txt");
+    if let Some(path) = rfd::FileDialog::new().add_filter("Text", &["txt"]).set_file_name(default_name).save_file() {
+        match fs::write(&path, contents) {
+            Ok(()) => app_state.log.push(LogEntry::success(format!("Success: Hex dump saved to {}", path.display()))),
+            Err(e) => app_state.log.push(LogEntry::error(format!("Error: Failed to save hex dump: {}", describe_io_error(&e)))),
+        }
+    } else {
+        app_state.log.push(LogEntry::info("Hex dump export cancelled."));
+    }
+}
+
+/// Opens the folder of the loaded file in the OS file manager.
+fn open_loaded_folder(app_state: &mut AppState) {
+    if let Err(e) = reveal::open_containing_folder(Path::new(&app_state.file_path)) {
+        app_state.log.push(LogEntry::error(format!("Error: {}", e)));
+    }
+}
+
+/// Shows the most recently saved file in the OS file manager.
+fn reveal_saved_file(app_state: &mut AppState) {
+    let Some(path) = app_state.last_saved_path.as_ref() else {
+        return;
+    };
+    if let Err(e) = reveal::reveal_file(path) {
+        app_state.log.push(LogEntry::error(format!("Error: {}", e)));
+    }
+}
+
+/// Handles the global keyboard shortcuts, invoking the same actions as the buttons.
+///
+/// * `Ctrl+O` - Browse for a firmware file.
+/// * `Ctrl+S` - Save the in-memory data, only when it has unsaved changes or applied patches.
+/// * `Ctrl+Z` - Undo the last patch operation or byte edit.
+///
+/// `Ctrl+P` and `Ctrl+R` are handled by `handle_patch_shortcuts`, since they depend on the Patcher tab.
+fn handle_shortcuts(ui: &Ui, app_state: &mut AppState) {
+    if !ui.io().key_ctrl {
+        return;
+    }
+    if ui.is_key_pressed_no_repeat(Key::O) {
+        browse_for_file(app_state);
+    }
+    if ui.is_key_pressed_no_repeat(Key::S) && can_save(app_state) {
+        save_as(app_state);
+    }
+    // Text fields have their own undo.
+    if ui.is_key_pressed(Key::Z) && !ui.io().want_text_input && !app_state.undo_history.is_empty() {
+        undo(app_state);
+    }
+}
+
+/// Handles the shortcuts of the Patcher tab's buttons, only when the matching button is enabled:
+///
+/// * `Ctrl+P` - Apply the patches, asking for confirmation of high-risk patches like the button.
+/// * `Ctrl+R` - Revert the patches.
+fn handle_patch_shortcuts(ui: &Ui, app_state: &mut AppState, can_apply: bool, can_revert: bool, fully_patched: bool) {
+    if !ui.io().key_ctrl || ui.io().want_text_input {
+        return;
+    }
+    if can_apply && ui.is_key_pressed_no_repeat(Key::P) {
+        request_apply(ui, app_state, None);
+    }
+    if can_revert && ui.is_key_pressed_no_repeat(Key::R) {
+        revert_and_save(app_state, fully_patched);
+    }
+}
+
+/// Returns a " (modified)"/" (foreign)" style suffix for a status row in the Unknown state.
+fn unknown_suffix(app_state: &AppState, index: usize, status: PatchStatus) -> String {
+    if status != PatchStatus::Unknown {
+        return String::new();
+    }
+    match (app_state.file_data.as_ref(), app_state.patch_set.and_then(|set| set.patches.get(index))) {
+        (Some(data), Some(patch)) => format!(" ({})", patcher::classify_unknown(data, patch).label()),
+        _ => String::new(),
+    }
+}
+
+/// Shows what the patch at `index` does, followed by its offset, expected bytes and current bytes, when
+/// its status row is hovered.
+///
+/// For the Unknown state, the tooltip also explains why the bytes match neither expected value.
+fn patch_status_tooltip(ui: &Ui, app_state: &AppState, index: usize, status: PatchStatus) {
+    if !ui.is_item_hovered() {
+        return;
+    }
+    if let (Some(data), Some(patch)) = (
+        app_state.file_data.as_ref(),
+        app_state.patch_set.and_then(|set| set.patches.get(index)),
+    ) {
+        let mut text = match patch.description {
+            "" => String::new(),
+            description => format!("{}\n\n", description),
+        };
+        text.push_str(&patcher::describe_patch_bytes(data, patch));
+        if status == PatchStatus::Unknown {
+            let kind = patcher::classify_unknown(data, patch);
+            text.push_str(&format!("\n\nLooks {}: {}", kind.label(), kind.explanation()));
+        }
+        ui.tooltip_text(text);
+    }
+}
+
+/// Formats the diff of `patch` as plain text for the clipboard: version, patch name, offset and address,
+/// then the original and patched bytes.
+fn format_patch_diff(patch_set: &PatchSet, patch: &Patch) -> String {
+    format!(
+        "{} {} patch\nOffset: {:#X} (address {:#X})\nOriginal: {}\nPatched:  {}",
+        patch_set.version_string,
+        patch.name,
+        patch.offset,
+        patch_set.offset_to_address(patch.offset),
+        bytes_to_hex_string(&patch.original),
+        bytes_to_hex_string(&patch.patched)
+    )
+}
+
+/// Title of the confirmation popup shown before applying high-risk patches or a manually chosen patch set.
+const CONFIRM_APPLY_POPUP: &str = "Confirm Apply";
+
+/// Returns the badge color used for a risk level.
+fn risk_color(risk: RiskLevel) -> [f32; 4] {
+    match risk {
+        RiskLevel::Low => [0.2, 0.8, 0.2, 1.0],
+        RiskLevel::Normal => [0.5, 0.5, 0.5, 1.0],
+        RiskLevel::High => [1.0, 0.3, 0.1, 1.0],
+    }
+}
+
+/// Renders the risk badge of the patch at `index` at the right end of its status row.
+fn render_risk_badge(ui: &Ui, app_state: &AppState, index: usize) {
+    let Some(patch) = app_state.patch_set.and_then(|set| set.patches.get(index)) else {
+        return;
+    };
+    ui.same_line_with_pos(ui.window_content_region_max()[0] - 60.0);
+    ui.text_colored(risk_color(patch.risk), format!("[{}]", patch.risk.label()));
+}
+
+/// Returns the high-risk patches about to be applied: only the one at `single`, or all of them.
+fn pending_high_risk_patches(patch_set: &PatchSet, single: Option<usize>) -> Vec<&Patch> {
+    match single {
+        Some(index) => patch_set.patches.get(index).filter(|patch| patch.risk == RiskLevel::High).into_iter().collect(),
+        None => patch_set.high_risk_patches(),
+    }
+}
+
+/// Applies the patch at `single`, or all patches if `None`, first asking for confirmation if any
+/// of them is high-risk or the patch set was chosen manually.
+fn request_apply(ui: &Ui, app_state: &mut AppState, single: Option<usize>) {
+    app_state.pending_single_patch = single;
+    let high_risk = app_state.patch_set.is_some_and(|set| !pending_high_risk_patches(set, single).is_empty());
+    if high_risk || is_manual_override(app_state) {
+        ui.open_popup(CONFIRM_APPLY_POPUP);
+    } else {
+        confirm_apply(app_state);
+    }
+}
+
+/// Runs the apply requested by `request_apply`.
+fn confirm_apply(app_state: &mut AppState) {
+    match app_state.pending_single_patch.take() {
+        Some(index) => change_single_patch_and_save(app_state, index, true),
+        None => apply_and_save(app_state),
+    }
+}
+
+/// Renders the confirmation popup listing the high-risk patches about to be applied, and warning
+/// about a manually chosen patch set.
+fn render_high_risk_confirmation(ui: &Ui, app_state: &mut AppState) {
+    ui.modal_popup_config(CONFIRM_APPLY_POPUP).always_auto_resize(true).build(|| {
+        if let (true, Some(patch_set)) = (is_manual_override(app_state), app_state.patch_set) {
+            ui.text_colored(
+                [1.0, 0.7, 0.1, 1.0],
+                format!(
+                    "MANUAL OVERRIDE: patch set {} is used instead of the detected version {}.",
+                    patch_set.version_string, app_state.detected_version
+                ),
+            );
+            ui.text("Its offsets were not confirmed for this file. Wrong offsets on the wrong version can brick the ECU.");
+            ui.spacing();
+        }
+        let high_risk = app_state
+            .patch_set
+            .map(|set| pending_high_risk_patches(set, app_state.pending_single_patch))
+            .unwrap_or_default();
+        if !high_risk.is_empty() {
+            ui.text("These patches change the immobilizer logic and are high-risk on a running car:");
+            for patch in high_risk {
+                ui.bullet_text(format!("{} at offset {:#X}", patch.name, patch.offset));
+            }
+        }
+        ui.text("Only continue if this file is meant for this car and you have a backup.");
+        ui.spacing();
+        if ui.button("Apply Anyway") {
+            ui.close_current_popup();
+            confirm_apply(app_state);
+        }
+        ui.same_line();
+        if ui.button("Cancel") {
+            ui.close_current_popup();
+            app_state.pending_single_patch = None;
+            app_state.log.push(LogEntry::info("Apply cancelled."));
+        }
+    });
+}
+
+/// Renders Apply/Revert buttons that change only the patch at `index`.
+///
+/// Each button is enabled only when the patch region is in the state its operation validates.
+fn render_single_patch_actions(ui: &Ui, app_state: &mut AppState, index: usize) {
+    let status = match (app_state.file_data.as_ref(), app_state.patch_set.and_then(|set| set.patches.get(index))) {
+        (Some(data), Some(patch)) => patcher::get_patch_status(data, patch),
+        _ => return,
+    };
+    let writable = !app_state.read_only;
+    ui.disabled(!writable || status != PatchStatus::Unpatched, || {
+        if ui.button("Apply This Patch") {
+            request_apply(ui, app_state, Some(index));
+        }
+    });
+    render_high_risk_confirmation(ui, app_state);
+    ui.same_line();
+    ui.disabled(!writable || status != PatchStatus::Patched, || {
+        if ui.button("Revert This Patch") {
+            change_single_patch_and_save(app_state, index, false);
+        }
+    });
+}
+
+/// Returns the status icon color used for a patch status.
+fn status_color(status: PatchStatus) -> [f32; 4] {
+    match status {
+        PatchStatus::Patched => [0.1, 0.9, 0.1, 1.0],
+        PatchStatus::Unpatched => [0.5, 0.5, 0.5, 1.0],
+        PatchStatus::Unknown => [0.9, 0.1, 0.1, 1.0],
+    }
+}
+
+/// Renders a strip representing the whole file with a colored mark at each patch offset.
+///
+/// Clicking a mark selects that patch; hovering shows its name and offset.
+fn render_patch_map(ui: &Ui, app_state: &mut AppState) {
+    let (Some(data), Some(patch_set)) = (app_state.file_data.as_ref(), app_state.patch_set) else {
+        return;
+    };
+    if data.is_empty() {
+        return;
+    }
+
+    let width = ui.content_region_avail()[0];
+    let height = 16.0;
+    let origin = ui.cursor_screen_pos();
+    ui.invisible_button("##patch_map", [width, height]);
+    let hovered = ui.is_item_hovered();
+    let clicked = ui.is_item_clicked();
+
+    let draw_list = ui.get_window_draw_list();
+    draw_list
+        .add_rect(origin, [origin[0] + width, origin[1] + height], [0.3, 0.3, 0.3, 1.0])
+        .filled(true)
+        .build();
+
+    let mouse_x = ui.io().mouse_pos[0];
+    let mut nearest: Option<(usize, f32)> = None;
+    for (index, patch) in patch_set.patches.iter().enumerate() {
+        let x = origin[0] + width * patch.offset as f32 / data.len() as f32;
+        let color = status_color(patcher::get_patch_status(data, patch));
+        let thickness = if app_state.selected_patch_index == Some(index) { 4.0 } else { 2.0 };
+        draw_list
+            .add_line([x, origin[1]], [x, origin[1] + height], color)
+            .thickness(thickness)
+            .build();
+
+        let distance = (mouse_x - x).abs();
+        if distance <= 6.0 && nearest.is_none_or(|(_, best)| distance < best) {
+            nearest = Some((index, distance));
+        }
+    }
+
+    if hovered {
+        if let Some((index, _)) = nearest {
+            let patch = &patch_set.patches[index];
+            ui.tooltip_text(format!("{} patch at offset {:#X}", patch.name, patch.offset));
+            if clicked {
+                app_state.selected_patch_index = Some(index);
+            }
+        }
+    }
+}
+
+/// Renders the converter between file offsets and mapped ECU addresses.
+fn render_address_calculator(ui: &Ui, app_state: &mut AppState, patch_set: &PatchSet) {
+    ui.text(format!("Address Calculator (flash mapped at {:#X})", patch_set.base_address));
+
+    ui.set_next_item_width(150.0);
+    ui.input_text("File offset##calc_offset", &mut app_state.calc_offset_input).build();
+    ui.same_line();
+    match parse_hex_usize(&app_state.calc_offset_input) {
+        Some(offset) => ui.text(format!("-> address {:#X}", patch_set.offset_to_address(offset))),
+        None if app_state.calc_offset_input.trim().is_empty() => {}
+        None => ui.text_disabled("not a hex number"),
+    }
+
+    ui.set_next_item_width(150.0);
+    ui.input_text("Mapped address##calc_address", &mut app_state.calc_address_input).build();
+    ui.same_line();
+    match parse_hex_usize(&app_state.calc_address_input) {
+        Some(address) => match patch_set.address_to_offset(address) {
+            Some(offset) => ui.text(format!("-> file offset {:#X}", offset)),
+            None => ui.text_disabled("address is below the flash base"),
+        },
+        None if app_state.calc_address_input.trim().is_empty() => {}
+        None => ui.text_disabled("not a hex number"),
+    }
+}
+
+fn render_menu_bar(ui: &Ui, app_state: &mut AppState) {
+    ui.menu_bar(|| {
+        ui.menu("File", || {
+            if ui.menu_item_config("Open...").enabled(!is_loading(app_state)).build() {
+                browse_for_file(app_state);
+            }
+            ui.menu("Recent Files", || {
+                if app_state.config.recent_files.is_empty() {
+                    ui.text_disabled("No recent files");
+                }
+                let mut chosen = None;
+                for path in &app_state.config.recent_files {
+                    // Files that were moved or deleted are shown grayed out.
+                    let enabled = path.exists() && !is_loading(app_state);
+                    if ui.menu_item_config(path.display().to_string()).enabled(enabled).build() {
+                        chosen = Some(path.clone());
+                    }
+                }
+                if let Some(path) = chosen {
+                    if !ask_before_discarding(app_state, DiscardAction::Load(path.clone())) {
+                        start_load(app_state, path);
+                    }
+                }
+                ui.separator();
+                if ui.menu_item_config("Remove Missing Files").enabled(!app_state.config.recent_files.is_empty()).build() {
+                    app_state.config.recent_files.retain(|path| path.exists());
+                }
+            });
+            ui.separator();
+            if ui.menu_item_config("Batch Patch Folder...").enabled(!app_state.read_only).build() {
+                batch_window::run_batch(&mut app_state.batch, &mut app_state.log);
+            }
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Patches every supported file in a folder and writes <name>_patched.bin files to another folder.");
+            }
+        });
+        ui.menu("View", || {
+            if ui.menu_item_config("Dark Theme").selected(app_state.config.theme == Theme::Dark).build() {
+                app_state.config.theme = Theme::Dark;
+            }
+            if ui.menu_item_config("Light Theme").selected(app_state.config.theme == Theme::Light).build() {
+                app_state.config.theme = Theme::Light;
+            }
+        });
+        ui.menu("Settings", || {
+            ui.menu_item_config("Inspect Mode (read-only)").build_with_ref(&mut app_state.read_only);
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Refuses applying, reverting, editing and saving, so the loaded file cannot be changed.");
+            }
+            ui.menu_item_config("Safe Mode").build_with_ref(&mut app_state.safe_mode);
+            if ui.is_item_hovered() {
+                ui.tooltip_text(
+                    "Only applies patches to a file detected with a single patch set and fully unpatched,\n\
+                     without force apply, and only after a backup of it was written.",
+                );
+            }
+            ui.menu_item_config("Paranoid Mode (cross-check patches)").build_with_ref(&mut app_state.config.paranoid_mode);
+            if ui.is_item_hovered() {
+                ui.tooltip_text(
+                    "Before saving, rebuilds the patched image in a separate buffer and refuses to save if it differs \
+                     from the applied data.",
+                );
+            }
+            ui.menu("Saved File Time", || {
+                let time = &mut app_state.config.saved_file_time;
+                if ui.menu_item_config("Time of Saving").selected(*time == SavedFileTime::Now).build() {
+                    *time = SavedFileTime::Now;
+                }
+                if ui.menu_item_config("Match Source File").selected(*time == SavedFileTime::MatchSource).build() {
+                    *time = SavedFileTime::MatchSource;
+                }
+            });
+        });
+        ui.menu("Help", || {
+            if ui.menu_item("Supported Versions") {
+                app_state.show_supported_versions = true;
+            }
+            if ui.menu_item("Compare Patch Sets") {
+                app_state.compare_sets.open_for(app_state.patch_set);
+            }
+        });
+    });
+}
+
+/// Returns the bytes at the primary version location as hex and ASCII, e.g. for pasting into a
+/// support request when the version is not recognized. Returns `None` until a file was probed.
+fn version_region_preview(probes: &[VersionProbe]) -> Option<String> {
+    let probe = probes.first()?;
+    if probe.raw.is_empty() {
+        return Some(format!("{:#X}: (the file ends before this offset)", probe.offset));
+    }
+    Some(format!("{:#X}: {} | {}", probe.offset, bytes_to_hex_string(&probe.raw), ascii_gutter(&probe.raw)))
+}
+
+/// Renders the raw bytes, cleaned string and match of every probed version location.
+fn render_version_details(ui: &Ui, app_state: &AppState) {
+    if !ui.collapsing_header("Version Detection Details", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+        return;
+    }
+    if app_state.version_probes.is_empty() {
+        ui.text("Load a file to see the probed version bytes.");
+        return;
+    }
+    for probe in &app_state.version_probes {
+        ui.text(format!("Offset {:#X}: {}", probe.offset, bytes_to_hex_string(&probe.raw)));
+        ui.indent();
+        ui.text(format!("Cleaned: '{}'", probe.cleaned));
+        match &probe.result {
+            Ok(set) => ui.text_colored(
+                [0.2, 0.8, 0.2, 1.0],
+                format!("Matched: {} (variant {})", set.version_string, set.hardware_variant.unwrap_or("N/A")),
+            ),
+            Err(e) => ui.text_colored([1.0, 0.7, 0.1, 1.0], format!("No match: {}", e)),
+        }
+        ui.unindent();
+    }
+}
+
+/// Renders a window listing every built-in patch set, so users can check support before opening a file.
+fn render_supported_versions(ui: &Ui, app_state: &mut AppState) {
+    if !app_state.show_supported_versions {
+        return;
+    }
+    ui.window("Supported Versions")
+        .opened(&mut app_state.show_supported_versions)
+        .size([420.0, 220.0], Condition::FirstUseEver)
+        .collapsible(false)
+        .build(|| {
+            if let Some(_table) = ui.begin_table_with_flags(
+                "SupportedVersionsTable",
+                3,
+                TableFlags::BORDERS | TableFlags::ROW_BG | TableFlags::SIZING_FIXED_FIT,
+            ) {
+                ui.table_setup_column("Version");
+                ui.table_setup_column("Variant");
+                ui.table_setup_column("Patches");
+                ui.table_headers_row();
+                for set in patches::known_patch_sets() {
+                    ui.table_next_row();
+                    ui.table_next_column();
+                    ui.text(set.version_string);
+                    ui.table_next_column();
+                    ui.text(set.hardware_variant.unwrap_or("-"));
+                    ui.table_next_column();
+                    let names: Vec<&str> = set.patches.iter().map(|patch| patch.name).collect();
+                    ui.text(names.join(", "));
+                }
+            }
+        });
 }
 
 pub fn render_main_window(ui: &mut Ui, app_state: &mut AppState) {
+    poll_pending_load(app_state);
+    handle_shortcuts(ui, app_state);
+
     let display_size = ui.io().display_size;
     ui.window("EWS Patcher")
         .size(display_size, Condition::Always)
@@ -68,9 +1740,12 @@ pub fn render_main_window(ui: &mut Ui, app_state: &mut AppState) {
                 | imgui::WindowFlags::NO_RESIZE
                 | imgui::WindowFlags::NO_MOVE
                 | imgui::WindowFlags::NO_COLLAPSE
-                | imgui::WindowFlags::NO_SAVED_SETTINGS,
+                | imgui::WindowFlags::NO_SAVED_SETTINGS
+                | imgui::WindowFlags::MENU_BAR,
         )
         .build(|| {
+            render_menu_bar(ui, app_state);
+
             TabBar::new("MainTabBar").build(ui, || {
                 TabItem::new("Patcher").build(ui, || {
                     // Top section for file selection
@@ -83,30 +1758,34 @@ pub fn render_main_window(ui: &mut Ui, app_state: &mut AppState) {
                                 .read_only(true)
                                 .build();
                             ui.same_line();
-                            if ui.button("Browse...") {
-                                if let Some(path) = rfd::FileDialog::new().add_filter("Binary firmware files", &["bin", "dat"]).pick_file() {
-                                    let file_path_str = path.display().to_string();
-                                    app_state.log.push(format!("Loading file: {}", file_path_str));
-                                    reset_state(app_state); // Reset state before loading new file
-                                    app_state.file_path = file_path_str; // Keep file path after reset
-
-                                    match fs::read(&path) {
-                                        Ok(data) => {
-                                            app_state.log.push(format!("Successfully read {} bytes.", data.len()));
-                                            match detect_version(&data) {
-                                                Ok(patch_set) => {
-                                                    app_state.log.push(format!("Success: Detected version '{}'", patch_set.version_string));
-                                                    app_state.detected_version = patch_set.version_string.to_string();
-                                                    app_state.hardware_variant = patch_set.hardware_variant.unwrap_or("N/A").to_string();
-                                                    app_state.patch_status = check_patch_status(&data, patch_set);
-                                                    app_state.patch_set = Some(patch_set);
-                                                    app_state.file_data = Some(data);
-                                                }
-                                                Err(e) => app_state.log.push(format!("Error: Version detection failed: {}", e)),
-                                            }
-                                        }
-                                        Err(e) => app_state.log.push(format!("Error: Failed to read file: {}", e)),
-                                    }
+                            ui.disabled(is_loading(app_state), || {
+                                if ui.button("Browse...") {
+                                    browse_for_file(app_state);
+                                }
+                            });
+                            if is_loading(app_state) {
+                                ui.same_line();
+                                let dots = ".".repeat((ui.time() * 3.0) as usize % 4);
+                                ui.text(format!("Loading{}", dots));
+                            }
+                            if ui.is_item_hovered() {
+                                ui.tooltip_text("Open a firmware file (Ctrl+O)");
+                            }
+                            ui.same_line();
+                            ui.disabled(app_state.file_path.is_empty(), || {
+                                if ui.button("Open Folder") {
+                                    open_loaded_folder(app_state);
+                                }
+                            });
+                            ui.same_line();
+                            ui.disabled(app_state.last_saved_path.is_none(), || {
+                                if ui.button("Reveal Saved File") {
+                                    reveal_saved_file(app_state);
+                                }
+                            });
+                            if let Some(path) = &app_state.last_saved_path {
+                                if ui.is_item_hovered() {
+                                    ui.tooltip_text(format!("Show {} in the file manager", path.display()));
                                 }
                             }
                         });
@@ -115,115 +1794,253 @@ pub fn render_main_window(ui: &mut Ui, app_state: &mut AppState) {
                     ui.child_window("StatusAndActions")
                         .size([0.0, 200.0]) // Increased height
                         .build(|| {
+                            if app_state.read_only {
+                                ui.text_colored([0.4, 0.7, 1.0, 1.0], "INSPECT MODE (read-only): applying, reverting, editing and saving are disabled.");
+                            }
                             ui.text(format!("Detected Version: {}", app_state.detected_version));
+                            if app_state.dirty {
+                                ui.same_line();
+                                ui.text_colored([1.0, 0.7, 0.1, 1.0], "* Unsaved changes");
+                            }
+                            render_patch_set_choice(ui, app_state);
+                            render_version_override(ui, app_state);
+                            if let Some(mut preview) = version_region_preview(&app_state.version_probes) {
+                                ui.input_text("Version Bytes", &mut preview).read_only(true).build();
+                                if ui.is_item_hovered() {
+                                    ui.tooltip_text(
+                                        "The raw bytes where the version string is expected.\n\
+                                         Copy them into a support request for an unsupported version.",
+                                    );
+                                }
+                            }
+                            if let Some(hash) = &app_state.file_sha256 {
+                                ui.text(format!("SHA-256: {}", hash));
+                            }
+                            if app_state.base_offset != 0 {
+                                ui.text_colored(
+                                    [1.0, 0.7, 0.1, 1.0],
+                                    format!(
+                                        "Header: {:#X} bytes (patch offsets shifted, header kept when saving)",
+                                        app_state.base_offset
+                                    ),
+                                );
+                            }
+                            if let Some(data) = &app_state.file_data {
+                                match app_state.patch_set.and_then(|set| set.expected_size) {
+                                    Some(expected) if expected != data.len() => ui.text_colored(
+                                        [1.0, 0.7, 0.1, 1.0],
+                                        format!("File Size: {} bytes (expected {} bytes)", data.len(), expected),
+                                    ),
+                                    Some(expected) => ui.text(format!("File Size: {} bytes (expected {} bytes)", data.len(), expected)),
+                                    None => ui.text(format!("File Size: {} bytes", data.len())),
+                                }
+                            }
                             ui.text("Patch Status (click to view diff in Hex Viewer tab):");
 
-                            let (jump, code, dtc) = app_state.patch_status;
-                            let green = [0.1, 0.9, 0.1, 1.0];
-                            let red = [0.9, 0.1, 0.1, 1.0];
-                            let grey = [0.5, 0.5, 0.5, 1.0];
-
-                            let (jump_char, jump_color) = match jump {
-                                PatchStatus::Patched => ('✓', green),
-                                PatchStatus::Unpatched => ('✗', grey),
-                                PatchStatus::Unknown => ('?', red),
-                            };
-                            let (code_char, code_color) = match code {
-                                PatchStatus::Patched => ('✓', green),
-                                PatchStatus::Unpatched => ('✗', grey),
-                                PatchStatus::Unknown => ('?', red),
-                            };
-                            let (dtc_char, dtc_color) = match dtc {
-                                PatchStatus::Patched => ('✓', green),
-                                PatchStatus::Unpatched => ('✗', grey),
-                                PatchStatus::Unknown => ('?', red),
-                            };
-
-                            let _jump_color = ui.push_style_color(imgui::StyleColor::Text, jump_color);
-                            if ui.selectable_config(format!("  {} Jump Patch", jump_char))
-                                .selected(app_state.selected_patch_index == Some(0))
-                                .build() {
-                                app_state.selected_patch_index = Some(0);
+                            let statuses: Vec<PatchStatus> = app_state.patch_status.iter().map(|&(_, status)| status).collect();
+                            if statuses.is_empty() {
+                                ui.text_disabled("  No file loaded.");
                             }
-                            
-                            let _code_color = ui.push_style_color(imgui::StyleColor::Text, code_color);
-                            if ui.selectable_config(format!("  {} Code Patch", code_char))
-                                .selected(app_state.selected_patch_index == Some(1))
-                                .build() {
-                                app_state.selected_patch_index = Some(1);
+                            for (index, (name, status)) in app_state.patch_status.clone().into_iter().enumerate() {
+                                let status_char = match status {
+                                    PatchStatus::Patched => '✓',
+                                    PatchStatus::Unpatched => '✗',
+                                    PatchStatus::Unknown => '?',
+                                };
+                                {
+                                    let _color = ui.push_style_color(imgui::StyleColor::Text, status_color(status));
+                                    let suffix = unknown_suffix(app_state, index, status);
+                                    let label = format!("  {} {} Patch{}##patch{}", status_char, name, suffix, index);
+                                    if ui.selectable_config(label).selected(app_state.selected_patch_index == Some(index)).build() {
+                                        app_state.selected_patch_index = Some(index);
+                                    }
+                                }
+                                patch_status_tooltip(ui, app_state, index, status);
+                                render_risk_badge(ui, app_state, index);
                             }
 
-                            let _dtc_color = ui.push_style_color(imgui::StyleColor::Text, dtc_color);
-                            if ui.selectable_config(format!("  {} DTC Patch", dtc_char))
-                                .selected(app_state.selected_patch_index == Some(2))
-                                .build() {
-                                app_state.selected_patch_index = Some(2);
-                            }
+                            render_patch_map(ui, app_state);
 
                             ui.spacing();
                             ui.separator();
                             ui.spacing();
 
-                            let can_apply = matches!(app_state.patch_status, (PatchStatus::Unpatched, PatchStatus::Unpatched, PatchStatus::Unpatched));
-                            let can_revert = matches!(app_state.patch_status, (PatchStatus::Patched, PatchStatus::Patched, PatchStatus::Patched));
+                            let writable = !app_state.read_only;
+                            // No statuses means no file is loaded.
+                            let can_apply = writable && !statuses.is_empty() && if app_state.force_apply {
+                                app_state.file_data.is_some() && !statuses.iter().all(|s| *s == PatchStatus::Patched)
+                            } else {
+                                statuses.iter().all(|s| *s == PatchStatus::Unpatched)
+                            };
+                            let fully_patched = !statuses.is_empty() && statuses.iter().all(|s| *s == PatchStatus::Patched);
+                            // A mixed Patched/Unpatched state can still be reverted patch-by-patch.
+                            let can_revert = writable && statuses.contains(&PatchStatus::Patched) && !statuses.contains(&PatchStatus::Unknown);
 
                             let button_size = [120.0, 30.0];
                             let content_width = ui.content_region_avail()[0];
-                            let buttons_total_width = button_size[0] * 2.0 + unsafe { ui.style() }.item_spacing[0];
+                            let buttons_total_width = button_size[0] * 3.0 + unsafe { ui.style() }.item_spacing[0] * 2.0;
                             let cursor_x = (content_width - buttons_total_width) * 0.5;
                             if cursor_x > 0.0 {
                                 ui.set_cursor_pos([cursor_x, ui.cursor_pos()[1]]);
                             }
 
+                            handle_patch_shortcuts(ui, app_state, can_apply, can_revert, fully_patched);
                             ui.disabled(!can_apply, || {
                                 if ui.button_with_size("Apply Patches", button_size) {
-                                    if let (Some(data), Some(patch_set)) = (app_state.file_data.as_mut(), app_state.patch_set) {
-                                        match patcher::apply_patches(data, patch_set) {
-                                            Ok(logs) => {
-                                                app_state.log.push("Success: Patches applied.".to_string());
-                                                app_state.log.extend(logs);
-                                                if let Some(save_path) = rfd::FileDialog::new().set_file_name("patched_firmware.bin").save_file() {
-                                                    match fs::write(&save_path, &*data) {
-                                                        Ok(()) => {
-                                                            app_state.log.push(format!("Success: Patched file saved to {}", save_path.display()));
-                                                            app_state.patch_status = (PatchStatus::Patched, PatchStatus::Patched, PatchStatus::Patched);
-                                                        }
-                                                        Err(e) => app_state.log.push(format!("Error: Failed to save file: {}", e)),
-                                                    }
-                                                } else {
-                                                    app_state.log.push("Save operation cancelled.".to_string());
-                                                }
-                                            }
-                                            Err(e) => app_state.log.push(format!("Error applying patches: {}", e)),
-                                        }
-                                    }
+                                    request_apply(ui, app_state, None);
                                 }
                             });
+                            if ui.is_item_hovered_with_flags(imgui::ItemHoveredFlags::ALLOW_WHEN_DISABLED) {
+                                ui.tooltip_text("Applies all patches and asks where to save the result (Ctrl+P)");
+                            }
+                            render_high_risk_confirmation(ui, app_state);
+                            ui.same_line();
+                            ui.disabled(app_state.patch_set.is_none() || app_state.file_data.is_none(), || {
+                                if ui.button_with_size("Preview", button_size) {
+                                    preview_patches(app_state);
+                                }
+                            });
+                            if ui.is_item_hovered() {
+                                ui.tooltip_text("Logs which bytes applying the patches would change, without modifying anything.");
+                            }
                             ui.same_line();
                             ui.disabled(!can_revert, || {
                                 if ui.button_with_size("Revert", button_size) {
-                                    if let (Some(data), Some(patch_set)) = (app_state.file_data.as_mut(), app_state.patch_set) {
-                                        match patcher::revert_patches(data, patch_set) {
-                                            Ok(logs) => {
-                                                app_state.log.push("Success: Patches reverted.".to_string());
-                                                app_state.log.extend(logs);
-                                                if let Some(save_path) = rfd::FileDialog::new().set_file_name("reverted_firmware.bin").save_file() {
-                                                    match fs::write(&save_path, &*data) {
-                                                        Ok(()) => {
-                                                            app_state.log.push(format!("Success: Reverted file saved to {}", save_path.display()));
-                                                            app_state.patch_status = (PatchStatus::Unpatched, PatchStatus::Unpatched, PatchStatus::Unpatched);
-                                                        }
-                                                        Err(e) => app_state.log.push(format!("Error: Failed to save file: {}", e)),
-                                                    }
-                                                } else {
-                                                    app_state.log.push("Save operation cancelled.".to_string());
-                                                }
-                                            }
-                                            Err(e) => app_state.log.push(format!("Error reverting patches: {}", e)),
+                                    revert_and_save(app_state, fully_patched);
+                                }
+                            });
+                            if ui.is_item_hovered_with_flags(imgui::ItemHoveredFlags::ALLOW_WHEN_DISABLED) {
+                                ui.tooltip_text("Restores the original bytes and asks where to save the result (Ctrl+R)");
+                            }
+                            if statuses.contains(&PatchStatus::Unknown) {
+                                if let Some(backup_path) = restorable_backup(app_state).map(Path::to_path_buf) {
+                                    if cursor_x > 0.0 {
+                                        ui.set_cursor_pos([cursor_x, ui.cursor_pos()[1]]);
+                                    }
+                                    ui.disabled(!writable, || {
+                                        if ui.button_with_size("Restore Backup", [buttons_total_width, 0.0]) {
+                                            restore_backup_and_save(app_state);
                                         }
+                                    });
+                                    if ui.is_item_hovered_with_flags(imgui::ItemHoveredFlags::ALLOW_WHEN_DISABLED) {
+                                        ui.tooltip_text(format!(
+                                            "The patch bytes match neither state, so they cannot be reverted.\n\
+                                             Replaces the whole file with {} and asks where to save the result.",
+                                            backup_path.display()
+                                        ));
                                     }
                                 }
+                            }
+                            if let Some((verb, done, total)) = app_state.patch_progress.filter(|&(_, _, total)| total > 0) {
+                                if cursor_x > 0.0 {
+                                    ui.set_cursor_pos([cursor_x, ui.cursor_pos()[1]]);
+                                }
+                                ProgressBar::new(done as f32 / total as f32)
+                                    .overlay_text(format!("{} {} of {} patches", verb, done, total))
+                                    .size([buttons_total_width, 0.0])
+                                    .build(ui);
+                            }
+
+                            ui.checkbox("Force apply (skip validation)", &mut app_state.force_apply);
+                            if ui.is_item_hovered() {
+                                ui.tooltip_text(
+                                    "Writes the patched bytes even where the file does not contain the expected original bytes.\n\
+                                     Only use this if you know why the bytes differ. Forcing a patch onto the wrong firmware can brick the ECU.",
+                                );
+                            }
+                            ui.same_line();
+                            ui.checkbox("Back up original", &mut app_state.config.create_backup);
+                            if ui.is_item_hovered() {
+                                ui.tooltip_text("Copies the loaded file to <name>.bak.<timestamp> next to it before the first save.");
+                            }
+                            ui.same_line();
+                            let has_regions = app_state.patch_set.is_some_and(|set| !set.zeroize_regions.is_empty());
+                            ui.disabled(!has_regions, || {
+                                ui.checkbox("Clear VIN/immobilizer data", &mut app_state.zeroize);
+                            });
+                            if ui.is_item_hovered_with_flags(imgui::ItemHoveredFlags::ALLOW_WHEN_DISABLED) {
+                                ui.tooltip_text(if has_regions {
+                                    "When applying, also fills the VIN/immobilizer regions of this version with 00."
+                                } else {
+                                    "No VIN/immobilizer regions are defined for this version."
+                                });
+                            }
+
+                            ui.disabled(app_state.file_data.is_none(), || {
+                                if ui.button("Diagnose Checksum") {
+                                    diagnose_checksum(app_state);
+                                }
+                                ui.same_line();
+                                if ui.button("Export Report") {
+                                    export_report(app_state);
+                                }
+                            });
+                            ui.same_line();
+                            ui.disabled(app_state.last_saved_path.is_none(), || {
+                                if ui.button("Save Report") {
+                                    save_report_next_to_output(app_state);
+                                }
+                            });
+                            if ui.is_item_hovered() {
+                                match app_state.last_saved_path.as_deref() {
+                                    Some(saved) => ui.tooltip_text(format!("Writes the report to {}", report_path_for(saved).display())),
+                                    None => ui.tooltip_text("Save a patched file first; the report is written next to it."),
+                                }
+                            }
+                            ui.disabled(!has_patched_data(app_state), || {
+                                if ui.button("Export Delta Patch") {
+                                    export_delta(app_state);
+                                }
+                            });
+                            ui.same_line();
+                            ui.disabled(app_state.file_data.is_none() || !writable, || {
+                                if ui.button("Apply Delta Patch...") {
+                                    apply_delta_file(app_state);
+                                }
+                            });
+                            ui.same_line();
+                            ui.disabled(!can_save(app_state) || !writable, || {
+                                if ui.button("Save As...") {
+                                    save_as(app_state);
+                                }
                             });
+                            if ui.is_item_hovered() {
+                                ui.tooltip_text("Saves the in-memory data, including bytes edited by hand, to a new file (Ctrl+S).");
+                            }
+                            ui.same_line();
+                            ui.disabled(app_state.undo_history.is_empty(), || {
+                                if ui.button(format!("Undo ({})", app_state.undo_history.len())) {
+                                    undo(app_state);
+                                }
+                            });
+                            if ui.is_item_hovered() {
+                                ui.tooltip_text("Restores the data from before the last apply, revert or byte edit (Ctrl+Z).");
+                            }
+                            ui.same_line();
+                            ui.disabled(!has_unsaved_edits(app_state), || {
+                                if ui.button("Discard Changes") {
+                                    discard_changes(app_state);
+                                }
+                            });
+                            ui.disabled(app_state.patch_set.is_none(), || {
+                                if ui.button("Export Hex Dump") {
+                                    export_hex_dump(app_state);
+                                }
+                            });
+                            ui.same_line();
+                            ui.checkbox("Full dump", &mut app_state.full_hex_dump);
+                            if ui.is_item_hovered() {
+                                ui.tooltip_text("Export every row instead of only the changed regions with context.");
+                            }
+                            ui.same_line();
+                            ui.disabled(app_state.patch_set.is_none(), || {
+                                if ui.button("Export PatchSet...") {
+                                    export_patch_set(app_state);
+                                }
+                            });
+                            if ui.is_item_hovered() {
+                                ui.tooltip_text("Saves the patch set definition as TOML or JSON, in the format loaded from patch_sets.toml.");
+                            }
                         });
 
                     // Bottom section for logs
@@ -232,18 +2049,19 @@ pub fn render_main_window(ui: &mut Ui, app_state: &mut AppState) {
                         .border(true)
                         .build(|| {
                             ui.text("Log");
+                            // Only offered once something was saved, so it always has a folder to open.
+                            if let Some(path) = app_state.last_saved_path.clone() {
+                                ui.same_line();
+                                if ui.small_button("Open Output Folder") {
+                                    reveal_saved_file(app_state);
+                                }
+                                if ui.is_item_hovered() {
+                                    ui.tooltip_text(format!("Show {} in the file manager", path.display()));
+                                }
+                            }
                             ui.separator();
                             let _log_rounding = ui.push_style_var(StyleVar::FrameRounding(4.0));
-                            ui.child_window("LogContent")
-                                .build(|| {
-                                    for message in &app_state.log {
-                                        let color = log_color(message);
-                                        ui.text_colored(color, message);
-                                    }
-                                    if ui.cursor_pos()[1] > ui.window_content_region_max()[1] {
-                                        ui.set_scroll_here_y_with_ratio(1.0);
-                                    }
-                                });
+                            render_log_panel(ui, &app_state.log, &mut app_state.log_filter);
                         });
                 });
 
@@ -252,25 +2070,43 @@ pub fn render_main_window(ui: &mut Ui, app_state: &mut AppState) {
                     ui.child_window("HexViewerContent")
                         .size([0.0, 0.0])
                         .build(|| {
+                            render_version_details(ui, app_state);
+                            ui.spacing();
+
                             if let (Some(patch_set), Some(index)) = (app_state.patch_set, app_state.selected_patch_index) {
                                 if let Some(patch) = patch_set.patches.get(index) {
-                                    ui.text(format!("Diff for '{}' at offset {:#X}", patch.name, patch.offset));
+                                    ui.text(format!(
+                                        "Diff for '{}' at offset {:#X} (address {:#X})",
+                                        patch.name,
+                                        patch.offset,
+                                        patch_set.offset_to_address(patch.offset)
+                                    ));
                                     ui.separator();
 
                                     let original_hex = bytes_to_hex_string(&patch.original);
                                     let patched_hex = bytes_to_hex_string(&patch.patched);
 
                                     ui.text("Original:");
-                                    let _red_text = ui.push_style_color(imgui::StyleColor::Text, [0.9, 0.2, 0.2, 1.0]);
-                                    ui.text_wrapped(&original_hex);
-                                    
+                                    ui.text_colored([0.9, 0.2, 0.2, 1.0], &original_hex);
 
                                     ui.spacing();
 
                                     ui.text("Patched:");
-                                    let _green_text = ui.push_style_color(imgui::StyleColor::Text, [0.2, 0.9, 0.2, 1.0]);
-                                    ui.text_wrapped(&patched_hex);
-                                    
+                                    ui.text_colored([0.2, 0.9, 0.2, 1.0], &patched_hex);
+
+                                    ui.spacing();
+                                    if ui.button("Copy") {
+                                        ui.set_clipboard_text(format_patch_diff(patch_set, patch));
+                                        app_state.log.push(LogEntry::info(format!(
+                                            "Copied the {} patch diff to the clipboard.",
+                                            patch.name
+                                        )));
+                                    }
+                                    if ui.is_item_hovered() {
+                                        ui.tooltip_text("Copies the patch name, offset and bytes, e.g. for a forum post.");
+                                    }
+                                    ui.same_line();
+                                    render_single_patch_actions(ui, app_state, index);
 
                                 } else {
                                     ui.text("No patch selected.");
@@ -278,9 +2114,366 @@ pub fn render_main_window(ui: &mut Ui, app_state: &mut AppState) {
                             } else {
                                 ui.text("Load a file and select a patch in the 'Patcher' tab to view differences.");
                             }
+
+                            if let Some(patch_set) = app_state.patch_set {
+                                ui.spacing();
+                                ui.separator();
+                                render_address_calculator(ui, app_state, patch_set);
+                            }
+
+                            ui.spacing();
+                            ui.separator();
+                            render_search_panel(ui, app_state);
+
+                            ui.spacing();
+                            ui.separator();
+                            render_hex_editor(ui, app_state);
                         });
                 });
+                TabItem::new("Compare").build(ui, || {
+                    let _style = ui.push_style_var(StyleVar::WindowPadding([10.0, 10.0]));
+                    ui.child_window("CompareContent").size([0.0, 0.0]).build(|| {
+                        render_compare_panel(ui, app_state);
+                    });
+                });
             });
         });
+
+    render_variant_choice(ui, app_state);
+    render_zip_choice(ui, app_state);
+    render_discard_confirmation(ui, app_state);
+    render_supported_versions(ui, app_state);
+    render_compare_sets(ui, &mut app_state.compare_sets);
+    render_batch_results(ui, &mut app_state.batch);
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_region_preview_shows_hex_and_ascii_even_for_short_files() {
+        let mut data = vec![0xFF; 0x70040];
+        data.extend_from_slice(b"ca439999\0\0\0\0\0\0\0\0");
+        assert_eq!(
+            version_region_preview(&version::probe_versions(&data)).unwrap(),
+            "0x70040: 63 61 34 33 39 39 39 39 00 00 00 00 00 00 00 00 | ca439999........"
+        );
+        assert_eq!(
+            version_region_preview(&version::probe_versions(&[0xFF; 16])).unwrap(),
+            "0x70040: (the file ends before this offset)"
+        );
+        assert!(version_region_preview(&[]).is_none());
+    }
+
+    #[test]
+    fn the_copied_patch_diff_names_the_patch_and_lists_both_byte_strings() {
+        let patch_set = patches::known_patch_sets().into_iter().find(|set| set.version_string == "ca430037").unwrap();
+        assert_eq!(
+            format_patch_diff(patch_set, &patch_set.patches[0]),
+            format!(
+                "ca430037 Jump patch\nOffset: 0x54E8C (address {:#X})\nOriginal: DA 0B 5A 1C\nPatched:  DA 0D 0C 35",
+                patch_set.offset_to_address(0x54E8C)
+            )
+        );
+    }
+
+    #[test]
+    fn default_save_name_is_built_from_the_source_file() {
+        assert_eq!(
+            default_save_name("/cars/E46 330i/dump.bin", "EWSdelete", Some("ca430037"), "ewsdelta"),
+            "dump_EWSdelete_ca430037.ewsdelta"
+        );
+        assert_eq!(default_save_name("C:\\flash\\read", "hexdump", Some("ca430069"), "txt"), "read_hexdump_ca430069.txt");
+        assert_eq!(default_save_name("my dump #2.BIN", "hexdump", None, "txt"), "my_dump__2_hexdump.txt");
+        // Only the last extension is dropped.
+        assert_eq!(default_save_name("/cars/dump.2024.01.bin", "hexdump", None, "txt"), "dump.2024.01_hexdump.txt");
+        assert_eq!(default_save_name("", "EWSdelete", Some("ca430037"), "ewsdelta"), "firmware_EWSdelete_ca430037.ewsdelta");
+    }
+
+    #[test]
+    fn failed_load_leaves_no_stale_state_behind() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut app_state = AppState {
+            file_path: "old.bin".to_string(),
+            file_data: Some(vec![0x00; 4]),
+            detected_version: "ca430037".to_string(),
+            pending_load: Some(receiver),
+            ..AppState::default()
+        };
+        sender
+            .send(LoadResult {
+                path: PathBuf::from("locked.bin"),
+                log: vec![LogEntry::error("Error: Failed to read file")],
+                file: None,
+                probes: Vec::new(),
+                variant_choice: None,
+                zip_choice: None,
+                zip_entry: None,
+                error: None,
+            })
+            .unwrap();
+
+        poll_pending_load(&mut app_state);
+        assert!(app_state.pending_load.is_none());
+        assert!(app_state.file_path.is_empty());
+        assert!(app_state.file_data.is_none());
+        assert_eq!(app_state.detected_version, "N/A");
+        assert_eq!(app_state.log.last().map(|entry| entry.message.as_str()), Some("Error: Failed to read file"));
+    }
+
+    #[test]
+    fn undo_restores_the_data_and_status_and_keeps_a_bounded_history() {
+        let patch_set = patches::known_patch_sets().into_iter().find(|set| set.version_string == "ca430037").unwrap();
+        let mut data = vec![0xFF; 0x80000];
+        for patch in &patch_set.patches {
+            data[patch.offset..patch.offset + patch.original.len()].copy_from_slice(&patch.original);
+        }
+        let mut patched = data.clone();
+        patcher::apply_patches(&mut patched, patch_set).unwrap();
+        let mut app_state = AppState {
+            patch_set: Some(patch_set),
+            patch_status: check_all_statuses(&patched, patch_set),
+            file_data: Some(patched),
+            ..AppState::default()
+        };
+
+        for _ in 0..UNDO_HISTORY_LIMIT {
+            push_undo_snapshot(&mut app_state, vec![0x00; 4]);
+        }
+        push_undo_snapshot(&mut app_state, data.clone());
+        assert_eq!(app_state.undo_history.len(), UNDO_HISTORY_LIMIT);
+        assert!(app_state.log.last().unwrap().message.contains("oldest undo step was dropped"));
+
+        undo(&mut app_state);
+        assert_eq!(app_state.file_data.as_ref(), Some(&data));
+        assert_eq!(app_state.patch_status, check_all_statuses(&data, patch_set));
+        assert!(app_state.patch_status.iter().all(|(_, status)| *status == PatchStatus::Unpatched));
+        assert_eq!(app_state.undo_history.len(), UNDO_HISTORY_LIMIT - 1);
+    }
+
+    #[test]
+    fn a_hand_edit_is_undone_on_its_own() {
+        let patch_set = patches::known_patch_sets().into_iter().find(|set| set.version_string == "ca430037").unwrap();
+        let data = ewsms43::selftest::build_synthetic_image(patch_set).unwrap();
+        let mut patched = data.clone();
+        patcher::apply_patches(&mut patched, patch_set).unwrap();
+        let mut app_state = AppState {
+            patch_set: Some(patch_set),
+            file_data: Some(patched.clone()),
+            undo_history: vec![data],
+            ..AppState::default()
+        };
+
+        crate::gui::hex_editor::write_byte(&mut app_state, 0x10, "AB").unwrap();
+        crate::gui::hex_editor::write_byte(&mut app_state, 0x20, "CD").unwrap();
+        assert_eq!(app_state.undo_history.len(), 3);
+        undo(&mut app_state);
+        let data = app_state.file_data.as_ref().unwrap();
+        assert_eq!((data[0x10], data[0x20]), (0xAB, patched[0x20]));
+        assert_eq!(app_state.dirty_offsets, BTreeSet::from([0x10]));
+
+        // The patch operation before the edits is still there and undone only after them.
+        undo(&mut app_state);
+        assert_eq!(app_state.file_data.as_ref(), Some(&patched));
+        assert!(app_state.dirty_offsets.is_empty());
+        assert!(app_state.patch_status.iter().all(|(_, status)| *status == PatchStatus::Patched));
+        assert_eq!(app_state.undo_history.len(), 1);
+    }
+
+    #[test]
+    fn hand_edits_of_an_unpatched_file_can_be_saved() {
+        let patch_set = patches::known_patch_sets().into_iter().find(|set| set.version_string == "ca430037").unwrap();
+        let data = ewsms43::selftest::build_synthetic_image(patch_set).unwrap();
+        let mut app_state = AppState {
+            patch_set: Some(patch_set),
+            file_data: Some(data),
+            config: Config { create_backup: false, ..Config::default() },
+            ..AppState::default()
+        };
+        assert!(!can_save(&app_state));
+
+        crate::gui::hex_editor::write_byte(&mut app_state, 0x10, "AB").unwrap();
+        assert!(!has_patched_data(&app_state));
+        assert!(can_save(&app_state));
+
+        let path = std::env::temp_dir().join(format!("ewsms43-hand-edit-{}.bin", std::process::id()));
+        let edited = app_state.file_data.clone().unwrap();
+        let written = write_saved_data(&mut app_state, Some(path.clone()), &edited, "Edited");
+        let saved = fs::read(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(written);
+        assert_eq!(saved.unwrap()[0x10], 0xAB);
+        assert!(!can_save(&app_state));
+    }
+
+    #[test]
+    fn in_memory_changes_mark_the_data_dirty_and_loading_asks_first() {
+        let mut app_state = AppState {
+            file_data: Some(vec![0x00; 4]),
+            loaded_data: Some(vec![0x00; 4]),
+            ..AppState::default()
+        };
+        assert!(!ask_before_discarding(&mut app_state, DiscardAction::Browse));
+        assert_eq!(app_state.pending_discard, None);
+
+        crate::gui::hex_editor::write_byte(&mut app_state, 1, "AB").unwrap();
+        assert!(app_state.dirty);
+        assert!(ask_before_discarding(&mut app_state, DiscardAction::Close));
+        assert_eq!(app_state.pending_discard, Some(DiscardAction::Close));
+
+        discard_changes(&mut app_state);
+        assert!(!app_state.dirty);
+        push_undo_snapshot(&mut app_state, vec![0x01; 4]);
+        undo(&mut app_state);
+        assert!(app_state.dirty);
+        reset_state(&mut app_state);
+        assert!(!app_state.dirty);
+    }
+
+    #[test]
+    fn safe_mode_logs_every_failed_precondition_and_backs_up_first() {
+        let patch_set = patches::known_patch_sets().into_iter().find(|set| set.version_string == "ca430037").unwrap();
+        let data = ewsms43::selftest::build_synthetic_image(patch_set).unwrap();
+        let mut patched = data.clone();
+        patcher::apply_patches(&mut patched, patch_set).unwrap();
+        let mut app_state = AppState {
+            safe_mode: true,
+            patch_set: Some(patch_set),
+            patch_set_candidates: vec![patch_set, patch_set],
+            patch_status: check_all_statuses(&patched, patch_set),
+            config: Config { create_backup: false, ..Config::default() },
+            ..AppState::default()
+        };
+        assert!(refuse_if_unsafe(&mut app_state));
+        let refusals: Vec<&str> = app_state.log.iter().skip(1).map(|entry| entry.message.as_str()).collect();
+        assert_eq!(refusals.len(), 3, "{:?}", refusals);
+        assert!(refusals.iter().all(|message| message.starts_with("Error: Safe mode refused")));
+
+        let dir = std::env::temp_dir().join(format!("ewsms43-safe-mode-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("dump.bin");
+        fs::write(&source, &data).unwrap();
+        app_state.file_path = source.display().to_string();
+        app_state.patch_set_candidates = vec![patch_set];
+        app_state.patch_status = check_all_statuses(&data, patch_set);
+        app_state.config.create_backup = true;
+        let refused = refuse_if_unsafe(&mut app_state);
+        let backed_up = app_state.backup_path.as_ref().is_some_and(|path| path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!refused);
+        assert!(backed_up);
+
+        app_state.safe_mode = false;
+        app_state.patch_set_candidates.clear();
+        assert!(!refuse_if_unsafe(&mut app_state));
+    }
+
+    #[test]
+    fn patched_data_is_only_adopted_once_it_was_saved() {
+        let patch_set = patches::known_patch_sets().into_iter().find(|set| set.version_string == "ca430037").unwrap();
+        let data = ewsms43::selftest::build_synthetic_image(patch_set).unwrap();
+        let mut patched = data.clone();
+        patcher::apply_patches(&mut patched, patch_set).unwrap();
+        let mut app_state = AppState {
+            patch_set: Some(patch_set),
+            patch_status: check_all_statuses(&data, patch_set),
+            file_data: Some(data.clone()),
+            config: Config { create_backup: false, ..Config::default() },
+            ..AppState::default()
+        };
+
+        // A cancelled save dialog leaves the data, the status and the undo history alone.
+        assert!(!adopt_if_saved(&mut app_state, patched.clone(), None, "Patched"));
+        assert_eq!(app_state.file_data.as_ref(), Some(&data));
+        assert_eq!(app_state.patch_status, check_all_statuses(&data, patch_set));
+        assert!(app_state.undo_history.is_empty());
+        assert!(app_state.log.iter().any(|entry| entry.message == "Save operation cancelled."));
+
+        let path = std::env::temp_dir().join(format!("ewsms43-adopt-{}.bin", std::process::id()));
+        let saved = adopt_if_saved(&mut app_state, patched.clone(), Some(path.clone()), "Patched");
+        let written = fs::read(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(saved);
+        assert_eq!(written.unwrap(), patched);
+        assert_eq!(app_state.file_data.as_ref(), Some(&patched));
+        assert!(app_state.patch_status.iter().all(|(_, status)| *status == PatchStatus::Patched));
+        assert_eq!(app_state.undo_history, [data]);
+    }
+
+    #[test]
+    fn a_zip_loaded_session_restores_the_loaded_file_from_the_backed_up_archive() {
+        use std::io::Write;
+        let patch_set = patches::known_patch_sets().into_iter().find(|set| set.version_string == "ca430037").unwrap();
+        let data = ewsms43::selftest::build_synthetic_image(patch_set).unwrap();
+        let dir = std::env::temp_dir().join(format!("ewsms43-zip-restore-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dumps.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        writer.start_file("car/dump.bin", zip::write::SimpleFileOptions::default()).unwrap();
+        writer.write_all(&data).unwrap();
+        writer.finish().unwrap();
+
+        let mut app_state = AppState { config: Config { create_backup: true, ..Config::default() }, ..AppState::default() };
+        adopt_load_result(&mut app_state, loader::load_file(&path));
+        let backed_up = ensure_backup(&mut app_state);
+        app_state.file_data.as_mut().unwrap()[0] ^= 0xFF;
+        let restored = read_backup(&mut app_state);
+        app_state.zip_entry = Some("missing.bin".to_string());
+        let refused = read_backup(&mut app_state);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(backed_up);
+        assert_eq!(restored, Some(data));
+        assert_eq!(refused, None);
+        assert!(app_state.log.last().unwrap().message.contains("has no file named 'missing.bin'"));
+    }
+
+    #[test]
+    fn a_picked_variant_keeps_the_header_of_the_dump() {
+        let set = patches::known_patch_sets().into_iter().find(|set| set.hardware_variant == Some("5WK90015")).unwrap();
+        let mut data = vec![0x5A; 0x200];
+        data.extend(ewsms43::selftest::build_synthetic_image(set).unwrap());
+        // Erase the hardware variant, so the user has to pick it.
+        data[0x200 + 0x70050..0x200 + 0x70058].fill(0xFF);
+        let path = std::env::temp_dir().join(format!("ewsms43-variant-header-{}.bin", std::process::id()));
+        fs::write(&path, &data).unwrap();
+        let result = loader::load_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        let mut app_state = AppState { variant_choice: result.variant_choice, ..AppState::default() };
+        assert_eq!(app_state.variant_choice.as_ref().map(|choice| choice.base_offset), Some(0x200));
+        pick_variant(&mut app_state, 0);
+        assert_eq!(app_state.base_offset, 0x200);
+        assert_eq!(app_state.patch_set.unwrap().patches[0].offset, set.patches[0].offset + 0x200);
+        assert!(app_state.patch_status.iter().all(|(_, status)| *status == PatchStatus::Unpatched), "{:?}", app_state.patch_status);
+        assert!(!is_manual_override(&app_state));
+    }
+
+    #[test]
+    fn the_version_change_warning_ignores_a_manual_override() {
+        let sets = patches::known_patch_sets();
+        let set = sets.iter().copied().find(|set| set.version_string == "ca430037").unwrap();
+        let override_set = sets.iter().copied().find(|set| set.version_string == "ca430066").unwrap();
+        let data = ewsms43::selftest::build_synthetic_image(set).unwrap();
+        let mut patched = data.clone();
+        patcher::apply_patches(&mut patched, set).unwrap();
+
+        // The active set would claim a version change although the version string is untouched.
+        assert!(patcher::version_change_warning(override_set, &patched).is_some());
+        assert_eq!(version_change_after_patching(&data, &patched), None);
+        patched[0x70040..0x70048].copy_from_slice(b"ca430066");
+        assert!(version_change_after_patching(&data, &patched).unwrap().contains("changed from ca430037 to ca430066"));
+    }
+
+    #[test]
+    fn a_patch_set_outside_the_detected_candidates_is_a_manual_override() {
+        let sets = patches::known_patch_sets();
+        let mut app_state = AppState { patch_set: Some(sets[0]), patch_set_candidates: vec![sets[0]], ..AppState::default() };
+        assert!(!is_manual_override(&app_state));
+        app_state.patch_set = Some(sets[1]);
+        assert!(is_manual_override(&app_state));
+        app_state.patch_set = None;
+        assert!(!is_manual_override(&app_state));
+    }
+}
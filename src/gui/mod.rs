@@ -1 +1,11 @@
+pub mod batch_window;
+pub mod clipboard;
+pub mod compare_panel;
+pub mod compare_sets;
+pub mod hex_editor;
+pub mod loader;
+pub mod log_panel;
 pub mod main_window;
+pub mod reveal;
+pub mod search_panel;
+pub mod theme;
@@ -0,0 +1,64 @@
+//! This module opens files and folders in the operating system's file manager.
+
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Returns the command that opens a folder in the file manager on this platform.
+fn folder_command(dir: &Path) -> Command {
+    #[cfg(target_os = "windows")]
+    let program = "explorer";
+    #[cfg(target_os = "macos")]
+    let program = "open";
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let program = "xdg-open";
+
+    let mut command = Command::new(program);
+    command.arg(dir);
+    command
+}
+
+/// Returns the command that shows a file selected in the file manager on this platform.
+///
+/// Linux file managers have no common "select" option, so the containing folder is opened instead.
+fn reveal_command(path: &Path) -> Command {
+    #[cfg(target_os = "windows")]
+    {
+        let mut command = Command::new("explorer");
+        command.arg(format!("/select,{}", path.display()));
+        command
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(path);
+        command
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    {
+        folder_command(path.parent().unwrap_or(path))
+    }
+}
+
+/// Starts `command` without waiting for it, turning a missing opener into a readable error.
+fn spawn(mut command: Command) -> Result<(), String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    match command.spawn() {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Err(format!("The file manager opener '{}' is not available on this system.", program))
+        }
+        Err(e) => Err(format!("Failed to run '{}': {}", program, e)),
+    }
+}
+
+/// Opens the folder containing `path` in the file manager.
+pub fn open_containing_folder(path: &Path) -> Result<(), String> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    spawn(folder_command(dir))
+}
+
+/// Opens the file manager with `path` selected, where the platform supports it.
+pub fn reveal_file(path: &Path) -> Result<(), String> {
+    spawn(reveal_command(path))
+}
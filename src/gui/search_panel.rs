@@ -0,0 +1,193 @@
+//! This module renders the byte-pattern search of the hex viewer. Searches run on a worker thread and
+//! report matches incrementally, so the first hits can be inspected before the scan finishes.
+
+use crate::gui::log_panel::LogEntry;
+use crate::gui::main_window::AppState;
+use ewsms43::search::{self, Pattern};
+use imgui::{ListClipper, ProgressBar, Ui};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+/// Progress messages sent by the search worker.
+enum SearchMessage {
+    Chunk { matches: Vec<usize>, scanned: usize },
+    Finished { cancelled: bool },
+}
+
+/// A search running on a worker thread.
+struct SearchJob {
+    receiver: Receiver<SearchMessage>,
+    cancel: Arc<AtomicBool>,
+    total: usize,
+}
+
+/// UI state of the byte search.
+#[derive(Default)]
+pub struct SearchState {
+    /// The pattern as typed, e.g. `DA 0B ?? 5A`.
+    pub query: String,
+    /// Offsets of the matches found so far, in ascending order.
+    pub results: Vec<usize>,
+    /// Index into `results` of the match shown in the hex view.
+    pub current: Option<usize>,
+    /// Length of the pattern the results belong to.
+    pub pattern_len: usize,
+    /// Fraction of the file scanned by the running search.
+    pub progress: f32,
+    job: Option<SearchJob>,
+}
+
+impl SearchState {
+    /// Returns true while a search is running.
+    pub fn is_running(&self) -> bool {
+        self.job.is_some()
+    }
+
+    /// Asks the running search to stop. Matches found so far are kept.
+    pub fn cancel(&self) {
+        if let Some(job) = &self.job {
+            job.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Starts searching a copy of `data` for `pattern`, replacing any running search.
+fn start_search(state: &mut SearchState, data: Vec<u8>, pattern: Pattern) {
+    state.cancel();
+    let (sender, receiver) = mpsc::channel();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let worker_cancel = Arc::clone(&cancel);
+    let total = data.len();
+    state.results.clear();
+    state.current = None;
+    state.pattern_len = pattern.len();
+    state.progress = 0.0;
+
+    thread::spawn(move || {
+        let completed = search::search_chunked(&data, &pattern, &worker_cancel, |matches, scanned| {
+            let _ = sender.send(SearchMessage::Chunk { matches: matches.to_vec(), scanned });
+        });
+        let _ = sender.send(SearchMessage::Finished { cancelled: !completed });
+    });
+    state.job = Some(SearchJob { receiver, cancel, total });
+}
+
+/// Shows the match at `index` in the hex view.
+fn show_match(app_state: &mut AppState, index: usize) {
+    let Some(&offset) = app_state.search.results.get(index) else {
+        return;
+    };
+    app_state.search.current = Some(index);
+    app_state.hex_editor.scroll_to = Some(offset);
+    app_state.hex_editor.highlight = Some((offset, app_state.search.pattern_len));
+}
+
+/// Collects the messages of the running search. Called once per frame.
+fn poll_search(app_state: &mut AppState) {
+    let Some(job) = &app_state.search.job else {
+        return;
+    };
+    let total = job.total.max(1);
+    let mut finished = None;
+    loop {
+        match job.receiver.try_recv() {
+            Ok(SearchMessage::Chunk { matches, scanned }) => {
+                app_state.search.results.extend(matches);
+                app_state.search.progress = scanned as f32 / total as f32;
+            }
+            Ok(SearchMessage::Finished { cancelled }) => {
+                finished = Some(cancelled);
+                break;
+            }
+            Err(TryRecvError::Empty) => break,
+            Err(TryRecvError::Disconnected) => {
+                finished = Some(true);
+                break;
+            }
+        }
+    }
+
+    // Jump to the first hit as soon as it arrives.
+    if app_state.search.current.is_none() && !app_state.search.results.is_empty() {
+        show_match(app_state, 0);
+    }
+    if let Some(cancelled) = finished {
+        app_state.search.job = None;
+        app_state.needs_redraw = true;
+        let count = app_state.search.results.len();
+        let query = app_state.search.query.trim();
+        app_state.log.push(LogEntry::info(if cancelled {
+            format!("Search for '{}' cancelled after {} match(es).", query, count)
+        } else {
+            format!("Search for '{}' found {} match(es).", query, count)
+        }));
+    }
+}
+
+/// Renders the search box, progress, navigation and the list of matches.
+pub fn render_search_panel(ui: &Ui, app_state: &mut AppState) {
+    poll_search(app_state);
+
+    ui.text("Search (hex bytes, ?? matches any byte)");
+    ui.set_next_item_width(240.0);
+    let submitted = ui
+        .input_text("##search_query", &mut app_state.search.query)
+        .hint("e.g. DA 0B ?? 5A")
+        .enter_returns_true(true)
+        .build();
+    ui.same_line();
+    let clicked = ui.button("Find All");
+    if (submitted || clicked) && !app_state.search.is_running() {
+        match (search::parse_pattern(&app_state.search.query), app_state.file_data.as_ref()) {
+            (Ok(pattern), Some(data)) => {
+                let data = data.clone();
+                start_search(&mut app_state.search, data, pattern);
+            }
+            (Err(e), _) => app_state.log.push(LogEntry::error(format!("Error: {}", e))),
+            (_, None) => app_state.log.push(LogEntry::error("Error: Load a file before searching.")),
+        }
+    }
+
+    if app_state.search.is_running() {
+        ui.same_line();
+        if ui.button("Cancel") {
+            app_state.search.cancel();
+        }
+        ProgressBar::new(app_state.search.progress)
+            .overlay_text(format!("{:.0}% - {} match(es)", app_state.search.progress * 100.0, app_state.search.results.len()))
+            .build(ui);
+    }
+
+    let count = app_state.search.results.len();
+    if count == 0 {
+        return;
+    }
+    let current = app_state.search.current.unwrap_or(0);
+    if ui.arrow_button("##prev_match", imgui::Direction::Left) {
+        show_match(app_state, (current + count - 1) % count);
+    }
+    ui.same_line();
+    if ui.arrow_button("##next_match", imgui::Direction::Right) {
+        show_match(app_state, (current + 1) % count);
+    }
+    ui.same_line();
+    ui.text(format!("Match {} of {}", current + 1, count));
+
+    ui.child_window("SearchResults").size([0.0, 80.0]).border(true).build(|| {
+        // Only the visible rows are submitted, since a short pattern can match hundreds of thousands of times.
+        let clipper = ListClipper::new(count as i32).begin(ui);
+        for index in clipper.iter() {
+            let index = index as usize;
+            let offset = app_state.search.results[index];
+            if ui
+                .selectable_config(format!("{:#08X}", offset))
+                .selected(app_state.search.current == Some(index))
+                .build()
+            {
+                show_match(app_state, index);
+            }
+        }
+    });
+}
@@ -0,0 +1,153 @@
+//! This module defines the dark and light color themes used by the GUI.
+
+use imgui::{Context, Style, StyleColor};
+use serde::{Deserialize, Serialize};
+
+/// The available color themes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl Theme {
+    /// Returns the color used to clear the window background behind the imgui windows.
+    pub fn clear_color(self) -> [f32; 4] {
+        match self {
+            Theme::Dark => [0.13, 0.14, 0.15, 1.0],
+            Theme::Light => [0.94, 0.94, 0.94, 1.0],
+        }
+    }
+}
+
+/// Applies the given theme to the imgui context.
+pub fn apply_theme(ctx: &mut Context, theme: Theme) {
+    match theme {
+        Theme::Dark => apply_dark_style(ctx),
+        Theme::Light => apply_light_style(ctx),
+    }
+}
+
+/// Sets the spacing and rounding shared by all themes.
+fn apply_layout(style: &mut Style) {
+    style.window_padding = [15.0, 15.0];
+    style.frame_padding = [8.0, 4.0];
+    style.item_spacing = [10.0, 8.0];
+    style.item_inner_spacing = [6.0, 6.0];
+    style.window_rounding = 8.0;
+    style.frame_rounding = 4.0;
+    style.child_rounding = 4.0;
+    style.grab_rounding = 4.0;
+    style.popup_rounding = 4.0;
+    style.scrollbar_rounding = 6.0;
+    style.tab_rounding = 4.0;
+}
+
+pub fn apply_dark_style(ctx: &mut Context) {
+    let style = ctx.style_mut();
+    apply_layout(style);
+
+    style.colors[StyleColor::Text as usize] = [0.90, 0.90, 0.90, 1.00];
+    style.colors[StyleColor::TextDisabled as usize] = [0.50, 0.50, 0.50, 1.00];
+    style.colors[StyleColor::WindowBg as usize] = [0.13, 0.14, 0.15, 1.00];
+    style.colors[StyleColor::ChildBg as usize] = [0.13, 0.14, 0.15, 1.00];
+    style.colors[StyleColor::PopupBg as usize] = [0.08, 0.08, 0.08, 0.94];
+    style.colors[StyleColor::Border as usize] = [0.43, 0.43, 0.50, 0.50];
+    style.colors[StyleColor::BorderShadow as usize] = [0.00, 0.00, 0.00, 0.00];
+    style.colors[StyleColor::FrameBg as usize] = [0.25, 0.25, 0.25, 0.54];
+    style.colors[StyleColor::FrameBgHovered as usize] = [0.38, 0.38, 0.38, 0.40];
+    style.colors[StyleColor::FrameBgActive as usize] = [0.42, 0.42, 0.42, 0.67];
+    style.colors[StyleColor::TitleBg as usize] = [0.04, 0.04, 0.04, 1.00];
+    style.colors[StyleColor::TitleBgActive as usize] = [0.16, 0.29, 0.48, 1.00];
+    style.colors[StyleColor::TitleBgCollapsed as usize] = [0.00, 0.00, 0.00, 0.51];
+    style.colors[StyleColor::MenuBarBg as usize] = [0.14, 0.14, 0.14, 1.00];
+    style.colors[StyleColor::ScrollbarBg as usize] = [0.02, 0.02, 0.02, 0.53];
+    style.colors[StyleColor::ScrollbarGrab as usize] = [0.31, 0.31, 0.31, 1.00];
+    style.colors[StyleColor::ScrollbarGrabHovered as usize] = [0.41, 0.41, 0.41, 1.00];
+    style.colors[StyleColor::ScrollbarGrabActive as usize] = [0.51, 0.51, 0.51, 1.00];
+    style.colors[StyleColor::CheckMark as usize] = [0.26, 0.59, 0.98, 1.00];
+    style.colors[StyleColor::SliderGrab as usize] = [0.24, 0.52, 0.88, 1.00];
+    style.colors[StyleColor::SliderGrabActive as usize] = [0.26, 0.59, 0.98, 1.00];
+    style.colors[StyleColor::Button as usize] = [0.26, 0.59, 0.98, 0.40];
+    style.colors[StyleColor::ButtonHovered as usize] = [0.26, 0.59, 0.98, 1.00];
+    style.colors[StyleColor::ButtonActive as usize] = [0.06, 0.53, 0.98, 1.00];
+    style.colors[StyleColor::Header as usize] = [0.26, 0.59, 0.98, 0.31];
+    style.colors[StyleColor::HeaderHovered as usize] = [0.26, 0.59, 0.98, 0.80];
+    style.colors[StyleColor::HeaderActive as usize] = [0.26, 0.59, 0.98, 1.00];
+    style.colors[StyleColor::Separator as usize] = style.colors[StyleColor::Border as usize];
+    style.colors[StyleColor::SeparatorHovered as usize] = [0.10, 0.40, 0.75, 0.78];
+    style.colors[StyleColor::SeparatorActive as usize] = [0.10, 0.40, 0.75, 1.00];
+    style.colors[StyleColor::ResizeGrip as usize] = [0.26, 0.59, 0.98, 0.25];
+    style.colors[StyleColor::ResizeGripHovered as usize] = [0.26, 0.59, 0.98, 0.67];
+    style.colors[StyleColor::ResizeGripActive as usize] = [0.26, 0.59, 0.98, 0.95];
+    style.colors[StyleColor::Tab as usize] = style.colors[StyleColor::Header as usize];
+    style.colors[StyleColor::TabHovered as usize] = style.colors[StyleColor::HeaderHovered as usize];
+    style.colors[StyleColor::TabActive as usize] = style.colors[StyleColor::HeaderActive as usize];
+    style.colors[StyleColor::TabUnfocused as usize] = style.colors[StyleColor::Tab as usize];
+    style.colors[StyleColor::TabUnfocusedActive as usize] = style.colors[StyleColor::TabActive as usize];
+    style.colors[StyleColor::PlotLines as usize] = [0.61, 0.61, 0.61, 1.00];
+    style.colors[StyleColor::PlotLinesHovered as usize] = [1.00, 0.43, 0.35, 1.00];
+    style.colors[StyleColor::PlotHistogram as usize] = [0.90, 0.70, 0.00, 1.00];
+    style.colors[StyleColor::PlotHistogramHovered as usize] = [1.00, 0.60, 0.00, 1.00];
+    style.colors[StyleColor::TextSelectedBg as usize] = [0.26, 0.59, 0.98, 0.35];
+    style.colors[StyleColor::DragDropTarget as usize] = [1.00, 1.00, 0.00, 0.90];
+    style.colors[StyleColor::NavHighlight as usize] = [0.26, 0.59, 0.98, 1.00];
+    style.colors[StyleColor::NavWindowingHighlight as usize] = [1.00, 1.00, 1.00, 0.70];
+    style.colors[StyleColor::NavWindowingDimBg as usize] = [0.80, 0.80, 0.80, 0.20];
+    style.colors[StyleColor::ModalWindowDimBg as usize] = [0.80, 0.80, 0.80, 0.35];
+}
+
+pub fn apply_light_style(ctx: &mut Context) {
+    let style = ctx.style_mut();
+    apply_layout(style);
+
+    style.colors[StyleColor::Text as usize] = [0.00, 0.00, 0.00, 1.00];
+    style.colors[StyleColor::TextDisabled as usize] = [0.60, 0.60, 0.60, 1.00];
+    style.colors[StyleColor::WindowBg as usize] = [0.94, 0.94, 0.94, 1.00];
+    style.colors[StyleColor::ChildBg as usize] = [0.94, 0.94, 0.94, 1.00];
+    style.colors[StyleColor::PopupBg as usize] = [1.00, 1.00, 1.00, 0.98];
+    style.colors[StyleColor::Border as usize] = [0.00, 0.00, 0.00, 0.30];
+    style.colors[StyleColor::BorderShadow as usize] = [0.00, 0.00, 0.00, 0.00];
+    style.colors[StyleColor::FrameBg as usize] = [1.00, 1.00, 1.00, 1.00];
+    style.colors[StyleColor::FrameBgHovered as usize] = [0.26, 0.59, 0.98, 0.40];
+    style.colors[StyleColor::FrameBgActive as usize] = [0.26, 0.59, 0.98, 0.67];
+    style.colors[StyleColor::TitleBg as usize] = [0.96, 0.96, 0.96, 1.00];
+    style.colors[StyleColor::TitleBgActive as usize] = [0.82, 0.82, 0.82, 1.00];
+    style.colors[StyleColor::TitleBgCollapsed as usize] = [1.00, 1.00, 1.00, 0.51];
+    style.colors[StyleColor::MenuBarBg as usize] = [0.86, 0.86, 0.86, 1.00];
+    style.colors[StyleColor::ScrollbarBg as usize] = [0.98, 0.98, 0.98, 0.53];
+    style.colors[StyleColor::ScrollbarGrab as usize] = [0.69, 0.69, 0.69, 0.80];
+    style.colors[StyleColor::ScrollbarGrabHovered as usize] = [0.49, 0.49, 0.49, 0.80];
+    style.colors[StyleColor::ScrollbarGrabActive as usize] = [0.49, 0.49, 0.49, 1.00];
+    style.colors[StyleColor::CheckMark as usize] = [0.26, 0.59, 0.98, 1.00];
+    style.colors[StyleColor::SliderGrab as usize] = [0.26, 0.59, 0.98, 0.78];
+    style.colors[StyleColor::SliderGrabActive as usize] = [0.46, 0.54, 0.80, 0.60];
+    style.colors[StyleColor::Button as usize] = [0.26, 0.59, 0.98, 0.40];
+    style.colors[StyleColor::ButtonHovered as usize] = [0.26, 0.59, 0.98, 1.00];
+    style.colors[StyleColor::ButtonActive as usize] = [0.06, 0.53, 0.98, 1.00];
+    style.colors[StyleColor::Header as usize] = [0.26, 0.59, 0.98, 0.31];
+    style.colors[StyleColor::HeaderHovered as usize] = [0.26, 0.59, 0.98, 0.80];
+    style.colors[StyleColor::HeaderActive as usize] = [0.26, 0.59, 0.98, 1.00];
+    style.colors[StyleColor::Separator as usize] = [0.39, 0.39, 0.39, 0.62];
+    style.colors[StyleColor::SeparatorHovered as usize] = [0.14, 0.44, 0.80, 0.78];
+    style.colors[StyleColor::SeparatorActive as usize] = [0.14, 0.44, 0.80, 1.00];
+    style.colors[StyleColor::ResizeGrip as usize] = [0.35, 0.35, 0.35, 0.17];
+    style.colors[StyleColor::ResizeGripHovered as usize] = [0.26, 0.59, 0.98, 0.67];
+    style.colors[StyleColor::ResizeGripActive as usize] = [0.26, 0.59, 0.98, 0.95];
+    style.colors[StyleColor::Tab as usize] = [0.76, 0.80, 0.84, 0.93];
+    style.colors[StyleColor::TabHovered as usize] = style.colors[StyleColor::HeaderHovered as usize];
+    style.colors[StyleColor::TabActive as usize] = [0.60, 0.73, 0.88, 1.00];
+    style.colors[StyleColor::TabUnfocused as usize] = style.colors[StyleColor::Tab as usize];
+    style.colors[StyleColor::TabUnfocusedActive as usize] = style.colors[StyleColor::TabActive as usize];
+    style.colors[StyleColor::PlotLines as usize] = [0.39, 0.39, 0.39, 1.00];
+    style.colors[StyleColor::PlotLinesHovered as usize] = [1.00, 0.43, 0.35, 1.00];
+    style.colors[StyleColor::PlotHistogram as usize] = [0.90, 0.70, 0.00, 1.00];
+    style.colors[StyleColor::PlotHistogramHovered as usize] = [1.00, 0.45, 0.00, 1.00];
+    style.colors[StyleColor::TextSelectedBg as usize] = [0.26, 0.59, 0.98, 0.35];
+    style.colors[StyleColor::DragDropTarget as usize] = [0.26, 0.59, 0.98, 0.95];
+    style.colors[StyleColor::NavHighlight as usize] = style.colors[StyleColor::HeaderHovered as usize];
+    style.colors[StyleColor::NavWindowingHighlight as usize] = [0.70, 0.70, 0.70, 0.70];
+    style.colors[StyleColor::NavWindowingDimBg as usize] = [0.20, 0.20, 0.20, 0.20];
+    style.colors[StyleColor::ModalWindowDimBg as usize] = [0.20, 0.20, 0.20, 0.35];
+}
@@ -0,0 +1,123 @@
+//! This module lets frontends run their own transformations after the standard patches.
+//!
+//! A hook receives the patched image and the matched patch set, e.g. to apply a byte fixup for a
+//! specific immobilizer box. Hooks run after the patches are applied, in the order they were
+//! registered, and the checksum is recalculated once more after the last hook.
+
+use crate::patcher::{self, PatcherError};
+use crate::patches::PatchSet;
+
+/// A transformation run after the patches are applied. Returns one log line per change it made.
+pub type PostPatchHook = Box<dyn Fn(&mut [u8], &PatchSet) -> Result<Vec<String>, PatcherError> + Send + Sync>;
+
+/// An ordered list of named post-patch hooks. Empty by default.
+#[derive(Default)]
+pub struct PostPatchHooks {
+    hooks: Vec<(String, PostPatchHook)>,
+}
+
+impl PostPatchHooks {
+    /// Adds a hook that runs after every previously registered one.
+    pub fn register<F>(&mut self, name: impl Into<String>, hook: F)
+    where
+        F: Fn(&mut [u8], &PatchSet) -> Result<Vec<String>, PatcherError> + Send + Sync + 'static,
+    {
+        self.hooks.push((name.into(), Box::new(hook)));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Runs every hook in registration order and returns their log lines, each group headed by the hook name.
+    ///
+    /// Stops at the first hook that fails. Changes made by earlier hooks are not undone.
+    pub fn run(&self, data: &mut [u8], patch_set: &PatchSet) -> Result<Vec<String>, PatcherError> {
+        let mut logs = Vec::new();
+        for (name, hook) in &self.hooks {
+            logs.push(format!("Running post-patch hook '{}'.", name));
+            logs.extend(hook(data, patch_set)?);
+        }
+        Ok(logs)
+    }
+}
+
+impl std::fmt::Debug for PostPatchHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.hooks.iter().map(|(name, _)| name)).finish()
+    }
+}
+
+/// Applies all patches, runs `hooks` on the result and then recalculates the checksum, so changes
+/// made by the hooks are covered.
+///
+/// If a hook fails, `data` is restored to its state before the patches were applied.
+pub fn apply_patches_with_hooks(
+    data: &mut [u8],
+    patch_set: &PatchSet,
+    hooks: &PostPatchHooks,
+) -> Result<Vec<String>, PatcherError> {
+    let before = (!hooks.is_empty()).then(|| data.to_vec());
+    let mut logs = patcher::apply_patches(data, patch_set)?;
+    match hooks.run(data, patch_set) {
+        Ok(hook_logs) => {
+            logs.extend(hook_logs);
+            if !hooks.is_empty() {
+                patcher::recalculate_checksum(data, patch_set)?;
+            }
+            Ok(logs)
+        }
+        Err(e) => {
+            if let Some(before) = before {
+                data.copy_from_slice(&before);
+            }
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{build_synthetic_firmware, builtin_patch_set};
+
+    #[test]
+    fn hooks_run_in_registration_order_after_the_patches() {
+        let patch_set = builtin_patch_set("ca430056");
+        let mut data = build_synthetic_firmware(&patch_set);
+        let mut hooks = PostPatchHooks::default();
+        hooks.register("first", |data: &mut [u8], _: &PatchSet| {
+            data[0] = 0x01;
+            Ok(vec!["set byte 0".to_string()])
+        });
+        hooks.register("second", |data: &mut [u8], set: &PatchSet| {
+            // Runs after the first hook and after the patches.
+            assert_eq!(data[0], 0x01);
+            assert_eq!(data[set.patches[2].offset], set.patches[2].patched[0]);
+            Ok(Vec::new())
+        });
+
+        let logs = apply_patches_with_hooks(&mut data, &patch_set, &hooks).unwrap();
+        assert_eq!(
+            logs[patch_set.patches.len()..],
+            ["Running post-patch hook 'first'.", "set byte 0", "Running post-patch hook 'second'."]
+        );
+        assert_eq!(format!("{:?}", hooks), r#"["first", "second"]"#);
+    }
+
+    #[test]
+    fn a_failing_hook_restores_the_unpatched_data() {
+        let patch_set = builtin_patch_set("ca430056");
+        let pristine = build_synthetic_firmware(&patch_set);
+        let mut data = pristine.clone();
+        let mut hooks = PostPatchHooks::default();
+        hooks.register("fails", |data: &mut [u8], _: &PatchSet| {
+            data[0] = 0x01;
+            Err(PatcherError::HookFailed("unexpected box type".to_string()))
+        });
+
+        let error = apply_patches_with_hooks(&mut data, &patch_set, &hooks).unwrap_err();
+        assert_eq!(error.error_code(), "hook_failed");
+        assert_eq!(data, pristine);
+    }
+}
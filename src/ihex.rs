@@ -0,0 +1,423 @@
+//! This module converts Intel HEX and Motorola S-record files to and from the flat binary image used by the patcher.
+//!
+//! Decoded images start at the lowest address found in the file. Gaps between records are filled
+//! with erased-flash bytes (0xFF). The record layout is remembered so the image can be written back
+//! in the format it was loaded from.
+
+use std::fmt::Write;
+
+/// Byte used to fill gaps between records.
+const FILL_BYTE: u8 = 0xFF;
+
+/// Number of data bytes per record when no layout is known.
+const DEFAULT_RECORD_LEN: usize = 16;
+
+/// Errors that can occur while decoding a HEX or S-record file.
+#[derive(Debug, thiserror::Error)]
+pub enum FormatError {
+    #[error("Line {line}: {reason}")]
+    InvalidRecord { line: usize, reason: &'static str },
+    #[error("Line {line}: Record checksum mismatch.")]
+    ChecksumMismatch { line: usize },
+    #[error("The file contains no data records.")]
+    NoData,
+}
+
+impl FormatError {
+    /// Returns a stable, machine-readable code identifying the kind of error.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            FormatError::InvalidRecord { .. } => "invalid_record",
+            FormatError::ChecksumMismatch { .. } => "record_checksum_mismatch",
+            FormatError::NoData => "no_data_records",
+        }
+    }
+}
+
+/// The on-disk format of a firmware image, with the layout needed to write it back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageFormat {
+    Binary,
+    IntelHex {
+        /// Address of the first byte of the image.
+        base_address: u32,
+        record_len: usize,
+    },
+    SRecord {
+        /// Address of the first byte of the image.
+        base_address: u32,
+        record_len: usize,
+        /// Contents of the S0 header record.
+        header: Vec<u8>,
+    },
+}
+
+impl ImageFormat {
+    /// Returns a short human-readable name of the format.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageFormat::Binary => "binary",
+            ImageFormat::IntelHex { .. } => "Intel HEX",
+            ImageFormat::SRecord { .. } => "Motorola S-record",
+        }
+    }
+
+    /// Returns the conventional file extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Binary => "bin",
+            ImageFormat::IntelHex { .. } => "hex",
+            ImageFormat::SRecord { .. } => "s19",
+        }
+    }
+
+    /// Picks the output format for a file extension.
+    ///
+    /// The layout of `source` is kept if it is of the same kind, so files are written back with
+    /// the same base address and record length they were loaded with.
+    pub fn for_extension(extension: &str, source: &ImageFormat) -> ImageFormat {
+        match extension.to_ascii_lowercase().as_str() {
+            "hex" | "ihex" => match source {
+                ImageFormat::IntelHex { .. } => source.clone(),
+                ImageFormat::SRecord { base_address, record_len, .. } => {
+                    ImageFormat::IntelHex { base_address: *base_address, record_len: *record_len }
+                }
+                ImageFormat::Binary => ImageFormat::IntelHex { base_address: 0, record_len: DEFAULT_RECORD_LEN },
+            },
+            "s19" | "s28" | "s37" | "srec" | "mot" => match source {
+                ImageFormat::SRecord { .. } => source.clone(),
+                ImageFormat::IntelHex { base_address, record_len } => {
+                    ImageFormat::SRecord { base_address: *base_address, record_len: *record_len, header: Vec::new() }
+                }
+                ImageFormat::Binary => {
+                    ImageFormat::SRecord { base_address: 0, record_len: DEFAULT_RECORD_LEN, header: Vec::new() }
+                }
+            },
+            _ => ImageFormat::Binary,
+        }
+    }
+}
+
+/// A decoded firmware image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedImage {
+    pub data: Vec<u8>,
+    pub format: ImageFormat,
+    /// Non-fatal problems found while decoding, such as gaps between records.
+    pub warnings: Vec<String>,
+}
+
+/// Detects the format of a file from its contents.
+pub fn detect_format(contents: &[u8]) -> ImageFormat {
+    let first_line = contents
+        .split(|&b| b == b'\n')
+        .map(|line| line.trim_ascii())
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+
+    let is_hex_digits = |bytes: &[u8]| !bytes.is_empty() && bytes.iter().all(u8::is_ascii_hexdigit);
+    match first_line {
+        [b':', rest @ ..] if is_hex_digits(rest) => {
+            ImageFormat::IntelHex { base_address: 0, record_len: DEFAULT_RECORD_LEN }
+        }
+        [b'S', kind, rest @ ..] if kind.is_ascii_digit() && is_hex_digits(rest) => {
+            ImageFormat::SRecord { base_address: 0, record_len: DEFAULT_RECORD_LEN, header: Vec::new() }
+        }
+        _ => ImageFormat::Binary,
+    }
+}
+
+/// Decodes a file into a flat image, detecting its format from the contents.
+///
+/// Raw binary files are returned unchanged.
+pub fn decode(contents: Vec<u8>) -> Result<DecodedImage, FormatError> {
+    match detect_format(&contents) {
+        ImageFormat::Binary => Ok(DecodedImage { data: contents, format: ImageFormat::Binary, warnings: Vec::new() }),
+        ImageFormat::IntelHex { .. } => decode_intel_hex(&contents),
+        ImageFormat::SRecord { .. } => decode_srecord(&contents),
+    }
+}
+
+/// Encodes a flat image in the given format.
+pub fn encode(data: &[u8], format: &ImageFormat) -> Vec<u8> {
+    match format {
+        ImageFormat::Binary => data.to_vec(),
+        ImageFormat::IntelHex { base_address, record_len } => encode_intel_hex(data, *base_address, *record_len),
+        ImageFormat::SRecord { base_address, record_len, header } => {
+            encode_srecord(data, *base_address, *record_len, header)
+        }
+    }
+}
+
+/// Parses the hex digits of a record into bytes.
+fn parse_record_bytes(digits: &str, line: usize) -> Result<Vec<u8>, FormatError> {
+    if !digits.len().is_multiple_of(2) || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(FormatError::InvalidRecord { line, reason: "Record contains invalid hex digits." });
+    }
+    Ok((0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+/// Assembles data chunks into a contiguous image, filling gaps and recording warnings.
+fn assemble(mut chunks: Vec<(u32, Vec<u8>)>) -> Result<(Vec<u8>, u32, Vec<String>), FormatError> {
+    let mut warnings = Vec::new();
+    if chunks.windows(2).any(|w| w[1].0 < w[0].0) {
+        warnings.push("Records are not in address order.".to_string());
+    }
+    chunks.sort_by_key(|(address, _)| *address);
+
+    let base = chunks.first().ok_or(FormatError::NoData)?.0;
+    let end = chunks.iter().map(|(address, bytes)| *address as usize + bytes.len()).max().unwrap_or(0);
+    let mut data = vec![FILL_BYTE; end - base as usize];
+
+    let mut covered_until = base as usize;
+    for (address, bytes) in &chunks {
+        let start = *address as usize;
+        if start > covered_until {
+            warnings.push(format!(
+                "Gap of {} bytes at address {:#X} filled with {:#04X}.",
+                start - covered_until,
+                covered_until,
+                FILL_BYTE
+            ));
+        }
+        let offset = start - base as usize;
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        covered_until = covered_until.max(start + bytes.len());
+    }
+
+    Ok((data, base, warnings))
+}
+
+fn decode_intel_hex(contents: &[u8]) -> Result<DecodedImage, FormatError> {
+    let text = String::from_utf8_lossy(contents);
+    let mut chunks = Vec::new();
+    let mut upper_address = 0u32;
+    let mut record_len = 0;
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line = index + 1;
+        let record = raw_line.trim();
+        if record.is_empty() {
+            continue;
+        }
+        let digits = record
+            .strip_prefix(':')
+            .ok_or(FormatError::InvalidRecord { line, reason: "Record does not start with ':'." })?;
+        let bytes = parse_record_bytes(digits, line)?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(FormatError::InvalidRecord { line, reason: "Record length does not match its byte count." });
+        }
+        if bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != 0 {
+            return Err(FormatError::ChecksumMismatch { line });
+        }
+
+        let payload = &bytes[4..bytes.len() - 1];
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        match bytes[3] {
+            0x00 => {
+                record_len = record_len.max(payload.len());
+                chunks.push((upper_address + address, payload.to_vec()));
+            }
+            0x01 => break,
+            0x02 if payload.len() == 2 => upper_address = (u16::from_be_bytes([payload[0], payload[1]]) as u32) << 4,
+            0x04 if payload.len() == 2 => upper_address = (u16::from_be_bytes([payload[0], payload[1]]) as u32) << 16,
+            0x03 | 0x05 => {} // Start addresses are irrelevant for a flash image.
+            _ => return Err(FormatError::InvalidRecord { line, reason: "Unsupported record type." }),
+        }
+    }
+
+    let (data, base_address, warnings) = assemble(chunks)?;
+    Ok(DecodedImage {
+        data,
+        format: ImageFormat::IntelHex { base_address, record_len },
+        warnings,
+    })
+}
+
+fn decode_srecord(contents: &[u8]) -> Result<DecodedImage, FormatError> {
+    let text = String::from_utf8_lossy(contents);
+    let mut chunks = Vec::new();
+    let mut header = Vec::new();
+    let mut record_len = 0;
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line = index + 1;
+        let record = raw_line.trim();
+        if record.is_empty() {
+            continue;
+        }
+        let (kind, digits) = match record.as_bytes() {
+            [b'S', kind, ..] if kind.is_ascii_digit() => (kind - b'0', &record[2..]),
+            _ => return Err(FormatError::InvalidRecord { line, reason: "Record does not start with 'S' and a type digit." }),
+        };
+        let bytes = parse_record_bytes(digits, line)?;
+        if bytes.len() < 2 || bytes.len() != bytes[0] as usize + 1 {
+            return Err(FormatError::InvalidRecord { line, reason: "Record length does not match its byte count." });
+        }
+        if bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != 0xFF {
+            return Err(FormatError::ChecksumMismatch { line });
+        }
+
+        let address_len = match kind {
+            0 | 1 | 5 | 9 => 2,
+            2 | 6 | 8 => 3,
+            3 | 7 => 4,
+            _ => return Err(FormatError::InvalidRecord { line, reason: "Unsupported record type." }),
+        };
+        if bytes.len() < 2 + address_len {
+            return Err(FormatError::InvalidRecord { line, reason: "Record is too short for its address." });
+        }
+        let address = bytes[1..1 + address_len].iter().fold(0u32, |acc, &b| (acc << 8) | b as u32);
+        let payload = &bytes[1 + address_len..bytes.len() - 1];
+        match kind {
+            0 => header = payload.to_vec(),
+            1..=3 => {
+                record_len = record_len.max(payload.len());
+                chunks.push((address, payload.to_vec()));
+            }
+            _ => {} // Record counts and start addresses are regenerated on write.
+        }
+    }
+
+    let (data, base_address, warnings) = assemble(chunks)?;
+    Ok(DecodedImage {
+        data,
+        format: ImageFormat::SRecord { base_address, record_len, header },
+        warnings,
+    })
+}
+
+/// Appends one Intel HEX record, including its checksum.
+fn push_intel_hex_record(out: &mut String, address: u16, record_type: u8, payload: &[u8]) {
+    let mut bytes = vec![payload.len() as u8];
+    bytes.extend_from_slice(&address.to_be_bytes());
+    bytes.push(record_type);
+    bytes.extend_from_slice(payload);
+    let checksum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)).wrapping_neg();
+    bytes.push(checksum);
+
+    out.push(':');
+    for b in bytes {
+        let _ = write!(out, "{:02X}", b);
+    }
+    out.push('\n');
+}
+
+fn encode_intel_hex(data: &[u8], base_address: u32, record_len: usize) -> Vec<u8> {
+    let mut out = String::new();
+    let mut current_upper = None;
+
+    for (index, chunk) in data.chunks(record_len.max(1)).enumerate() {
+        let address = base_address + (index * record_len.max(1)) as u32;
+        let upper = (address >> 16) as u16;
+        if current_upper != Some(upper) {
+            push_intel_hex_record(&mut out, 0, 0x04, &upper.to_be_bytes());
+            current_upper = Some(upper);
+        }
+        push_intel_hex_record(&mut out, address as u16, 0x00, chunk);
+    }
+    push_intel_hex_record(&mut out, 0, 0x01, &[]);
+
+    out.into_bytes()
+}
+
+/// Appends one S-record, including its byte count and checksum.
+fn push_srecord(out: &mut String, kind: u8, address: u32, address_len: usize, payload: &[u8]) {
+    let mut bytes = vec![(address_len + payload.len() + 1) as u8];
+    bytes.extend_from_slice(&address.to_be_bytes()[4 - address_len..]);
+    bytes.extend_from_slice(payload);
+    let checksum = !bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    bytes.push(checksum);
+
+    let _ = write!(out, "S{}", kind);
+    for b in bytes {
+        let _ = write!(out, "{:02X}", b);
+    }
+    out.push('\n');
+}
+
+fn encode_srecord(data: &[u8], base_address: u32, record_len: usize, header: &[u8]) -> Vec<u8> {
+    let end_address = base_address as u64 + data.len() as u64;
+    let (data_kind, end_kind, address_len) = if end_address <= 0x1_0000 {
+        (1, 9, 2)
+    } else if end_address <= 0x100_0000 {
+        (2, 8, 3)
+    } else {
+        (3, 7, 4)
+    };
+
+    let mut out = String::new();
+    push_srecord(&mut out, 0, 0, 2, header);
+    for (index, chunk) in data.chunks(record_len.max(1)).enumerate() {
+        let address = base_address + (index * record_len.max(1)) as u32;
+        push_srecord(&mut out, data_kind, address, address_len, chunk);
+    }
+    push_srecord(&mut out, end_kind, base_address, address_len, &[]);
+
+    out.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_binary_is_passed_through() {
+        let decoded = decode(vec![0x00, 0x01, 0xFF]).unwrap();
+        assert_eq!(decoded.format, ImageFormat::Binary);
+        assert_eq!(decoded.data, vec![0x00, 0x01, 0xFF]);
+    }
+
+    #[test]
+    fn intel_hex_round_trips_with_extended_addresses() {
+        let data: Vec<u8> = (0..=255u8).cycle().take(0x30).collect();
+        let format = ImageFormat::IntelHex { base_address: 0x80FFF0, record_len: 16 };
+        let encoded = encode(&data, &format);
+        assert!(String::from_utf8_lossy(&encoded).starts_with(":02000004008"));
+
+        let decoded = decode(encoded).unwrap();
+        assert_eq!(decoded.data, data);
+        assert_eq!(decoded.format, format);
+        assert!(decoded.warnings.is_empty());
+    }
+
+    #[test]
+    fn srecord_round_trips_and_keeps_the_header() {
+        let data: Vec<u8> = (0..100u8).collect();
+        let format = ImageFormat::SRecord { base_address: 0x800000, record_len: 32, header: b"MS43".to_vec() };
+        let decoded = decode(encode(&data, &format)).unwrap();
+        assert_eq!(decoded.data, data);
+        assert_eq!(decoded.format, format);
+    }
+
+    #[test]
+    fn gaps_and_unordered_records_are_filled_and_reported() {
+        let hex = ":020010001122BB\n:020000000102FB\n:00000001FF\n";
+        let decoded = decode(hex.as_bytes().to_vec()).unwrap();
+        let mut expected = vec![0xFF; 0x12];
+        expected[0..2].copy_from_slice(&[0x01, 0x02]);
+        expected[0x10..0x12].copy_from_slice(&[0x11, 0x22]);
+        assert_eq!(decoded.data, expected);
+        assert_eq!(decoded.warnings.len(), 2);
+    }
+
+    #[test]
+    fn corrupt_checksum_is_rejected() {
+        let result = decode(b":020000000102FC\n".to_vec());
+        assert!(matches!(result, Err(FormatError::ChecksumMismatch { line: 1 })));
+    }
+
+    #[test]
+    fn output_format_follows_the_extension() {
+        let source = ImageFormat::IntelHex { base_address: 0x800000, record_len: 32 };
+        assert_eq!(ImageFormat::for_extension("HEX", &source), source);
+        assert_eq!(ImageFormat::for_extension("bin", &source), ImageFormat::Binary);
+        assert!(matches!(
+            ImageFormat::for_extension("s19", &source),
+            ImageFormat::SRecord { base_address: 0x800000, record_len: 32, .. }
+        ));
+    }
+}
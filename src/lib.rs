@@ -0,0 +1,40 @@
+//! Core logic of the EWS IMMO Patcher for MS43, usable without the GUI.
+//!
+//! The typical flow is to detect the firmware version, inspect the patch status, and then apply
+//! or revert the patches on the in-memory firmware image:
+//!
+//! ```no_run
+//! let mut data = std::fs::read("firmware.bin").unwrap();
+//! let patch_set = ewsms43::detect_version(&data).unwrap();
+//! println!("{:?}", ewsms43::check_patch_status(&data, patch_set));
+//! ewsms43::apply_patches(&mut data, patch_set).unwrap();
+//! ```
+//!
+//! The items re-exported at the crate root form the stable API. The modules themselves are public
+//! for advanced use, but their other items may change between releases.
+
+pub mod archive;
+pub mod backup;
+pub mod batch;
+pub mod checksum;
+pub mod compare;
+pub mod delta;
+pub mod fingerprint;
+pub mod hooks;
+pub mod ihex;
+pub mod mapped;
+pub mod patch_file;
+pub mod patcher;
+pub mod patches;
+pub mod report;
+pub mod search;
+pub mod selftest;
+#[cfg(test)]
+mod testutil;
+pub mod verify;
+pub mod version;
+
+pub use hooks::PostPatchHooks;
+pub use patcher::{apply_patches, check_all_statuses, check_patch_status, revert_patches, PatchStatus, PatcherError};
+pub use patches::{Patch, PatchSet, RiskLevel};
+pub use version::{detect_version, VersionError};
@@ -1,94 +1,132 @@
+mod cli;
+mod config;
 mod gui;
-mod patches;
-mod patcher;
-mod version;
 
-use crate::gui::main_window::{render_main_window, AppState};
+use crate::gui::clipboard::SystemClipboard;
+use crate::gui::log_panel::LogEntry;
+use crate::gui::main_window::{ask_before_discarding, render_main_window, AppState, DiscardAction};
+use crate::gui::theme::apply_theme;
 use glium::backend::glutin::SimpleWindowBuilder;
 use glium::Surface;
-use imgui::{Context, FontSource, StyleColor};
+use imgui::{Context, FontSource};
 use imgui_glium_renderer::Renderer;
 use imgui_winit_support::WinitPlatform;
-use std::time::Instant;
+use crate::config::WindowGeometry;
+use std::time::{Duration, Instant};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
 use winit::event::{Event, WindowEvent};
-use winit::event_loop::{EventLoop};
-
-fn apply_custom_style(ctx: &mut Context) {
-    let style = ctx.style_mut();
-    style.window_padding = [15.0, 15.0];
-    style.frame_padding = [8.0, 4.0];
-    style.item_spacing = [10.0, 8.0];
-    style.item_inner_spacing = [6.0, 6.0];
-    style.window_rounding = 8.0;
-    style.frame_rounding = 4.0;
-    style.child_rounding = 4.0;
-    style.grab_rounding = 4.0;
-    style.popup_rounding = 4.0;
-    style.scrollbar_rounding = 6.0;
-    style.tab_rounding = 4.0;
-
-    style.colors[StyleColor::Text as usize] = [0.90, 0.90, 0.90, 1.00];
-    style.colors[StyleColor::TextDisabled as usize] = [0.50, 0.50, 0.50, 1.00];
-    style.colors[StyleColor::WindowBg as usize] = [0.13, 0.14, 0.15, 1.00];
-    style.colors[StyleColor::ChildBg as usize] = [0.13, 0.14, 0.15, 1.00];
-    style.colors[StyleColor::PopupBg as usize] = [0.08, 0.08, 0.08, 0.94];
-    style.colors[StyleColor::Border as usize] = [0.43, 0.43, 0.50, 0.50];
-    style.colors[StyleColor::BorderShadow as usize] = [0.00, 0.00, 0.00, 0.00];
-    style.colors[StyleColor::FrameBg as usize] = [0.25, 0.25, 0.25, 0.54];
-    style.colors[StyleColor::FrameBgHovered as usize] = [0.38, 0.38, 0.38, 0.40];
-    style.colors[StyleColor::FrameBgActive as usize] = [0.42, 0.42, 0.42, 0.67];
-    style.colors[StyleColor::TitleBg as usize] = [0.04, 0.04, 0.04, 1.00];
-    style.colors[StyleColor::TitleBgActive as usize] = [0.16, 0.29, 0.48, 1.00];
-    style.colors[StyleColor::TitleBgCollapsed as usize] = [0.00, 0.00, 0.00, 0.51];
-    style.colors[StyleColor::MenuBarBg as usize] = [0.14, 0.14, 0.14, 1.00];
-    style.colors[StyleColor::ScrollbarBg as usize] = [0.02, 0.02, 0.02, 0.53];
-    style.colors[StyleColor::ScrollbarGrab as usize] = [0.31, 0.31, 0.31, 1.00];
-    style.colors[StyleColor::ScrollbarGrabHovered as usize] = [0.41, 0.41, 0.41, 1.00];
-    style.colors[StyleColor::ScrollbarGrabActive as usize] = [0.51, 0.51, 0.51, 1.00];
-    style.colors[StyleColor::CheckMark as usize] = [0.26, 0.59, 0.98, 1.00];
-    style.colors[StyleColor::SliderGrab as usize] = [0.24, 0.52, 0.88, 1.00];
-    style.colors[StyleColor::SliderGrabActive as usize] = [0.26, 0.59, 0.98, 1.00];
-    style.colors[StyleColor::Button as usize] = [0.26, 0.59, 0.98, 0.40];
-    style.colors[StyleColor::ButtonHovered as usize] = [0.26, 0.59, 0.98, 1.00];
-    style.colors[StyleColor::ButtonActive as usize] = [0.06, 0.53, 0.98, 1.00];
-    style.colors[StyleColor::Header as usize] = [0.26, 0.59, 0.98, 0.31];
-    style.colors[StyleColor::HeaderHovered as usize] = [0.26, 0.59, 0.98, 0.80];
-    style.colors[StyleColor::HeaderActive as usize] = [0.26, 0.59, 0.98, 1.00];
-    style.colors[StyleColor::Separator as usize] = style.colors[StyleColor::Border as usize];
-    style.colors[StyleColor::SeparatorHovered as usize] = [0.10, 0.40, 0.75, 0.78];
-    style.colors[StyleColor::SeparatorActive as usize] = [0.10, 0.40, 0.75, 1.00];
-    style.colors[StyleColor::ResizeGrip as usize] = [0.26, 0.59, 0.98, 0.25];
-    style.colors[StyleColor::ResizeGripHovered as usize] = [0.26, 0.59, 0.98, 0.67];
-    style.colors[StyleColor::ResizeGripActive as usize] = [0.26, 0.59, 0.98, 0.95];
-    style.colors[StyleColor::Tab as usize] = style.colors[StyleColor::Header as usize];
-    style.colors[StyleColor::TabHovered as usize] = style.colors[StyleColor::HeaderHovered as usize];
-    style.colors[StyleColor::TabActive as usize] = style.colors[StyleColor::HeaderActive as usize];
-    style.colors[StyleColor::TabUnfocused as usize] = style.colors[StyleColor::Tab as usize];
-    style.colors[StyleColor::TabUnfocusedActive as usize] = style.colors[StyleColor::TabActive as usize];
-    style.colors[StyleColor::PlotLines as usize] = [0.61, 0.61, 0.61, 1.00];
-    style.colors[StyleColor::PlotLinesHovered as usize] = [1.00, 0.43, 0.35, 1.00];
-    style.colors[StyleColor::PlotHistogram as usize] = [0.90, 0.70, 0.00, 1.00];
-    style.colors[StyleColor::PlotHistogramHovered as usize] = [1.00, 0.60, 0.00, 1.00];
-    style.colors[StyleColor::TextSelectedBg as usize] = [0.26, 0.59, 0.98, 0.35];
-    style.colors[StyleColor::DragDropTarget as usize] = [1.00, 1.00, 0.00, 0.90];
-    style.colors[StyleColor::NavHighlight as usize] = [0.26, 0.59, 0.98, 1.00];
-    style.colors[StyleColor::NavWindowingHighlight as usize] = [1.00, 1.00, 1.00, 0.70];
-    style.colors[StyleColor::NavWindowingDimBg as usize] = [0.80, 0.80, 0.80, 0.20];
-    style.colors[StyleColor::ModalWindowDimBg as usize] = [0.80, 0.80, 0.80, 0.35];
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::window::Window;
+
+const DEFAULT_WINDOW_SIZE: (u32, u32) = (1024, 768);
+const WINDOW_TITLE: &str = "EWS IMMO Patcher MS43";
+/// UI font embedded in the binary, so the app starts on every platform. See assets/LICENSE-DejaVu.txt.
+const EMBEDDED_FONT: &[u8] = include_bytes!("../assets/DejaVuSans.ttf");
+/// System font preferred over the embedded one when it is installed, for the native Windows look.
+const SYSTEM_FONT_PATH: &str = "C:\\Windows\\Fonts\\segoeui.ttf";
+const MIN_WINDOW_SIZE: u32 = 200;
+/// Frames drawn after an input or state change before the window goes idle.
+const FRAMES_AFTER_INPUT: u32 = 3;
+/// How often the window is redrawn while idle but waiting for background work or showing a text cursor.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Returns true if the top-left corner of the saved window lies on one of the connected monitors.
+fn is_on_screen(window: &Window, geometry: &WindowGeometry) -> bool {
+    let Some((x, y)) = geometry.position else {
+        return true;
+    };
+    window.available_monitors().any(|monitor| {
+        let origin = monitor.position();
+        let size = monitor.size();
+        x >= origin.x && x < origin.x + size.width as i32 && y >= origin.y && y < origin.y + size.height as i32
+    })
+}
+
+/// Returns the UI font: the system font if it can be read, otherwise the embedded one.
+fn ui_font_data() -> &'static [u8] {
+    if cfg!(windows) {
+        if let Ok(data) = std::fs::read(SYSTEM_FONT_PATH) {
+            // Loaded once at startup and needed for the whole run.
+            return data.leak();
+        }
+    }
+    EMBEDDED_FONT
+}
+
+/// Returns the window title, marked with an asterisk while the loaded data has unsaved changes.
+fn window_title(dirty: bool) -> String {
+    if dirty {
+        format!("*{}", WINDOW_TITLE)
+    } else {
+        WINDOW_TITLE.to_string()
+    }
+}
+
+/// Reads the current window size and position for saving in the config.
+fn current_geometry(window: &Window) -> WindowGeometry {
+    let size = window.inner_size();
+    WindowGeometry {
+        width: size.width,
+        height: size.height,
+        position: window.outer_position().ok().map(|p| (p.x, p.y)),
+    }
+}
+
+/// Saves the window geometry with the settings and ends the event loop.
+fn save_window_and_exit(app_state: &mut AppState, window: &Window, event_loop: &ActiveEventLoop) {
+    app_state.config.window = Some(current_geometry(window));
+    if let Err(e) = config::save(&app_state.config) {
+        eprintln!("Failed to save settings: {}", e);
+    }
+    event_loop.exit();
 }
 
 fn main() {
+    // External patch sets are registered first, so both the CLI and the GUI detect their versions.
+    let patch_set_log = config::load_external_patch_sets();
+    if let Some(code) = cli::run_from_env(&patch_set_log) {
+        std::process::exit(code);
+    }
+
+    // Debug builds refuse to start with broken patch data, so mistakes in patches.rs are caught early.
+    #[cfg(debug_assertions)]
+    if let Err(message) = cli::self_test() {
+        eprintln!("{}", message);
+        std::process::exit(1);
+    }
+
+    let config = config::load();
     let event_loop = EventLoop::new().expect("Failed to create event loop");
+    let saved_geometry = config
+        .window
+        .filter(|g| g.width >= MIN_WINDOW_SIZE && g.height >= MIN_WINDOW_SIZE);
+    let (width, height) = saved_geometry.map_or(DEFAULT_WINDOW_SIZE, |g| (g.width, g.height));
     let (window, display) = SimpleWindowBuilder::new()
-        .with_title("EWS IMMO Patcher MS43")
-        .with_inner_size(1024, 768)
+        .with_title(WINDOW_TITLE)
+        .with_inner_size(width, height)
         .build(&event_loop);
 
+    // Restore the saved position, unless it is on a monitor that is no longer connected.
+    if let Some(geometry) = saved_geometry {
+        if !is_on_screen(&window, &geometry) {
+            let _ = window.request_inner_size(PhysicalSize::new(DEFAULT_WINDOW_SIZE.0, DEFAULT_WINDOW_SIZE.1));
+        } else if let Some((x, y)) = geometry.position {
+            window.set_outer_position(PhysicalPosition::new(x, y));
+        }
+    }
+
     // window.set_resizable(false); // Allow window to be resizable
 
     let mut imgui = Context::create();
     imgui.set_ini_filename(None);
-    apply_custom_style(&mut imgui);
+    let clipboard = SystemClipboard::new();
+    let clipboard_available = clipboard.is_some();
+    if let Some(clipboard) = clipboard {
+        imgui.set_clipboard_backend(clipboard);
+    }
+    let mut active_theme = config.theme;
+    apply_theme(&mut imgui, active_theme);
+    let mut saved_config = config.clone();
 
     let mut platform = WinitPlatform::new(&mut imgui);
     platform.attach_window(
@@ -100,7 +138,7 @@ fn main() {
     let hidpi_factor = platform.hidpi_factor();
     let font_size = (14.0 * hidpi_factor) as f32;
     imgui.fonts().add_font(&[FontSource::TtfData {
-        data: include_bytes!("../../../../../../../Windows/Fonts/segoeui.ttf"),
+        data: ui_font_data(),
         size_pixels: font_size,
         config: Some(imgui::FontConfig {
             rasterizer_multiply: 1.5,
@@ -111,8 +149,24 @@ fn main() {
     let mut renderer = Renderer::new(&mut imgui, &display).expect("Failed to initialize renderer");
 
     let mut last_frame = Instant::now();
-    let mut app_state = AppState::default();
+    let mut app_state = AppState {
+        config,
+        ..AppState::default()
+    };
+    app_state.log.extend(patch_set_log);
+    if !clipboard_available {
+        app_state
+            .log
+            .push(LogEntry::warning("Warning: The system clipboard is not available, so copied text stays inside the patcher."));
+    }
 
+    // Frames still to draw before the loop goes idle. imgui needs a few frames after an input to settle,
+    // e.g. for a popup opened by a click to appear and size itself.
+    let mut frames_to_draw = FRAMES_AFTER_INPUT;
+    let mut last_draw = Instant::now();
+    let mut title_dirty = false;
+
+    #[allow(deprecated)]
     event_loop
         .run(move |event, window_target| {
             match event {
@@ -122,35 +176,82 @@ fn main() {
                     last_frame = now;
                 }
                 Event::AboutToWait => {
-                    platform
-                        .prepare_frame(imgui.io_mut(), &window)
-                        .expect("Failed to prepare frame");
-                    window.request_redraw();
+                    // Background work and a blinking text cursor need frames without input, but only a few per second.
+                    let polling = app_state.has_background_work() || imgui.io().want_text_input;
+                    let poll_due = polling && last_draw.elapsed() >= IDLE_POLL_INTERVAL;
+                    if frames_to_draw > 0 || poll_due {
+                        frames_to_draw = frames_to_draw.saturating_sub(1);
+                        platform
+                            .prepare_frame(imgui.io_mut(), &window)
+                            .expect("Failed to prepare frame");
+                        window.request_redraw();
+                    }
+                    window_target.set_control_flow(if polling {
+                        ControlFlow::WaitUntil(last_draw + IDLE_POLL_INTERVAL)
+                    } else {
+                        ControlFlow::Wait
+                    });
                 }
                 Event::WindowEvent {
                     event: WindowEvent::RedrawRequested,
                     ..
                 } => {
+                    // Theme changes are requested by the UI and applied between frames.
+                    if app_state.config.theme != active_theme {
+                        active_theme = app_state.config.theme;
+                        apply_theme(&mut imgui, active_theme);
+                    }
+                    // Any settings changed in the UI are saved right away.
+                    if app_state.config != saved_config {
+                        saved_config = app_state.config.clone();
+                        if let Err(e) = config::save(&app_state.config) {
+                            app_state.log.push(LogEntry::error(format!("Error: Failed to save settings: {}", e)));
+                        }
+                    }
+
+                    last_draw = Instant::now();
                     let ui = imgui.new_frame();
 
                     render_main_window(ui, &mut app_state);
 
                     let mut target = display.draw();
-                    // Use the same background color as the custom style
-                    target.clear_color_srgb(0.13, 0.14, 0.15, 1.0);
+                    // Use the same background color as the active theme
+                    let [r, g, b, a] = active_theme.clear_color();
+                    target.clear_color_srgb(r, g, b, a);
                     platform.prepare_render(ui, &window);
                     let draw_data = imgui.render();
                     renderer
                         .render(&mut target, draw_data)
                         .expect("Rendering failed");
                     target.finish().expect("Failed to swap buffers");
+                    if std::mem::take(&mut app_state.needs_redraw) {
+                        frames_to_draw = frames_to_draw.max(FRAMES_AFTER_INPUT);
+                    }
+                    if app_state.dirty != title_dirty {
+                        title_dirty = app_state.dirty;
+                        window.set_title(&window_title(title_dirty));
+                    }
+                    if app_state.exit_requested {
+                        save_window_and_exit(&mut app_state, &window, window_target);
+                    }
                 }
                 Event::WindowEvent {
                     event: WindowEvent::CloseRequested,
                     ..
-                } => window_target.exit(),
+                } => {
+                    // With unsaved changes, the window only closes once the user confirmed it in the UI.
+                    if ask_before_discarding(&mut app_state, DiscardAction::Close) {
+                        frames_to_draw = FRAMES_AFTER_INPUT;
+                    } else {
+                        save_window_and_exit(&mut app_state, &window, window_target);
+                    }
+                }
                 event => {
                     platform.handle_event(imgui.io_mut(), &window, &event);
+                    // Any other window or device event may change what is shown.
+                    if matches!(event, Event::WindowEvent { .. }) {
+                        frames_to_draw = FRAMES_AFTER_INPUT;
+                    }
                 }
             }
         })
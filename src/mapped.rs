@@ -0,0 +1,145 @@
+//! This module applies patches to files through a memory map, without reading the whole image into memory.
+//!
+//! It is meant for very large raw binary images. The output file is created as a copy of the input and
+//! patched in place; the input is never modified. A header in front of the image is accounted for the
+//! same way the GUI loader does it (see `patcher::header_shifted`).
+
+use crate::patcher::{self, PatchStatus, PatcherError};
+use crate::patches::PatchSet;
+use crate::verify::{self, VerifyError};
+use crate::version::{self, VersionError};
+use memmap2::MmapMut;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// Errors raised while patching a memory-mapped file.
+#[derive(Debug, thiserror::Error)]
+pub enum MappedError {
+    #[error("The output file must differ from the input file.")]
+    SameFile,
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Version(#[from] VersionError),
+    #[error(transparent)]
+    Patcher(#[from] PatcherError),
+    #[error("Verification failed, do not flash this file: {0}")]
+    Verify(#[from] VerifyError),
+}
+
+impl MappedError {
+    /// Returns a stable, machine-readable code identifying the kind of error, e.g. the code of the
+    /// version or patcher error it wraps.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            MappedError::SameFile => "same_file",
+            MappedError::Io(_) => "io_error",
+            MappedError::Version(e) => e.error_code(),
+            MappedError::Patcher(e) => e.error_code(),
+            MappedError::Verify(e) => e.error_code(),
+        }
+    }
+}
+
+/// The result of a successful memory-mapped apply.
+#[derive(Debug)]
+pub struct MappedApply {
+    pub patch_set: &'static PatchSet,
+    /// One log line per applied patch.
+    pub log: Vec<String>,
+    /// Length of the output file, which was read back and compared after writing.
+    pub verified_len: usize,
+    /// Name and status of every patch in the written image, checked before it was unmapped.
+    pub statuses: Vec<(String, PatchStatus)>,
+}
+
+/// Copies `input` to `output`, then detects the version and applies all patches directly in the mapped output.
+///
+/// The patches are validated before anything is written. The map is flushed and the output is read
+/// back and compared with it before returning. If any step fails, the output file is removed.
+pub fn apply_patches_to_file(input: &Path, output: &Path) -> Result<MappedApply, MappedError> {
+    if output.exists() && fs::canonicalize(input)? == fs::canonicalize(output)? {
+        return Err(MappedError::SameFile);
+    }
+    fs::copy(input, output)?;
+    let result = patch_mapped(output);
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    result
+}
+
+fn patch_mapped(path: &Path) -> Result<MappedApply, MappedError> {
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    // SAFETY: the output file was just created by us; it is only modified through this map while it is alive.
+    let mut map = unsafe { MmapMut::map_mut(&file)? };
+    let detection = version::detect(&map)?;
+    let patch_set = patcher::header_shifted(&map, detection.patch_set, detection.offset_shift()).unwrap_or(detection.patch_set);
+    let mut log = patcher::apply_patches(&mut map, patch_set)?;
+    log.extend(patcher::version_change_warning(patch_set, &map));
+    map.flush()?;
+    verify::verify_written(path, &map)?;
+    Ok(MappedApply { patch_set, log, verified_len: map.len(), statuses: patcher::check_all_statuses(&map, patch_set) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patcher::check_patch_status;
+    use crate::testutil::{build_synthetic_firmware, builtin_patch_set};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ewsms43-mapped-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn applies_patches_to_a_copy_of_the_input() {
+        let patch_set = builtin_patch_set("ca430066");
+        let (input, output) = (temp_path("in.bin"), temp_path("out.bin"));
+        fs::write(&input, build_synthetic_firmware(&patch_set)).unwrap();
+
+        let applied = apply_patches_to_file(&input, &output).unwrap();
+        assert_eq!(applied.patch_set.version_string, "ca430066");
+        assert_eq!(applied.log.len(), 3);
+        assert!(applied.statuses.iter().all(|(_, status)| *status == PatchStatus::Patched), "{:?}", applied.statuses);
+        let patched = fs::read(&output).unwrap();
+        let unchanged = fs::read(&input).unwrap();
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        let all_patched = (PatchStatus::Patched, PatchStatus::Patched, PatchStatus::Patched);
+        assert_eq!(check_patch_status(&patched, &patch_set), all_patched);
+        assert_eq!(unchanged, build_synthetic_firmware(&patch_set));
+    }
+
+    #[test]
+    fn a_header_in_front_of_the_image_is_accounted_for() {
+        let patch_set = builtin_patch_set("ca430066");
+        let (input, output) = (temp_path("header-in.bin"), temp_path("header-out.bin"));
+        let mut original = vec![0x5A; 0x200];
+        original.extend(build_synthetic_firmware(&patch_set));
+        fs::write(&input, &original).unwrap();
+
+        let applied = apply_patches_to_file(&input, &output).unwrap();
+        let patched = fs::read(&output).unwrap();
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        assert_eq!(applied.patch_set.patches[0].offset, patch_set.patches[0].offset + 0x200);
+        assert_eq!(applied.verified_len, original.len());
+        assert_eq!(patched[..0x200], original[..0x200]);
+        assert_eq!(check_patch_status(&patched[0x200..], &patch_set).0, PatchStatus::Patched);
+    }
+
+    #[test]
+    fn failed_apply_removes_the_output_and_same_file_is_rejected() {
+        let (input, output) = (temp_path("bad-in.bin"), temp_path("bad-out.bin"));
+        fs::write(&input, vec![0xFF; 0x80000]).unwrap();
+
+        assert!(matches!(apply_patches_to_file(&input, &output), Err(MappedError::Version(_))));
+        assert!(!output.exists());
+        assert!(matches!(apply_patches_to_file(&input, &input), Err(MappedError::SameFile)));
+        fs::remove_file(&input).unwrap();
+    }
+}
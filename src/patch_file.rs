@@ -0,0 +1,340 @@
+//! This module loads patch sets from external TOML or JSON files, so new firmware revisions can be
+//! supported without recompiling.
+//!
+//! Byte lists are written as hex strings such as `"DA 0B 5A 1C"`; signatures may also contain `??`
+//! wildcards. Offsets and sizes may be numbers or strings; strings starting with `0x` are read as hex.
+//! See `patch_sets.example.toml` for the schema.
+//!
+//! Loaded patch sets use the same `&'static str` fields as the built-in ones. Their strings are leaked
+//! when loading, which is fine because patch sets are loaded once and kept for the whole program run.
+
+use crate::checksum::ChecksumScheme;
+use crate::patches::{Patch, PatchSet, RiskLevel, MS43_FLASH_BASE_ADDRESS};
+use crate::report::bytes_to_hex_string;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Errors that can occur while loading a patch set file.
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("Could not read the patch set file: {0}")]
+    Io(#[from] io::Error),
+    #[error("Unsupported patch set file extension '{0}'; use .toml or .json.")]
+    UnsupportedExtension(String),
+    #[error("Invalid TOML: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid {field} '{value}' in patch set '{version}'.")]
+    InvalidValue { version: String, field: &'static str, value: String },
+}
+
+/// File formats patch sets can be stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchFileFormat {
+    Toml,
+    Json,
+}
+
+impl PatchFileFormat {
+    /// Returns the format matching a file extension, ignoring case.
+    pub fn for_extension(extension: &str) -> Option<PatchFileFormat> {
+        match extension.to_ascii_lowercase().as_str() {
+            "toml" => Some(PatchFileFormat::Toml),
+            "json" => Some(PatchFileFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// A number written either as an integer or as a decimal or `0x` hex string.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Number {
+    Int(u64),
+    Text(String),
+}
+
+impl Number {
+    fn parse(&self) -> Option<usize> {
+        match self {
+            Number::Int(value) => usize::try_from(*value).ok(),
+            Number::Text(text) => {
+                let text = text.trim();
+                match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                    Some(hex) => usize::from_str_radix(hex, 16).ok(),
+                    None => text.parse().ok(),
+                }
+            }
+        }
+    }
+
+    fn hex(value: usize) -> Number {
+        Number::Text(format!("{:#X}", value))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PatchFile {
+    patch_sets: Vec<PatchSetEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PatchSetEntry {
+    version_string: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hardware_variant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expected_size: Option<Number>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base_address: Option<Number>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checksum_region: Option<(Number, Number)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checksum_offset: Option<Number>,
+    #[serde(default)]
+    checksum_scheme: ChecksumScheme,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    zeroize_regions: Vec<ZeroizeEntry>,
+    patches: Vec<PatchEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ZeroizeEntry {
+    name: String,
+    offset: Number,
+    length: Number,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PatchEntry {
+    name: String,
+    offset: Number,
+    original: String,
+    patched: String,
+    #[serde(default)]
+    risk: RiskLevel,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+}
+
+/// Parses a hex byte list such as `"DA 0B 5A 1C"` or `"DA0B5A1C"`.
+pub fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    let digits: String = text.split_whitespace().collect();
+    if digits.is_empty() || !digits.is_ascii() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..digits.len()).step_by(2).map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok()).collect()
+}
+
+/// Parses a signature such as `"DA 0B ?? 1C"`, where `??` is a wildcard byte.
+pub fn parse_signature(text: &str) -> Option<Vec<Option<u8>>> {
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    if tokens.is_empty() {
+        return None;
+    }
+    tokens
+        .into_iter()
+        .map(|token| match token {
+            "??" => Some(None),
+            _ if token.len() == 2 => u8::from_str_radix(token, 16).ok().map(Some),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Formats a signature the way `parse_signature` reads it.
+fn signature_to_string(signature: &[Option<u8>]) -> String {
+    let tokens: Vec<String> = signature.iter().map(|byte| byte.map_or("??".to_string(), |b| format!("{:02X}", b))).collect();
+    tokens.join(" ")
+}
+
+fn leak(text: String) -> &'static str {
+    text.leak()
+}
+
+impl PatchSetEntry {
+    fn into_patch_set(self) -> Result<PatchSet, LoadError> {
+        let version = self.version_string.clone();
+        let invalid = |field: &'static str, value: String| LoadError::InvalidValue { version: version.clone(), field, value };
+        let number = |field: &'static str, value: &Number| {
+            value.parse().ok_or_else(|| invalid(field, format!("{:?}", value)))
+        };
+
+        let patches = self
+            .patches
+            .into_iter()
+            .map(|entry| {
+                Ok(Patch {
+                    offset: number("offset", &entry.offset)?,
+                    original: parse_hex_bytes(&entry.original).ok_or_else(|| invalid("original bytes", entry.original.clone()))?,
+                    patched: parse_hex_bytes(&entry.patched).ok_or_else(|| invalid("patched bytes", entry.patched.clone()))?,
+                    signature: match &entry.signature {
+                        Some(text) => Some(parse_signature(text).ok_or_else(|| invalid("signature", text.clone()))?),
+                        None => None,
+                    },
+                    name: leak(entry.name),
+                    risk: entry.risk,
+                    description: leak(entry.description),
+                })
+            })
+            .collect::<Result<Vec<Patch>, LoadError>>()?;
+
+        Ok(PatchSet {
+            expected_size: self.expected_size.as_ref().map(|size| number("expected size", size)).transpose()?,
+            base_address: match &self.base_address {
+                Some(address) => number("base address", address)?,
+                None => MS43_FLASH_BASE_ADDRESS,
+            },
+            checksum_region: match &self.checksum_region {
+                Some((start, end)) => Some((number("checksum region", start)?, number("checksum region", end)?)),
+                None => None,
+            },
+            checksum_offset: self.checksum_offset.as_ref().map(|offset| number("checksum offset", offset)).transpose()?,
+            checksum_scheme: self.checksum_scheme,
+            zeroize_regions: self
+                .zeroize_regions
+                .into_iter()
+                .map(|entry| {
+                    Ok((number("zeroize offset", &entry.offset)?, number("zeroize length", &entry.length)?, leak(entry.name)))
+                })
+                .collect::<Result<Vec<_>, LoadError>>()?,
+            version_string: leak(self.version_string),
+            hardware_variant: self.hardware_variant.map(leak),
+            patches,
+        })
+    }
+
+    fn from_patch_set(set: &PatchSet) -> PatchSetEntry {
+        PatchSetEntry {
+            version_string: set.version_string.to_string(),
+            hardware_variant: set.hardware_variant.map(str::to_string),
+            expected_size: set.expected_size.map(Number::hex),
+            base_address: Some(Number::hex(set.base_address)),
+            checksum_region: set.checksum_region.map(|(start, end)| (Number::hex(start), Number::hex(end))),
+            checksum_offset: set.checksum_offset.map(Number::hex),
+            checksum_scheme: set.checksum_scheme,
+            zeroize_regions: set
+                .zeroize_regions
+                .iter()
+                .map(|&(offset, length, name)| ZeroizeEntry {
+                    name: name.to_string(),
+                    offset: Number::hex(offset),
+                    length: Number::hex(length),
+                })
+                .collect(),
+            patches: set
+                .patches
+                .iter()
+                .map(|patch| PatchEntry {
+                    name: patch.name.to_string(),
+                    offset: Number::hex(patch.offset),
+                    original: bytes_to_hex_string(&patch.original),
+                    patched: bytes_to_hex_string(&patch.patched),
+                    risk: patch.risk,
+                    description: patch.description.to_string(),
+                    signature: patch.signature.as_deref().map(signature_to_string),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Parses patch sets from the contents of a patch set file.
+pub fn parse_patch_sets(contents: &str, format: PatchFileFormat) -> Result<Vec<PatchSet>, LoadError> {
+    let file: PatchFile = match format {
+        PatchFileFormat::Toml => toml::from_str(contents)?,
+        PatchFileFormat::Json => serde_json::from_str(contents)?,
+    };
+    file.patch_sets.into_iter().map(PatchSetEntry::into_patch_set).collect()
+}
+
+/// Loads patch sets from a `.toml` or `.json` file.
+///
+/// The sets are only parsed; register them with `patches::register_patch_sets` to use them for detection.
+pub fn load_patch_sets_from_file(path: &Path) -> Result<Vec<PatchSet>, LoadError> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let format = PatchFileFormat::for_extension(extension).ok_or_else(|| LoadError::UnsupportedExtension(extension.to_string()))?;
+    parse_patch_sets(&fs::read_to_string(path)?, format)
+}
+
+/// Writes patch sets in the given file format. Offsets and sizes are written as hex strings.
+pub fn serialize_patch_sets(sets: &[&PatchSet], format: PatchFileFormat) -> String {
+    let file = PatchFile { patch_sets: sets.iter().map(|set| PatchSetEntry::from_patch_set(set)).collect() };
+    match format {
+        PatchFileFormat::Toml => toml::to_string_pretty(&file).expect("patch sets are always representable in TOML"),
+        PatchFileFormat::Json => serde_json::to_string_pretty(&file).expect("patch sets are always representable in JSON"),
+    }
+}
+
+/// Writes a single patch set as a TOML patch set file, ready to be loaded as an external set.
+///
+/// Use `serialize_patch_sets` for JSON or for several sets in one file.
+pub fn serialize_patch_set(set: &PatchSet) -> String {
+    serialize_patch_sets(&[set], PatchFileFormat::Toml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patches::{self, get_all_patch_sets};
+    use crate::testutil::build_synthetic_firmware;
+
+    #[test]
+    fn builtin_sets_round_trip_through_toml_and_json() {
+        let mut sets = get_all_patch_sets();
+        sets[1].checksum_scheme = ChecksumScheme::Crc32;
+        sets[2].zeroize_regions.push((0x100, 17, "VIN"));
+        let refs: Vec<&PatchSet> = sets.iter().collect();
+        for format in [PatchFileFormat::Toml, PatchFileFormat::Json] {
+            let text = serialize_patch_sets(&refs, format);
+            assert_eq!(parse_patch_sets(&text, format).unwrap(), sets, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn a_single_exported_set_loads_back() {
+        let set = get_all_patch_sets().remove(0);
+        let text = serialize_patch_set(&set);
+        assert!(text.contains(&format!("version_string = \"{}\"", set.version_string)));
+        assert!(text.contains("original = \"DA 0B 5A 1C\""));
+        assert_eq!(parse_patch_sets(&text, PatchFileFormat::Toml).unwrap(), vec![set]);
+    }
+
+    #[test]
+    fn example_file_parses_and_registered_sets_are_detected() {
+        let sets = parse_patch_sets(include_str!("../patch_sets.example.toml"), PatchFileFormat::Toml).unwrap();
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].version_string, "ca439901");
+        assert_eq!(sets[0].base_address, MS43_FLASH_BASE_ADDRESS);
+        assert_eq!(sets[0].patches[0].original, vec![0xDA, 0x0B, 0x5A, 0x1C]);
+        assert_eq!(sets[0].patches[2].risk, RiskLevel::Low);
+
+        let data = build_synthetic_firmware(&sets[0]);
+        assert!(crate::detect_version(&data).is_err());
+        assert_eq!(patches::register_patch_sets(sets.clone()), Ok(1));
+        assert_eq!(crate::detect_version(&data).unwrap().version_string, "ca439901");
+        // Registering the same version twice is refused.
+        assert!(patches::register_patch_sets(sets).is_err());
+    }
+
+    #[test]
+    fn invalid_values_are_reported() {
+        let json = r#"{"patch_sets": [{"version_string": "ca43x", "patches": [
+            {"name": "DTC", "offset": 1234, "original": "0Z", "patched": "00"}]}]}"#;
+        let error = parse_patch_sets(json, PatchFileFormat::Json).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid original bytes '0Z' in patch set 'ca43x'.");
+
+        let json = r#"{"patch_sets": [{"version_string": "ca43x", "patches": [
+            {"name": "DTC", "offset": "0xZZ", "original": "02", "patched": "00"}]}]}"#;
+        assert!(matches!(parse_patch_sets(json, PatchFileFormat::Json), Err(LoadError::InvalidValue { field: "offset", .. })));
+        assert!(matches!(parse_patch_sets("patch_sets = 1", PatchFileFormat::Toml), Err(LoadError::Toml(_))));
+        assert_eq!(parse_signature("DA ?? 1C"), Some(vec![Some(0xDA), None, Some(0x1C)]));
+        assert_eq!(parse_signature("DA ?"), None);
+        assert!(matches!(load_patch_sets_from_file(Path::new("sets.yaml")), Err(LoadError::UnsupportedExtension(_))));
+    }
+}
@@ -1,20 +1,74 @@
 //! This module contains the core logic for applying and reverting patches to the firmware binary.
 
-use crate::patches::{Patch, PatchSet};
+use crate::checksum::{self, crc32, ChecksumError};
+use crate::patches::{self, Patch, PatchSet};
+use crate::report::bytes_to_hex_string;
+use crate::version;
 
+/// Errors that can occur while applying or reverting patches.
 #[derive(Debug, thiserror::Error)]
 pub enum PatcherError {
+    /// The bytes at a patch offset do not match the state required by the operation.
     #[error("Validation failed: Original data mismatch at offset {offset:#X}. Expected {expected:02X?}, found {found:02X?}. The file may be of the wrong version or already modified.")]
     ValidationMismatch {
         offset: usize,
         expected: Vec<u8>,
         found: Vec<u8>,
     },
+    /// The file ends before the end of a patch region.
     #[error("File is too small to apply patch '{patch_name}' at offset {offset:#X}.")]
     FileTooSmall {
         patch_name: &'static str,
         offset: usize,
     },
+    /// The checksum layout of the patch set does not fit the file.
+    #[error("Cannot update the checksum: {0}")]
+    Checksum(#[from] ChecksumError),
+    /// A post-patch hook rejected the image.
+    #[error("Post-patch hook failed: {0}")]
+    HookFailed(String),
+    /// The original and patched bytes of a patch differ in length. Patches overwrite bytes in place,
+    /// so writing such a patch would corrupt the bytes after it.
+    #[error("Patch '{patch_name}' has {original_len} original bytes but {patched_len} patched bytes; only same-length patches can be written in place.")]
+    LengthMismatch {
+        patch_name: &'static str,
+        original_len: usize,
+        patched_len: usize,
+    },
+    /// The image patched in place differs from the one built independently by `build_expected_patched`.
+    #[error("Cross-check failed: the patched data differs from the independently built image at offset {offset:#X}.")]
+    CrossCheckMismatch {
+        offset: usize,
+        /// Bytes of the independently built image from `offset`, at most `CROSS_CHECK_CONTEXT` of them.
+        expected: Vec<u8>,
+        /// Bytes of the data patched in place from `offset`, at most `CROSS_CHECK_CONTEXT` of them.
+        found: Vec<u8>,
+    },
+    /// A zeroize region of the patch set ends after the end of the file.
+    #[error("The '{region}' region at offset {offset:#X} ({length} bytes) does not fit in the file, so nothing was cleared.")]
+    ZeroizeOutOfBounds {
+        region: &'static str,
+        offset: usize,
+        length: usize,
+    },
+}
+
+/// Number of bytes from the first difference reported by `PatcherError::CrossCheckMismatch`.
+pub const CROSS_CHECK_CONTEXT: usize = 8;
+
+impl PatcherError {
+    /// Returns a stable, machine-readable code identifying the kind of error.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            PatcherError::ValidationMismatch { .. } => "validation_mismatch",
+            PatcherError::FileTooSmall { .. } => "file_too_small",
+            PatcherError::Checksum(_) => "checksum",
+            PatcherError::HookFailed(_) => "hook_failed",
+            PatcherError::LengthMismatch { .. } => "length_mismatch",
+            PatcherError::CrossCheckMismatch { .. } => "cross_check_mismatch",
+            PatcherError::ZeroizeOutOfBounds { .. } => "zeroize_out_of_bounds",
+        }
+    }
 }
 
 /// Validates that the original bytes in the data slice match the expected original bytes for all patches in the set.
@@ -29,24 +83,107 @@ pub enum PatcherError {
 /// * `Ok(())` if all original bytes match.
 /// * `Err(PatcherError)` if there is a mismatch or the file is too small.
 pub fn validate_pre_patch(data: &[u8], patch_set: &PatchSet) -> Result<(), PatcherError> {
+    check_lengths(&patch_set.patches)?;
     for patch in &patch_set.patches {
-        let end_offset = patch.offset + patch.original.len();
-        if data.len() < end_offset {
-            return Err(PatcherError::FileTooSmall { patch_name: patch.name, offset: patch.offset });
-        }
-
-        let actual_bytes = &data[patch.offset..end_offset];
-        if actual_bytes != patch.original.as_slice() {
-            return Err(PatcherError::ValidationMismatch {
-                offset: patch.offset,
-                expected: patch.original.clone(),
-                found: actual_bytes.to_vec(),
-            });
-        }
+        resolve_patch_offset(data, patch)?;
     }
     Ok(())
 }
 
+/// Checks that every patch overwrites its bytes in place, i.e. that its original and patched bytes
+/// have the same length.
+///
+/// `patches::validate_patch_set` rejects such sets too; this guards sets built without validation.
+fn check_lengths(patches: &[Patch]) -> Result<(), PatcherError> {
+    match patches.iter().find(|patch| patch.original.len() != patch.patched.len()) {
+        Some(patch) => Err(PatcherError::LengthMismatch {
+            patch_name: patch.name,
+            original_len: patch.original.len(),
+            patched_len: patch.patched.len(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Returns true if the original bytes of `patch` are at `offset` and the whole patch region fits.
+fn original_bytes_at(data: &[u8], patch: &Patch, offset: usize) -> bool {
+    data.len() >= offset + region_len(patch) && data[offset..].starts_with(&patch.original)
+}
+
+/// Scans `data` for the signature of `patch` and returns the offset where it starts.
+///
+/// Returns `None` if the patch has no signature, or if the signature is found nowhere or more than
+/// once, since an ambiguous match cannot tell where the patch belongs.
+pub fn locate_patch(data: &[u8], patch: &Patch) -> Option<usize> {
+    let signature = patch.signature.as_ref().filter(|signature| !signature.is_empty())?;
+    find_unique(data, signature)
+}
+
+/// Like `locate_patch`, but for a patch that was already applied there: the start of the signature is
+/// replaced by the patched bytes, since the signature itself describes the original bytes.
+fn locate_applied_patch(data: &[u8], patch: &Patch) -> Option<usize> {
+    let signature = patch.signature.as_ref().filter(|signature| !signature.is_empty())?;
+    let len = signature.len().max(patch.patched.len());
+    let applied: Vec<Option<u8>> = (0..len).map(|i| patch.patched.get(i).map_or_else(|| signature[i], |&b| Some(b))).collect();
+    find_unique(data, &applied)
+}
+
+/// Returns the offset of the only match of `signature` in `data`, where `None` bytes match anything.
+fn find_unique(data: &[u8], signature: &[Option<u8>]) -> Option<usize> {
+    let mut matches = data
+        .windows(signature.len())
+        .enumerate()
+        .filter(|(_, window)| window.iter().zip(signature).all(|(byte, expected)| expected.is_none_or(|e| e == *byte)))
+        .map(|(offset, _)| offset);
+    let first = matches.next()?;
+    matches.next().is_none().then_some(first)
+}
+
+/// Returns the offset at which `patch` can be applied: its fixed offset if the original bytes are
+/// there, otherwise the offset recovered from its signature.
+fn resolve_patch_offset(data: &[u8], patch: &Patch) -> Result<usize, PatcherError> {
+    if original_bytes_at(data, patch, patch.offset) {
+        return Ok(patch.offset);
+    }
+    if let Some(offset) = locate_patch(data, patch).filter(|offset| original_bytes_at(data, patch, *offset)) {
+        return Ok(offset);
+    }
+    // The patched bytes may be longer than the original ones; both must fit before anything is written.
+    if data.len() < patch.offset + region_len(patch) {
+        return Err(PatcherError::FileTooSmall { patch_name: patch.name, offset: patch.offset });
+    }
+    Err(PatcherError::ValidationMismatch {
+        offset: patch.offset,
+        expected: patch.original.clone(),
+        found: data[patch.offset..patch.offset + patch.original.len()].to_vec(),
+    })
+}
+
+/// Returns the offset at which `patch` can be reverted: where `find_patch` sees it applied, which is
+/// its fixed offset or the one recovered from its signature.
+fn resolve_applied_offset(data: &[u8], patch: &Patch) -> Result<usize, PatcherError> {
+    if let Some((offset, PatchStatus::Patched)) = find_patch(data, patch) {
+        return Ok(offset);
+    }
+    if data.len() < patch.offset + region_len(patch) {
+        return Err(PatcherError::FileTooSmall { patch_name: patch.name, offset: patch.offset });
+    }
+    Err(PatcherError::ValidationMismatch {
+        offset: patch.offset,
+        expected: patch.patched.clone(),
+        found: data[patch.offset..patch.offset + patch.patched.len()].to_vec(),
+    })
+}
+
+/// Formats the log line for a patch written at `offset` by `verb`, noting when it was found by signature.
+fn written_log(verb: &str, patch: &Patch, offset: usize) -> String {
+    if offset == patch.offset {
+        format!("  {} {} patch at offset {:#X}", verb, patch.name, offset)
+    } else {
+        format!("  {} {} patch at offset {:#X} (found by signature; expected at {:#X})", verb, patch.name, offset, patch.offset)
+    }
+}
+
 /// Applies the patches to the firmware data after validation.
 ///
 /// This function first validates the data and then applies all patches.
@@ -61,26 +198,151 @@ pub fn validate_pre_patch(data: &[u8], patch_set: &PatchSet) -> Result<(), Patch
 /// * `Ok(())` on success.
 /// * `Err(PatcherError)` if validation fails.
 pub fn apply_patches(data: &mut [u8], patch_set: &PatchSet) -> Result<Vec<String>, PatcherError> {
+    apply_patches_with_progress(data, patch_set, &mut |_, _| {})
+}
+
+/// Same as `apply_patches`, calling `progress(done, total)` after each patch is written.
+///
+/// Nothing is reported if validation fails, since nothing is written then.
+pub fn apply_patches_with_progress(
+    data: &mut [u8],
+    patch_set: &PatchSet,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Vec<String>, PatcherError> {
     // First, ensure the file is in the expected state before modifying anything.
-    validate_pre_patch(data, patch_set)?;
+    check_lengths(&patch_set.patches)?;
+    let offsets = patch_set
+        .patches
+        .iter()
+        .map(|patch| resolve_patch_offset(data, patch))
+        .collect::<Result<Vec<usize>, PatcherError>>()?;
+    check_checksum_layout(data, patch_set)?;
 
     let mut logs = Vec::new();
 
     // If validation passes, apply all patches.
+    let total = patch_set.patches.len();
+    for (index, (patch, offset)) in patch_set.patches.iter().zip(offsets).enumerate() {
+        data[offset..offset + patch.patched.len()].copy_from_slice(&patch.patched);
+        logs.push(written_log("Applied", patch, offset));
+        progress(index + 1, total);
+    }
+    logs.extend(update_checksum(data, patch_set)?);
+
+    Ok(logs)
+}
+
+/// Builds the fully patched image of `data` in a new buffer, without touching `data`.
+///
+/// The original bytes are validated as by `apply_patches`, but the image is assembled byte by byte
+/// from an overlay of the patched bytes instead of being written in place, so the two can be compared
+/// by `cross_check_applied`. The checksum is updated like `apply_patches` does.
+pub fn build_expected_patched(data: &[u8], patch_set: &PatchSet) -> Result<Vec<u8>, PatcherError> {
+    check_lengths(&patch_set.patches)?;
+    let mut overlay: Vec<Option<u8>> = vec![None; data.len()];
+    for patch in &patch_set.patches {
+        let offset = resolve_patch_offset(data, patch)?;
+        for (index, &byte) in patch.patched.iter().enumerate() {
+            overlay[offset + index] = Some(byte);
+        }
+    }
+    check_checksum_layout(data, patch_set)?;
+    let mut expected: Vec<u8> = data.iter().zip(overlay).map(|(&original, patched)| patched.unwrap_or(original)).collect();
+    update_checksum(&mut expected, patch_set)?;
+    Ok(expected)
+}
+
+/// Checks that `after`, the result of applying `patch_set` in place to `before`, matches the image
+/// built independently by `build_expected_patched`.
+///
+/// Fails with `PatcherError::CrossCheckMismatch` at the first differing offset.
+pub fn cross_check_applied(before: &[u8], after: &[u8], patch_set: &PatchSet) -> Result<(), PatcherError> {
+    let expected = build_expected_patched(before, patch_set)?;
+    let offset = match expected.iter().zip(after).position(|(a, b)| a != b) {
+        Some(offset) => offset,
+        None if expected.len() == after.len() => return Ok(()),
+        None => expected.len().min(after.len()),
+    };
+    let context = |bytes: &[u8]| bytes.iter().skip(offset).take(CROSS_CHECK_CONTEXT).copied().collect();
+    Err(PatcherError::CrossCheckMismatch { offset, expected: context(&expected), found: context(after) })
+}
+
+/// Fills every `zeroize_regions` entry of the patch set with 0x00, e.g. to strip a stored VIN before a
+/// dump is shared or moved to another car.
+///
+/// This is independent of the patches and only meant to run when the user opted in. Every region is
+/// checked against the file size before anything is cleared, and the checksum is updated afterwards.
+/// Returns one log line per cleared region.
+pub fn zeroize(data: &mut [u8], patch_set: &PatchSet) -> Result<Vec<String>, PatcherError> {
+    for &(offset, length, region) in &patch_set.zeroize_regions {
+        if offset.checked_add(length).is_none_or(|end| end > data.len()) {
+            return Err(PatcherError::ZeroizeOutOfBounds { region, offset, length });
+        }
+    }
+    check_checksum_layout(data, patch_set)?;
+    let mut logs = Vec::new();
+    for &(offset, length, region) in &patch_set.zeroize_regions {
+        data[offset..offset + length].fill(0x00);
+        logs.push(format!("  Cleared the {} region at offset {:#X} ({} bytes)", region, offset, length));
+    }
+    logs.extend(update_checksum(data, patch_set)?);
+    Ok(logs)
+}
+
+
+/// Applies the patches without validating the original bytes first.
+///
+/// **Forcing can brick an ECU.** The patched bytes are written even where the file does not contain the
+/// expected original bytes, which is only correct if the differing bytes are known to be harmless. Every
+/// patch written over unexpected bytes is logged as a warning. The file size is still checked for all
+/// patches before anything is written.
+///
+/// # Arguments
+///
+/// * `data` - A mutable slice of the firmware binary data.
+/// * `patch_set` - The `PatchSet` to apply.
+///
+/// # Returns
+///
+/// * `Ok(Vec<String>)` on success with a vector of log messages.
+/// * `Err(PatcherError::FileTooSmall)` if any patch does not fit in the file.
+pub fn force_apply_patches(data: &mut [u8], patch_set: &PatchSet) -> Result<Vec<String>, PatcherError> {
+    check_lengths(&patch_set.patches)?;
+    for patch in &patch_set.patches {
+        if data.len() < patch.offset + patch.original.len().max(patch.patched.len()) {
+            return Err(PatcherError::FileTooSmall { patch_name: patch.name, offset: patch.offset });
+        }
+    }
+    check_checksum_layout(data, patch_set)?;
+
+    let mut logs = Vec::new();
+
     for patch in &patch_set.patches {
         let end_offset = patch.offset + patch.patched.len();
-        if data.len() < end_offset {
-            // This check is somewhat redundant due to validate_pre_patch, but good for safety.
-             return Err(PatcherError::FileTooSmall { patch_name: patch.name, offset: patch.offset });
+        // Forced patches are always written at their fixed offset, so only that offset is looked at.
+        match status_at(data, patch, patch.offset) {
+            PatchStatus::Unpatched => {
+                logs.push(format!("  Applied {} patch at offset {:#X}", patch.name, patch.offset));
+            }
+            PatchStatus::Patched => {
+                logs.push(format!("  {} patch at offset {:#X} was already applied", patch.name, patch.offset));
+            }
+            PatchStatus::Unknown => {
+                logs.push(format!(
+                    "Warning: FORCED {} patch at offset {:#X} over unexpected bytes {:02X?}",
+                    patch.name,
+                    patch.offset,
+                    &data[patch.offset..end_offset]
+                ));
+            }
         }
         data[patch.offset..end_offset].copy_from_slice(&patch.patched);
-        logs.push(format!("  Applied {} patch at offset {:#X}", patch.name, patch.offset));
     }
+    logs.extend(update_checksum(data, patch_set)?);
 
     Ok(logs)
 }
 
-
 /// Reverts the patches from the firmware data.
 ///
 /// This function validates that the data is currently patched, then restores the original bytes.
@@ -95,38 +357,151 @@ pub fn apply_patches(data: &mut [u8], patch_set: &PatchSet) -> Result<Vec<String
 /// * `Ok(Vec<String>)` on success with a vector of log messages.
 /// * `Err(PatcherError)` if the data does not appear to be patched as expected.
 pub fn revert_patches(data: &mut [u8], patch_set: &PatchSet) -> Result<Vec<String>, PatcherError> {
-    // Validate that the file is currently in a patched state before reverting.
-    for patch in &patch_set.patches {
-        let end_offset = patch.offset + patch.patched.len();
-        if data.len() < end_offset {
-            return Err(PatcherError::FileTooSmall { patch_name: patch.name, offset: patch.offset });
-        }
+    revert_patches_with_progress(data, patch_set, &mut |_, _| {})
+}
+
+/// Same as `revert_patches`, calling `progress(done, total)` after each patch is reverted.
+pub fn revert_patches_with_progress(
+    data: &mut [u8],
+    patch_set: &PatchSet,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Vec<String>, PatcherError> {
+    // Validate that the file is currently in a patched state before reverting. Patches applied at an
+    // offset recovered from their signature are reverted there.
+    check_lengths(&patch_set.patches)?;
+    let offsets = patch_set
+        .patches
+        .iter()
+        .map(|patch| resolve_applied_offset(data, patch))
+        .collect::<Result<Vec<usize>, PatcherError>>()?;
+
+    check_checksum_layout(data, patch_set)?;
+
+    let mut logs = Vec::new();
+
+    // If validation passes, revert all patches.
+    let total = patch_set.patches.len();
+    for (index, (patch, offset)) in patch_set.patches.iter().zip(offsets).enumerate() {
+        data[offset..offset + patch.original.len()].copy_from_slice(&patch.original);
+        logs.push(written_log("Reverted", patch, offset));
+        progress(index + 1, total);
+    }
+    logs.extend(update_checksum(data, patch_set)?);
+
+    Ok(logs)
+}
+
+
+/// Reverts only the patches that are currently applied, leaving unpatched regions untouched.
+///
+/// This allows recovering a file that is in a partially-patched state. Patches in the `Patched`
+/// state are restored to their original bytes, patches already `Unpatched` are skipped, and any
+/// patch in the `Unknown` state aborts the whole operation before anything is modified.
+///
+/// # Arguments
+///
+/// * `data` - A mutable slice of the firmware binary data.
+/// * `patch_set` - The `PatchSet` that was (partially) applied.
+///
+/// # Returns
+///
+/// * `Ok(Vec<String>)` on success with a log line for every reverted or skipped patch.
+/// * `Err(PatcherError)` if any patch region matches neither its original nor its patched bytes.
+pub fn revert_patches_partial(data: &mut [u8], patch_set: &PatchSet) -> Result<Vec<String>, PatcherError> {
+    let mut locations = Vec::with_capacity(patch_set.patches.len());
 
-        let actual_bytes = &data[patch.offset..end_offset];
-        if actual_bytes != patch.patched.as_slice() {
+    // Validate every patch region before touching the data.
+    check_lengths(&patch_set.patches)?;
+    for patch in &patch_set.patches {
+        let Some(location) = find_patch(data, patch) else {
+            if data.len() < patch.offset + region_len(patch) {
+                return Err(PatcherError::FileTooSmall { patch_name: patch.name, offset: patch.offset });
+            }
+            let end_offset = patch.offset + patch.patched.len();
             return Err(PatcherError::ValidationMismatch {
                 offset: patch.offset,
                 expected: patch.patched.clone(),
-                found: actual_bytes.to_vec(),
+                found: data[patch.offset..end_offset].to_vec(),
             });
-        }
+        };
+        locations.push(location);
     }
+    check_checksum_layout(data, patch_set)?;
 
     let mut logs = Vec::new();
 
-    // If validation passes, revert all patches.
-    for patch in &patch_set.patches {
-        let end_offset = patch.offset + patch.original.len();
-         if data.len() < end_offset {
-             return Err(PatcherError::FileTooSmall { patch_name: patch.name, offset: patch.offset });
+    for (patch, (offset, status)) in patch_set.patches.iter().zip(locations) {
+        if status == PatchStatus::Patched {
+            data[offset..offset + patch.original.len()].copy_from_slice(&patch.original);
+            logs.push(written_log("Reverted", patch, offset));
+        } else {
+            logs.push(format!("  Skipped {} patch at offset {:#X} (not applied)", patch.name, offset));
         }
-        data[patch.offset..end_offset].copy_from_slice(&patch.original);
-        logs.push(format!("  Reverted {} patch at offset {:#X}", patch.name, patch.offset));
     }
+    logs.extend(update_checksum(data, patch_set)?);
 
     Ok(logs)
 }
 
+/// Applies a single patch after checking that its region still holds the original bytes.
+///
+/// Unlike `apply_patches`, this does not update the checksum, since a lone patch does not know its
+/// patch set. Call `recalculate_checksum` afterwards.
+///
+/// # Returns
+///
+/// * `Ok(String)` with a log line on success.
+/// * `Err(PatcherError)` if the patch does not fit or the region is not in its original state.
+pub fn apply_patch(data: &mut [u8], patch: &Patch) -> Result<String, PatcherError> {
+    check_lengths(std::slice::from_ref(patch))?;
+    let offset = resolve_patch_offset(data, patch)?;
+    data[offset..offset + patch.patched.len()].copy_from_slice(&patch.patched);
+    Ok(written_log("Applied", patch, offset))
+}
+
+/// Reverts a single patch after checking that its region holds the patched bytes.
+///
+/// Like `apply_patch`, this leaves the checksum to `recalculate_checksum`.
+///
+/// # Returns
+///
+/// * `Ok(String)` with a log line on success.
+/// * `Err(PatcherError)` if the patch does not fit or is not currently applied.
+pub fn revert_patch(data: &mut [u8], patch: &Patch) -> Result<String, PatcherError> {
+    check_lengths(std::slice::from_ref(patch))?;
+    let offset = resolve_applied_offset(data, patch)?;
+    data[offset..offset + patch.original.len()].copy_from_slice(&patch.original);
+    Ok(written_log("Reverted", patch, offset))
+}
+
+/// Recomputes the 16-bit additive checksum of the patch set's checksum region and writes it to the
+/// checksum offset, so the DME accepts the image at boot.
+///
+/// Patch sets without a known checksum layout are skipped. `apply_patches` and the revert functions
+/// already call this; it is only needed after changing the data by other means.
+pub fn recalculate_checksum(data: &mut [u8], patch_set: &PatchSet) -> Result<(), PatcherError> {
+    update_checksum(data, patch_set).map(|_| ())
+}
+
+/// Fails if the patch set has a checksum layout that does not fit `data`. Run before writing anything.
+fn check_checksum_layout(data: &[u8], patch_set: &PatchSet) -> Result<(), PatcherError> {
+    if let Some(layout) = patch_set.checksum_layout() {
+        checksum::verify_checksum(data, &layout)?;
+    }
+    Ok(())
+}
+
+/// Recalculates the checksum if the layout is known and returns a log line with the old and new value.
+fn update_checksum(data: &mut [u8], patch_set: &PatchSet) -> Result<Option<String>, PatcherError> {
+    let Some(layout) = patch_set.checksum_layout() else {
+        return Ok(None);
+    };
+    let verification = checksum::recalculate_checksum(data, &layout)?;
+    Ok(Some(format!(
+        "  Updated checksum at offset {:#X}: {:#06X} -> {:#06X}",
+        layout.checksum_offset, verification.stored, verification.computed
+    )))
+}
 
 /// Represents the state of a single patch location in the file.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -139,11 +514,21 @@ pub enum PatchStatus {
     Unknown,
 }
 
-/// Checks the status of each patch in the set against the provided data.
+/// Checks the status of every patch in the set against the provided data.
+///
+/// Returns `(patch name, status)` pairs in patch order, so sets with any number and names of patches,
+/// such as external ones, are fully reported.
+pub fn check_all_statuses(data: &[u8], patch_set: &PatchSet) -> Vec<(String, PatchStatus)> {
+    patch_set.patches.iter().map(|patch| (patch.name.to_string(), get_patch_status(data, patch))).collect()
+}
+
+/// Checks the status of the Jump, Code and DTC patches of the built-in sets against the provided data.
 ///
 /// # Returns
 ///
 /// A tuple `(PatchStatus, PatchStatus, PatchStatus)` corresponding to the status of (Jump, Code, DTC).
+/// A patch the set does not have is reported as `Unknown`, and other patches are ignored; use
+/// `check_all_statuses` for sets with other patches.
 pub fn check_patch_status(data: &[u8], patch_set: &PatchSet) -> (PatchStatus, PatchStatus, PatchStatus) {
     let mut status = (PatchStatus::Unknown, PatchStatus::Unknown, PatchStatus::Unknown);
 
@@ -162,24 +547,879 @@ pub fn check_patch_status(data: &[u8], patch_set: &PatchSet) -> (PatchStatus, Pa
     status
 }
 
-/// Helper function to determine the status of a single patch.
-fn get_patch_status(data: &[u8], patch: &Patch) -> PatchStatus {
-    // Check against patched bytes first. Note that lengths can differ.
-    let patched_end = patch.offset + patch.patched.len();
-    if data.len() >= patched_end {
-        if &data[patch.offset..patched_end] == patch.patched.as_slice() {
-            return PatchStatus::Patched;
+/// Determines the status of a single patch.
+///
+/// If the region at the fixed offset is in neither state, a patch with a signature is looked for
+/// where its signature is found, in either state, just as applying and reverting do.
+pub fn get_patch_status(data: &[u8], patch: &Patch) -> PatchStatus {
+    find_patch(data, patch).map_or(PatchStatus::Unknown, |(_, status)| status)
+}
+
+/// Returns the offset of `patch` in `data`: where `get_patch_status` finds it, which is its fixed
+/// offset unless it was found by its signature. The fixed offset if it is found nowhere.
+pub fn patch_offset(data: &[u8], patch: &Patch) -> usize {
+    find_patch(data, patch).map_or(patch.offset, |(offset, _)| offset)
+}
+
+/// Returns where `patch` is and whether it is applied there: at its fixed offset if the region there
+/// is in a known state, otherwise at the unique match of its signature, in the original or in the
+/// applied state. `None` if it is found nowhere.
+fn find_patch(data: &[u8], patch: &Patch) -> Option<(usize, PatchStatus)> {
+    match status_at(data, patch, patch.offset) {
+        PatchStatus::Unknown => {}
+        status => return Some((patch.offset, status)),
+    }
+    let unpatched = locate_patch(data, patch).filter(|&offset| status_at(data, patch, offset) == PatchStatus::Unpatched);
+    if let Some(offset) = unpatched {
+        return Some((offset, PatchStatus::Unpatched));
+    }
+    let patched = locate_applied_patch(data, patch).filter(|&offset| status_at(data, patch, offset) == PatchStatus::Patched);
+    patched.map(|offset| (offset, PatchStatus::Patched))
+}
+
+/// Determines the status of the region of `patch` starting at `offset`.
+fn status_at(data: &[u8], patch: &Patch, offset: usize) -> PatchStatus {
+    // The original and patched bytes can differ in length. A patch is only in a known state if the longer
+    // of the two fits in the file, since otherwise it can neither be applied nor reverted.
+    let region_end = offset + region_len(patch);
+    if data.len() < region_end {
+        return PatchStatus::Unknown;
+    }
+    let region = &data[offset..region_end];
+
+    // Check against patched bytes first. A shorter patch leaves the tail of the original bytes in place.
+    let original_tail = patch.original.get(patch.patched.len()..).unwrap_or_default();
+    if region.starts_with(&patch.patched) && region[patch.patched.len()..] == *original_tail {
+        return PatchStatus::Patched;
+    }
+
+    // Check against original bytes. Bytes beyond them, covered only by a longer patch, can be anything.
+    if region.starts_with(&patch.original) {
+        return PatchStatus::Unpatched;
+    }
+
+    PatchStatus::Unknown
+}
+
+/// Returns the number of bytes covered by a patch: the longer of its original and patched bytes.
+fn region_len(patch: &Patch) -> usize {
+    patch.original.len().max(patch.patched.len())
+}
+
+
+/// Finer classification of a patch region in the `Unknown` state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownKind {
+    /// The file ends before the end of the patch region.
+    Truncated,
+    /// Most bytes still match the original or patched state; the region was altered slightly.
+    Modified,
+    /// Most bytes match neither state; the region was most likely patched by a different tool.
+    Foreign,
+}
+
+impl UnknownKind {
+    /// Returns a short lowercase label for display.
+    pub fn label(self) -> &'static str {
+        match self {
+            UnknownKind::Truncated => "truncated",
+            UnknownKind::Modified => "modified",
+            UnknownKind::Foreign => "foreign",
         }
     }
 
-    // Check against original bytes.
-    let original_end = patch.offset + patch.original.len();
-    if data.len() >= original_end {
-        if &data[patch.offset..original_end] == patch.original.as_slice() {
-            return PatchStatus::Unpatched;
+    /// Returns a one-sentence explanation for the user.
+    pub fn explanation(self) -> &'static str {
+        match self {
+            UnknownKind::Truncated => "The file is too short to contain this patch region.",
+            UnknownKind::Modified => "The region is close to a known state but some bytes were changed, e.g. by a manual edit or a bad read.",
+            UnknownKind::Foreign => "The region differs from both known states; it was most likely patched by another tool.",
         }
     }
+}
 
-    PatchStatus::Unknown
+/// Classifies a patch region that `get_patch_status` reports as `Unknown`.
+///
+/// The region is compared byte-by-byte with both the original and the patched bytes. If at least half of
+/// the bytes still match one of them, the region is considered `Modified`, otherwise `Foreign`.
+pub fn classify_unknown(data: &[u8], patch: &Patch) -> UnknownKind {
+    let len = patch.original.len().max(patch.patched.len());
+    if data.len() < patch.offset + len {
+        return UnknownKind::Truncated;
+    }
+
+    let matching = |expected: &[u8]| {
+        expected.iter().zip(&data[patch.offset..]).filter(|(a, b)| a == b).count()
+    };
+    let best = matching(&patch.original).max(matching(&patch.patched));
+    if best * 2 >= len {
+        UnknownKind::Modified
+    } else {
+        UnknownKind::Foreign
+    }
+}
+
+/// Lists the positions where `found` differs from `expected`, one line each, as
+/// `0x7099B: EXP 02 -> GOT 01`. `base_offset` is the file offset of the first byte.
+///
+/// A byte missing from the shorter slice is shown as `--`. Returns an empty string if the slices are equal.
+pub fn format_byte_diff(expected: &[u8], found: &[u8], base_offset: usize) -> String {
+    let show = |byte: Option<&u8>| byte.map_or("--".to_string(), |b| format!("{:02X}", b));
+    (0..expected.len().max(found.len()))
+        .filter(|&i| expected.get(i) != found.get(i))
+        .map(|i| format!("{:#07X}: EXP {} -> GOT {}", base_offset + i, show(expected.get(i)), show(found.get(i))))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Describes why a patch region matches neither its original nor its patched bytes.
+///
+/// Single-byte patches (such as DTC) are reported as a plain value, longer ones as byte lists.
+pub fn describe_patch_mismatch(data: &[u8], patch: &Patch) -> String {
+    let len = patch.original.len().max(patch.patched.len());
+    if data.len() < patch.offset + len {
+        return format!(
+            "{} region at offset {:#X} extends past the end of the file ({} bytes).",
+            patch.name,
+            patch.offset,
+            data.len()
+        );
+    }
+
+    if patch.original.len() == 1 && patch.patched.len() == 1 {
+        format!(
+            "{} byte at offset {:#X} is {:#04X}, expected {:#04X} (original) or {:#04X} (patched).",
+            patch.name, patch.offset, data[patch.offset], patch.original[0], patch.patched[0]
+        )
+    } else {
+        format!(
+            "{} bytes at offset {:#X} are {:02X?}, expected {:02X?} (original) or {:02X?} (patched).",
+            patch.name,
+            patch.offset,
+            &data[patch.offset..patch.offset + len],
+            patch.original,
+            patch.patched
+        )
+    }
+}
+
+/// Describes the bytes of a patch region: its offset, the expected original and patched bytes and the
+/// bytes currently in `data`.
+///
+/// In the Unknown state, every byte that matches neither the original nor the patched value is listed.
+pub fn describe_patch_bytes(data: &[u8], patch: &Patch) -> String {
+    let offset = patch_offset(data, patch);
+    let end = (offset + region_len(patch)).min(data.len());
+    let current = data.get(offset..end).unwrap_or_default();
+    let mut text = format!(
+        "{} patch at offset {:#X}\nOriginal: {}\nPatched:  {}\nCurrent:  {}",
+        patch.name,
+        offset,
+        bytes_to_hex_string(&patch.original),
+        bytes_to_hex_string(&patch.patched),
+        if current.is_empty() { "(past the end of the file)".to_string() } else { bytes_to_hex_string(current) }
+    );
+
+    if get_patch_status(data, patch) == PatchStatus::Unknown {
+        for (i, &byte) in current.iter().enumerate() {
+            let (original, patched) = (patch.original.get(i), patch.patched.get(i));
+            if original != Some(&byte) && patched != Some(&byte) {
+                let expected: Vec<String> = [original, patched].into_iter().flatten().map(|b| format!("{:02X}", b)).collect();
+                text.push_str(&format!(
+                    "\n  {:#X}: {:02X}, expected {}",
+                    offset + i,
+                    byte,
+                    expected.join(" or ")
+                ));
+            }
+        }
+        if current.len() < region_len(patch) {
+            text.push_str(&format!("\n  {} byte(s) missing at the end of the file", region_len(patch) - current.len()));
+        }
+    }
+    text
+}
+
+/// What applying one patch would change, as reported by `preview_patches`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchPreview {
+    pub name: &'static str,
+    /// Offset the patch would be written at. Differs from the patch offset if it was found by signature.
+    pub offset: usize,
+    /// Bytes currently in the file that would be overwritten.
+    pub original: Vec<u8>,
+    /// Bytes that would be written.
+    pub patched: Vec<u8>,
+    /// Number of bytes that would actually change.
+    pub bytes_changed: usize,
+    /// Why `apply_patches` would refuse this patch, if it would.
+    pub warning: Option<String>,
+}
+
+/// Reports what `apply_patches` would write, without modifying `data`.
+///
+/// Patches whose original bytes do not match are still listed, with the validation error as a
+/// warning, so every region can be reviewed at once.
+///
+/// # Returns
+///
+/// * `Ok(Vec<PatchPreview>)` with one entry per patch, in order.
+/// * `Err(PatcherError::FileTooSmall)` if a patch does not fit in the file.
+pub fn preview_patches(data: &[u8], patch_set: &PatchSet) -> Result<Vec<PatchPreview>, PatcherError> {
+    patch_set
+        .patches
+        .iter()
+        .map(|patch| {
+            let (offset, warning) = match resolve_patch_offset(data, patch) {
+                Ok(offset) => (offset, None),
+                Err(e @ PatcherError::ValidationMismatch { .. }) => (patch.offset, Some(e.to_string())),
+                Err(e) => return Err(e),
+            };
+            let original = data[offset..offset + patch.patched.len()].to_vec();
+            let bytes_changed = original.iter().zip(&patch.patched).filter(|(a, b)| a != b).count();
+            Ok(PatchPreview { name: patch.name, offset, original, patched: patch.patched.clone(), bytes_changed, warning })
+        })
+        .collect()
+}
+
+/// A patch region whose bytes were changed by an apply or revert operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchChange {
+    pub name: &'static str,
+    pub offset: usize,
+    /// Number of bytes written, i.e. the length of the patch.
+    pub len: usize,
+}
+
+/// Lists the patches whose regions differ between `before` and `after`, at the offset they are at in `after`.
+pub fn changed_patches(before: &[u8], after: &[u8], patch_set: &PatchSet) -> Vec<PatchChange> {
+    patch_set
+        .patches
+        .iter()
+        .map(|patch| (patch, patch_offset(after, patch)))
+        .filter(|&(patch, offset)| {
+            let end = offset + patch.patched.len();
+            before.get(offset..end) != after.get(offset..end)
+        })
+        .map(|(patch, offset)| PatchChange { name: patch.name, offset, len: patch.patched.len() })
+        .collect()
+}
+
+/// Builds a one-line headline for an operation, e.g.
+/// "Applied 3 patches, 13 bytes changed, checksum updated 0xAB12 -> 0xCD34".
+///
+/// `verb` is the past-tense action, such as "Applied" or "Reverted". The checksum is the stored value
+/// of the patch set's checksum layout. Sets without a known layout report the CRC32 of the whole
+/// image instead, e.g. "image CRC32 0x12345678 -> 0x9ABCDEF0", which the ECU does not check.
+pub fn summarize_changes(verb: &str, before: &[u8], after: &[u8], patch_set: &PatchSet) -> String {
+    let changes = changed_patches(before, after, patch_set);
+    let bytes: usize = changes.iter().map(|change| change.len).sum();
+    format!(
+        "{} {} patch{}, {} byte{} changed, {}",
+        verb,
+        changes.len(),
+        if changes.len() == 1 { "" } else { "es" },
+        bytes,
+        if bytes == 1 { "" } else { "s" },
+        describe_checksum_change(before, after, patch_set)
+    )
+}
+
+/// Describes how the stored checksum of `patch_set` changed from `before` to `after`, or how the image
+/// CRC32 changed if the layout is unknown or does not fit the data.
+fn describe_checksum_change(before: &[u8], after: &[u8], patch_set: &PatchSet) -> String {
+    if let Some(layout) = patch_set.checksum_layout() {
+        if let (Ok(old), Ok(new)) = (checksum::verify_checksum(before, &layout), checksum::verify_checksum(after, &layout)) {
+            let digits = 2 + 2 * layout.scheme.width();
+            return match old.stored == new.stored {
+                true => format!("checksum unchanged {:#0digits$X}", old.stored),
+                false => format!("checksum updated {:#0digits$X} -> {:#0digits$X}", old.stored, new.stored),
+            };
+        }
+    }
+    format!("image CRC32 {:#010X} -> {:#010X}", crc32(before), crc32(after))
+}
+
+/// Returns `patch_set` with its offsets moved by `shift`, the distance of the version string from its
+/// usual offset (see `version::Detection::offset_shift`), if `data` looks like a dump with a header
+/// of that length.
+///
+/// The shift is only accounted for if it is positive and every shifted patch is in a known state;
+/// otherwise `None` is returned and the unshifted set should be used.
+pub fn header_shifted(data: &[u8], patch_set: &'static PatchSet, shift: isize) -> Option<&'static PatchSet> {
+    if shift <= 0 {
+        return None;
+    }
+    let shifted = patches::with_header(patch_set, shift.unsigned_abs());
+    shifted.patches.iter().all(|patch| get_patch_status(data, patch) != PatchStatus::Unknown).then_some(shifted)
 }
 
+/// Re-runs version detection on the patched image and returns a warning if it no longer finds `detected`,
+/// the version detected before patching.
+///
+/// A patch that touches the version string points to a wrong offset in the patch definition, so a
+/// changed or lost version is a red flag even when the patches themselves validated.
+pub fn version_change_warning(detected: &PatchSet, after: &[u8]) -> Option<String> {
+    match version::detect_version(after) {
+        // Compare only the version string: variants of one version share it.
+        Ok(new) if new.version_string == detected.version_string => None,
+        Ok(new) => Some(format!(
+            "Warning: The detected version changed from {} to {} after patching. A patch offset is probably wrong; do not flash this file.",
+            detected.version_string, new.version_string
+        )),
+        Err(e) => Some(format!(
+            "Warning: Version {} is no longer detected after patching ({}). A patch offset is probably wrong; do not flash this file.",
+            detected.version_string, e
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patches::{get_all_patch_sets, RiskLevel};
+    use crate::testutil::{build_synthetic_firmware, builtin_patch_set};
+
+    const ALL_PATCHED: (PatchStatus, PatchStatus, PatchStatus) = (PatchStatus::Patched, PatchStatus::Patched, PatchStatus::Patched);
+    const ALL_UNPATCHED: (PatchStatus, PatchStatus, PatchStatus) = (PatchStatus::Unpatched, PatchStatus::Unpatched, PatchStatus::Unpatched);
+
+    #[test]
+    fn apply_then_revert_round_trips_every_builtin_set() {
+        for patch_set in get_all_patch_sets() {
+            let pristine = build_synthetic_firmware(&patch_set);
+            let mut data = pristine.clone();
+            assert_eq!(check_patch_status(&data, &patch_set), ALL_UNPATCHED);
+
+            let logs = apply_patches(&mut data, &patch_set).unwrap();
+            assert_eq!(logs.len(), patch_set.patches.len());
+            assert_eq!(check_patch_status(&data, &patch_set), ALL_PATCHED);
+
+            revert_patches(&mut data, &patch_set).unwrap();
+            assert_eq!(check_patch_status(&data, &patch_set), ALL_UNPATCHED);
+            assert_eq!(data, pristine, "round trip changed bytes for {}", patch_set.version_string);
+        }
+    }
+
+    #[test]
+    fn every_patch_is_reported_by_name_whatever_it_is_called() {
+        let mut patch_set = builtin_patch_set("ca430037");
+        patch_set.patches[2].name = "Fault code";
+        let extra = Patch { name: "Extra", offset: 0x100, original: vec![0xFF], patched: vec![0x00], ..patch_set.patches[2].clone() };
+        patch_set.patches.push(extra);
+        let mut data = build_synthetic_firmware(&builtin_patch_set("ca430037"));
+        apply_patch(&mut data, &patch_set.patches[0]).unwrap();
+
+        let statuses = check_all_statuses(&data, &patch_set);
+        let expected = [
+            ("Jump", PatchStatus::Patched),
+            ("Code", PatchStatus::Unpatched),
+            ("Fault code", PatchStatus::Unpatched),
+            ("Extra", PatchStatus::Unpatched),
+        ];
+        assert_eq!(statuses, expected.map(|(name, status)| (name.to_string(), status)));
+        // The compatibility tuple only knows the three built-in names.
+        assert_eq!(check_patch_status(&data, &patch_set), (PatchStatus::Patched, PatchStatus::Unpatched, PatchStatus::Unknown));
+    }
+
+    #[test]
+    fn the_expected_image_matches_the_applied_data_and_divergence_is_located() {
+        let patch_set = builtin_patch_set("ca430037");
+        let original = build_synthetic_firmware(&patch_set);
+        let mut applied = original.clone();
+        apply_patches(&mut applied, &patch_set).unwrap();
+        assert_eq!(build_expected_patched(&original, &patch_set).unwrap(), applied);
+        assert!(cross_check_applied(&original, &applied, &patch_set).is_ok());
+
+        let code = &patch_set.patches[1];
+        applied[code.offset + 1] ^= 0xFF;
+        match cross_check_applied(&original, &applied, &patch_set) {
+            Err(PatcherError::CrossCheckMismatch { offset, expected, found }) => {
+                assert_eq!(offset, code.offset + 1);
+                assert_eq!(expected[0], code.patched[1]);
+                assert_eq!(found[0], code.patched[1] ^ 0xFF);
+                assert_eq!(found.len(), CROSS_CHECK_CONTEXT);
+            }
+            other => panic!("expected a cross-check mismatch, got {:?}", other),
+        }
+        // The expected image is built from validated originals only.
+        assert!(matches!(build_expected_patched(&applied, &patch_set), Err(PatcherError::ValidationMismatch { .. })));
+    }
+
+    #[test]
+    fn progress_is_reported_once_per_patch() {
+        let patch_set = builtin_patch_set("ca430037");
+        let mut data = build_synthetic_firmware(&patch_set);
+        let mut calls = Vec::new();
+        apply_patches_with_progress(&mut data, &patch_set, &mut |done, total| calls.push((done, total))).unwrap();
+        assert_eq!(calls, [(1, 3), (2, 3), (3, 3)]);
+
+        calls.clear();
+        revert_patches_with_progress(&mut data, &patch_set, &mut |done, total| calls.push((done, total))).unwrap();
+        assert_eq!(calls.last(), Some(&(3, 3)));
+
+        // A failed validation writes nothing and reports nothing.
+        calls.clear();
+        assert!(revert_patches_with_progress(&mut data, &patch_set, &mut |done, total| calls.push((done, total))).is_err());
+        assert!(calls.is_empty());
+    }
+
+    #[test]
+    fn apply_rejects_mismatched_original_bytes() {
+        let patch_set = builtin_patch_set("ca430037");
+        let mut data = build_synthetic_firmware(&patch_set);
+        let jump = &patch_set.patches[0];
+        data[jump.offset] ^= 0xFF;
+        let before = data.clone();
+
+        match apply_patches(&mut data, &patch_set) {
+            Err(PatcherError::ValidationMismatch { offset, expected, found }) => {
+                assert_eq!(offset, jump.offset);
+                assert_eq!(expected, jump.original);
+                assert_eq!(found[0], jump.original[0] ^ 0xFF);
+            }
+            other => panic!("expected ValidationMismatch, got {:?}", other),
+        }
+        assert_eq!(data, before, "failed apply must not modify the data");
+        assert_eq!(check_patch_status(&data, &patch_set).0, PatchStatus::Unknown);
+    }
+
+    #[test]
+    fn errors_have_stable_codes() {
+        let patch_set = builtin_patch_set("ca430037");
+        let mut data = build_synthetic_firmware(&patch_set);
+        assert_eq!(revert_patches(&mut data, &patch_set).unwrap_err().error_code(), "validation_mismatch");
+        data.truncate(0x100);
+        assert_eq!(apply_patches(&mut data, &patch_set).unwrap_err().error_code(), "file_too_small");
+    }
+
+    #[test]
+    fn patches_of_different_lengths_are_not_written() {
+        let mut patch_set = builtin_patch_set("ca430037");
+        let mut data = build_synthetic_firmware(&patch_set);
+        // Same-length patches are written in place.
+        assert!(apply_patch(&mut data.clone(), &patch_set.patches[2]).is_ok());
+
+        patch_set.patches[2].patched = vec![0x00, 0x00];
+        let before = data.clone();
+        let expected = PatcherError::LengthMismatch { patch_name: "DTC", original_len: 1, patched_len: 2 };
+        assert_eq!(apply_patches(&mut data, &patch_set).unwrap_err().to_string(), expected.to_string());
+        assert_eq!(apply_patch(&mut data, &patch_set.patches[2]).unwrap_err().error_code(), "length_mismatch");
+        assert!(matches!(force_apply_patches(&mut data, &patch_set), Err(PatcherError::LengthMismatch { .. })));
+        assert!(matches!(revert_patches(&mut data, &patch_set), Err(PatcherError::LengthMismatch { .. })));
+        assert_eq!(data, before);
+    }
+
+    #[test]
+    fn apply_twice_is_rejected() {
+        let patch_set = builtin_patch_set("ca430066");
+        let mut data = build_synthetic_firmware(&patch_set);
+        apply_patches(&mut data, &patch_set).unwrap();
+        assert!(matches!(apply_patches(&mut data, &patch_set), Err(PatcherError::ValidationMismatch { .. })));
+    }
+
+    #[test]
+    fn revert_rejects_unpatched_file() {
+        let patch_set = builtin_patch_set("ca430069");
+        let mut data = build_synthetic_firmware(&patch_set);
+        assert!(matches!(revert_patches(&mut data, &patch_set), Err(PatcherError::ValidationMismatch { .. })));
+    }
+
+    #[test]
+    fn partial_revert_restores_only_applied_patches() {
+        let patch_set = builtin_patch_set("ca430056");
+        let pristine = build_synthetic_firmware(&patch_set);
+        let mut data = pristine.clone();
+        for patch in &patch_set.patches[..2] {
+            data[patch.offset..patch.offset + patch.patched.len()].copy_from_slice(&patch.patched);
+        }
+        assert_eq!(
+            check_patch_status(&data, &patch_set),
+            (PatchStatus::Patched, PatchStatus::Patched, PatchStatus::Unpatched)
+        );
+        assert!(revert_patches(&mut data.clone(), &patch_set).is_err());
+
+        let logs = revert_patches_partial(&mut data, &patch_set).unwrap();
+        assert!(logs[0].contains("Reverted Jump"));
+        assert!(logs[1].contains("Reverted Code"));
+        assert!(logs[2].contains("Skipped DTC"));
+        assert_eq!(data, pristine);
+    }
+
+    #[test]
+    fn partial_revert_rejects_unknown_state_without_modifying() {
+        let patch_set = builtin_patch_set("ca430056");
+        let mut data = build_synthetic_firmware(&patch_set);
+        apply_patches(&mut data, &patch_set).unwrap();
+        let dtc = &patch_set.patches[2];
+        data[dtc.offset] = 0x01;
+        let before = data.clone();
+
+        assert!(matches!(
+            revert_patches_partial(&mut data, &patch_set),
+            Err(PatcherError::ValidationMismatch { offset, .. }) if offset == dtc.offset
+        ));
+        assert_eq!(data, before);
+    }
+
+    #[test]
+    fn mismatch_description_reports_the_actual_bytes() {
+        let patch_set = builtin_patch_set("ca430037");
+        let mut data = build_synthetic_firmware(&patch_set);
+        let dtc = &patch_set.patches[2];
+        data[dtc.offset] = 0x01;
+        assert_eq!(
+            describe_patch_mismatch(&data, dtc),
+            "DTC byte at offset 0x7099B is 0x01, expected 0x02 (original) or 0x00 (patched)."
+        );
+
+        let jump = &patch_set.patches[0];
+        data[jump.offset + 3] = 0xAA;
+        assert!(describe_patch_mismatch(&data, jump).contains("are [DA, 0B, 5A, AA]"));
+
+        data.truncate(dtc.offset);
+        assert!(describe_patch_mismatch(&data, dtc).contains("extends past the end of the file"));
+    }
+
+    #[test]
+    fn byte_diff_lists_only_the_differing_positions() {
+        assert_eq!(format_byte_diff(&[0xDA, 0x0B, 0x5A, 0x1C], &[0xDA, 0x0B, 0x5A, 0xAA], 0x54E8C), "0x54E8F: EXP 1C -> GOT AA");
+        assert_eq!(
+            format_byte_diff(&[0x00, 0x00, 0xFF], &[0xDA, 0x00], 0x5350C),
+            "0x5350C: EXP 00 -> GOT DA\n0x5350E: EXP FF -> GOT --"
+        );
+        assert_eq!(format_byte_diff(&[0x02], &[0x02], 0x7099B), "");
+    }
+
+    #[test]
+    fn force_apply_overwrites_unexpected_bytes_with_a_warning() {
+        let patch_set = builtin_patch_set("ca430069");
+        let mut data = build_synthetic_firmware(&patch_set);
+        let dtc = &patch_set.patches[2];
+        data[dtc.offset] = 0x01;
+        assert!(apply_patches(&mut data.clone(), &patch_set).is_err());
+
+        let logs = force_apply_patches(&mut data, &patch_set).unwrap();
+        assert_eq!(check_patch_status(&data, &patch_set), ALL_PATCHED);
+        assert!(logs[2].starts_with("Warning: FORCED DTC patch"));
+        assert!(!logs[0].starts_with("Warning"));
+    }
+
+    #[test]
+    fn force_apply_still_checks_the_file_size() {
+        let patch_set = builtin_patch_set("ca430069");
+        let mut data = build_synthetic_firmware(&patch_set);
+        let dtc = &patch_set.patches[2];
+        data.truncate(dtc.offset);
+        let before = data.clone();
+        assert!(matches!(force_apply_patches(&mut data, &patch_set), Err(PatcherError::FileTooSmall { patch_name: "DTC", .. })));
+        assert_eq!(data, before, "nothing may be written when a patch does not fit");
+    }
+
+    #[test]
+    fn unknown_regions_are_classified() {
+        let patch_set = builtin_patch_set("ca430037");
+        let mut data = build_synthetic_firmware(&patch_set);
+        let code = &patch_set.patches[1];
+        data[code.offset + 7] = 0x12;
+        assert_eq!(classify_unknown(&data, code), UnknownKind::Modified);
+
+        data[code.offset..code.offset + 8].copy_from_slice(&[0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88]);
+        assert_eq!(classify_unknown(&data, code), UnknownKind::Foreign);
+
+        data.truncate(code.offset + 4);
+        assert_eq!(classify_unknown(&data, code), UnknownKind::Truncated);
+    }
+
+    #[test]
+    fn truncated_file_reports_file_too_small() {
+        let patch_set = builtin_patch_set("ca430037");
+        let mut data = build_synthetic_firmware(&patch_set);
+        let jump = &patch_set.patches[0];
+        data.truncate(jump.offset + 1);
+
+        assert!(matches!(
+            apply_patches(&mut data, &patch_set),
+            Err(PatcherError::FileTooSmall { patch_name: "Jump", .. })
+        ));
+        assert!(matches!(
+            revert_patches(&mut data, &patch_set),
+            Err(PatcherError::FileTooSmall { patch_name: "Jump", .. })
+        ));
+        assert_eq!(check_patch_status(&data, &patch_set).0, PatchStatus::Unknown);
+    }
+
+    #[test]
+    fn summary_counts_changed_patches_and_bytes() {
+        let patch_set = builtin_patch_set("ca430037");
+        let before = build_synthetic_firmware(&patch_set);
+        let mut after = before.clone();
+        apply_patches(&mut after, &patch_set).unwrap();
+
+        let summary = summarize_changes("Applied", &before, &after, &patch_set);
+        assert!(summary.starts_with("Applied 3 patches, 13 bytes changed, image CRC32 0x"), "{}", summary);
+        assert_eq!(changed_patches(&before, &before, &patch_set), vec![]);
+
+        let mut reverted = after.clone();
+        revert_patches_partial(&mut reverted, &patch_set).unwrap();
+        let dtc = &patch_set.patches[2];
+        reverted[dtc.offset] = dtc.patched[0];
+        assert!(summarize_changes("Reverted", &after, &reverted, &patch_set).starts_with("Reverted 2 patches, 12 bytes changed"));
+
+        // With a known layout, the stored checksum is reported instead of the image CRC32.
+        let mut with_layout = patch_set.clone();
+        with_layout.checksum_region = Some((0x50000, 0x60000));
+        with_layout.checksum_offset = Some(0x7FFFE);
+        let mut updated = before.clone();
+        apply_patches(&mut updated, &with_layout).unwrap();
+        let stored = checksum::verify_checksum(&updated, &with_layout.checksum_layout().unwrap()).unwrap().stored;
+        assert_eq!(
+            summarize_changes("Applied", &before, &updated, &with_layout),
+            format!("Applied 3 patches, 13 bytes changed, checksum updated 0xFFFF -> {:#06X}", stored)
+        );
+        assert!(summarize_changes("Applied", &before, &before, &with_layout).ends_with("checksum unchanged 0xFFFF"));
+    }
+
+    #[test]
+    fn status_handles_patches_longer_than_the_original_near_the_end_of_the_file() {
+        let patch = Patch { name: "DTC", offset: 2, original: vec![0x02], patched: vec![0x00, 0x00], risk: RiskLevel::Low, description: "", signature: None };
+        let set = PatchSet {
+            version_string: "test",
+            hardware_variant: None,
+            expected_size: None,
+            base_address: 0,
+            checksum_region: None,
+            checksum_offset: None,
+            checksum_scheme: Default::default(),
+            zeroize_regions: Vec::new(),
+            patches: vec![patch.clone()],
+        };
+
+        // The file ends right after the original byte, so the longer patch region does not fit.
+        let data = vec![0xAA, 0xAA, 0x02];
+        assert_eq!(get_patch_status(&data, &patch), PatchStatus::Unknown);
+
+        let mut data = vec![0xAA, 0xAA, 0x02, 0x55];
+        assert_eq!(get_patch_status(&data, &patch), PatchStatus::Unpatched);
+        assert_eq!(get_patch_status(&[0xAA, 0xAA, 0x00, 0x00], &patch), PatchStatus::Patched);
+        // The status can be read, but writing would overwrite the byte after the original one.
+        assert!(matches!(apply_patches(&mut data, &set), Err(PatcherError::LengthMismatch { .. })));
+        assert_eq!(data, vec![0xAA, 0xAA, 0x02, 0x55]);
+    }
+
+    #[test]
+    fn status_handles_patches_shorter_than_the_original() {
+        let patch = Patch { name: "DTC", offset: 0, original: vec![0x00, 0x02], patched: vec![0x01], risk: RiskLevel::Low, description: "", signature: None };
+        assert_eq!(get_patch_status(&[0x00, 0x02], &patch), PatchStatus::Unpatched);
+        assert_eq!(get_patch_status(&[0x01, 0x02], &patch), PatchStatus::Patched);
+        // The patched byte alone is not enough: the untouched tail must still hold the original bytes.
+        assert_eq!(get_patch_status(&[0x01, 0x7F], &patch), PatchStatus::Unknown);
+        assert_eq!(get_patch_status(&[0x01], &patch), PatchStatus::Unknown);
+    }
+
+    #[test]
+    fn version_change_after_patching_is_flagged() {
+        let patch_set = builtin_patch_set("ca430056");
+        let mut after = build_synthetic_firmware(&patch_set);
+        apply_patches(&mut after, &patch_set).unwrap();
+        assert_eq!(version_change_warning(&patch_set, &after), None);
+
+        let offset = version::VERSION_STRING_OFFSET;
+        after[offset..offset + 8].copy_from_slice(b"ca430037");
+        let warning = version_change_warning(&patch_set, &after).unwrap();
+        assert!(warning.contains("from ca430056 to ca430037"), "{}", warning);
+
+        after[offset..offset + 8].fill(0xFF);
+        let warning = version_change_warning(&patch_set, &after).unwrap();
+        assert!(warning.contains("ca430056 is no longer detected"), "{}", warning);
+    }
+
+    #[test]
+    fn byte_description_lists_the_differing_bytes() {
+        let patch_set = builtin_patch_set("ca430056");
+        let mut data = build_synthetic_firmware(&patch_set);
+        let code = &patch_set.patches[1];
+        let text = describe_patch_bytes(&data, code);
+        assert!(text.contains(&format!("Current:  {}", bytes_to_hex_string(&code.original))), "{}", text);
+        assert_eq!(text.lines().count(), 4);
+
+        data[code.offset + 1] = 0x5A;
+        let text = describe_patch_bytes(&data, code);
+        assert!(text.contains(&format!("{:#X}: 5A, expected", code.offset + 1)), "{}", text);
+        assert_eq!(text.lines().count(), 5);
+
+        let text = describe_patch_bytes(&data[..code.offset + 1], code);
+        assert!(text.contains("byte(s) missing"), "{}", text);
+    }
+
+    #[test]
+    fn checksum_is_recalculated_after_apply_and_revert() {
+        let mut patch_set = builtin_patch_set("ca430056");
+        patch_set.checksum_region = Some((0x50000, 0x60000));
+        patch_set.checksum_offset = Some(0x7FFFE);
+        let layout = patch_set.checksum_layout().unwrap();
+        let pristine = build_synthetic_firmware(&patch_set);
+        let mut data = pristine.clone();
+
+        let logs = apply_patches(&mut data, &patch_set).unwrap();
+        assert!(logs.last().unwrap().starts_with("  Updated checksum at offset 0x7FFFE: 0xFFFF -> "), "{:?}", logs);
+        let after_apply = checksum::verify_checksum(&data, &layout).unwrap();
+        assert!(after_apply.is_valid());
+        assert_eq!(after_apply.stored, checksum::byte_sum16(&data[0x50000..0x60000]) as u32);
+
+        revert_patches(&mut data, &patch_set).unwrap();
+        assert!(checksum::verify_checksum(&data, &layout).unwrap().is_valid());
+        assert_eq!(data[..0x7FFFE], pristine[..0x7FFFE]);
+
+        // Sets without a known layout leave the rest of the file alone.
+        let plain = builtin_patch_set("ca430056");
+        let mut data = pristine.clone();
+        apply_patches(&mut data, &plain).unwrap();
+        assert_eq!(data[0x7FFFE..], pristine[0x7FFFE..]);
+    }
+
+    #[test]
+    fn a_checksum_layout_beyond_the_file_is_rejected_before_writing() {
+        let mut patch_set = builtin_patch_set("ca430056");
+        patch_set.checksum_region = Some((0x50000, 0x60000));
+        patch_set.checksum_offset = Some(0x90000);
+        let pristine = build_synthetic_firmware(&patch_set);
+        let mut data = pristine.clone();
+
+        let error = apply_patches(&mut data, &patch_set).unwrap_err();
+        assert!(matches!(error, PatcherError::Checksum(ChecksumError::OutOfBounds { .. })));
+        assert_eq!(data, pristine);
+    }
+
+    #[test]
+    fn single_patches_are_applied_and_reverted_independently() {
+        let patch_set = builtin_patch_set("ca430056");
+        let pristine = build_synthetic_firmware(&patch_set);
+        let mut data = pristine.clone();
+        let (jump, dtc) = (&patch_set.patches[0], &patch_set.patches[2]);
+
+        assert_eq!(apply_patch(&mut data, jump).unwrap(), format!("  Applied Jump patch at offset {:#X}", jump.offset));
+        assert_eq!(check_patch_status(&data, &patch_set), (PatchStatus::Patched, PatchStatus::Unpatched, PatchStatus::Unpatched));
+        // The region no longer holds the original bytes, so a second apply is refused.
+        assert!(matches!(apply_patch(&mut data, jump), Err(PatcherError::ValidationMismatch { .. })));
+        assert!(matches!(revert_patch(&mut data, dtc), Err(PatcherError::ValidationMismatch { .. })));
+
+        revert_patch(&mut data, jump).unwrap();
+        assert_eq!(data, pristine);
+    }
+
+    #[test]
+    fn patches_shifted_by_a_header_are_found_by_signature() {
+        let mut patch_set = builtin_patch_set("ca430056");
+        let code_offset = patch_set.patches[1].offset;
+        for patch in &mut patch_set.patches {
+            let mut signature: Vec<Option<u8>> = patch.original.iter().copied().map(Some).collect();
+            if signature.len() > 1 {
+                signature[1] = None;
+            }
+            patch.signature = Some(signature);
+        }
+        // The erased flash around the Code patch looks like its original bytes; one byte of context makes it unique.
+        patch_set.patches[1].signature.as_mut().unwrap().push(Some(0xAA));
+        // The one-byte DTC patch needs context as well, or its patched byte is found all over the file.
+        let dtc_offset = patch_set.patches[2].offset;
+        patch_set.patches[2].signature.as_mut().unwrap().push(Some(0xBB));
+        let mut pristine = build_synthetic_firmware(&patch_set);
+        pristine[code_offset + 8] = 0xAA;
+        pristine[dtc_offset + 1] = 0xBB;
+
+        let mut shifted = vec![0xFF; 0x200];
+        shifted.extend_from_slice(&pristine[..pristine.len() - 0x200]);
+        let jump = &patch_set.patches[0];
+        assert_eq!(locate_patch(&shifted, jump), Some(jump.offset + 0x200));
+        assert!(validate_pre_patch(&shifted, &patch_set).is_ok());
+
+        let logs = apply_patches(&mut shifted, &patch_set).unwrap();
+        assert_eq!(
+            logs[0],
+            format!("  Applied Jump patch at offset {:#X} (found by signature; expected at {:#X})", jump.offset + 0x200, jump.offset)
+        );
+        for patch in &patch_set.patches {
+            let offset = patch.offset + 0x200;
+            assert_eq!(shifted[offset..offset + patch.patched.len()], patch.patched[..], "{}", patch.name);
+        }
+
+        // The relocated patches are seen as applied, and revert finds them the same way.
+        let statuses: Vec<PatchStatus> = check_all_statuses(&shifted, &patch_set).into_iter().map(|(_, status)| status).collect();
+        assert_eq!(statuses, [PatchStatus::Patched; 3]);
+        let mut single = shifted.clone();
+        assert_eq!(
+            revert_patch(&mut single, jump).unwrap(),
+            format!("  Reverted Jump patch at offset {:#X} (found by signature; expected at {:#X})", jump.offset + 0x200, jump.offset)
+        );
+        assert_eq!(get_patch_status(&single, jump), PatchStatus::Unpatched);
+        let applied = shifted.clone();
+        let logs = revert_patches(&mut shifted, &patch_set).unwrap();
+        assert_eq!(logs.len(), 3);
+        assert_eq!(changed_patches(&applied, &shifted, &patch_set)[0].offset, jump.offset + 0x200);
+        assert_eq!(shifted[..], [vec![0xFF; 0x200], pristine[..pristine.len() - 0x200].to_vec()].concat()[..]);
+        assert!(check_all_statuses(&shifted, &patch_set).iter().all(|(_, status)| *status == PatchStatus::Unpatched));
+    }
+
+    #[test]
+    fn missing_or_ambiguous_signatures_do_not_locate_a_patch() {
+        let mut patch_set = builtin_patch_set("ca430056");
+        let mut data = build_synthetic_firmware(&patch_set);
+        let jump_offset = patch_set.patches[0].offset;
+        data.copy_within(jump_offset..jump_offset + 4, jump_offset + 0x100);
+        data.copy_within(jump_offset..jump_offset + 4, jump_offset + 0x200);
+        data[jump_offset] = 0x00;
+        assert_eq!(locate_patch(&data, &patch_set.patches[0]), None);
+
+        // The original bytes are found twice, so neither copy can be trusted.
+        let jump = &mut patch_set.patches[0];
+        jump.signature = Some(jump.original.iter().copied().map(Some).collect());
+        assert_eq!(locate_patch(&data, jump), None);
+        assert!(matches!(validate_pre_patch(&data, &patch_set), Err(PatcherError::ValidationMismatch { .. })));
+    }
+
+    #[test]
+    fn preview_reports_changes_without_modifying_the_data() {
+        let patch_set = builtin_patch_set("ca430056");
+        let mut data = build_synthetic_firmware(&patch_set);
+        let code = &patch_set.patches[1];
+        data[code.offset] = 0x12;
+        let before = data.clone();
+
+        let previews = preview_patches(&data, &patch_set).unwrap();
+        assert_eq!(data, before);
+        assert_eq!(previews.len(), 3);
+        assert_eq!((previews[0].name, previews[0].offset, previews[0].bytes_changed), ("Jump", patch_set.patches[0].offset, 3));
+        assert_eq!(previews[0].warning, None);
+        // The modified Code region is still previewed, with the validation error as a warning.
+        assert_eq!(previews[1].original[0], 0x12);
+        assert_eq!(previews[1].bytes_changed, 8);
+        assert!(previews[1].warning.as_deref().is_some_and(|w| w.starts_with("Validation failed")));
+        assert_eq!(previews[2].bytes_changed, 1);
+
+        assert!(matches!(preview_patches(&data[..0x1000], &patch_set), Err(PatcherError::FileTooSmall { .. })));
+    }
+
+    #[test]
+    fn zeroize_clears_every_region_only_if_all_of_them_fit() {
+        let mut patch_set = builtin_patch_set("ca430056");
+        patch_set.zeroize_regions = vec![(0x100, 17, "VIN"), (0x7FF00, 0x100, "EWS")];
+        let mut data = build_synthetic_firmware(&patch_set);
+        data[0x100..0x111].copy_from_slice(b"WBAAA00000A000000");
+
+        let logs = zeroize(&mut data, &patch_set).unwrap();
+        assert_eq!(
+            logs,
+            ["  Cleared the VIN region at offset 0x100 (17 bytes)", "  Cleared the EWS region at offset 0x7FF00 (256 bytes)"]
+        );
+        assert!(data[0x100..0x111].iter().chain(&data[0x7FF00..]).all(|&byte| byte == 0x00));
+        assert_eq!(data[0x111], 0xFF);
+
+        // A region past the end of the file is refused before the first region is cleared.
+        let mut data = build_synthetic_firmware(&patch_set);
+        patch_set.zeroize_regions[1].1 = 0x101;
+        let error = zeroize(&mut data, &patch_set).unwrap_err();
+        assert!(matches!(error, PatcherError::ZeroizeOutOfBounds { region: "EWS", offset: 0x7FF00, length: 0x101 }));
+        assert_eq!(error.error_code(), "zeroize_out_of_bounds");
+        assert_eq!(data, build_synthetic_firmware(&patch_set));
+    }
+}
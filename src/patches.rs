@@ -1,83 +1,573 @@
 //! This module defines the data structures for patches and contains the hardcoded patch data for each supported firmware version.
 
-use std::collections::HashMap;
+use crate::checksum::{ChecksumRegion, ChecksumScheme, Endian};
+use std::collections::HashSet;
+use std::sync::{Mutex, RwLock};
+use std::fmt;
 use lazy_static::lazy_static;
 
+/// Size of a full 512KB MS43 flash dump.
+pub const MS43_FLASH_SIZE: usize = 0x80000;
+
+/// Address at which the MS43 flash is mapped in the C167 memory space.
+pub const MS43_FLASH_BASE_ADDRESS: usize = 0x800000;
+
 /// Represents a single modification in the binary.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Patch {
+    /// Short name of the patch, e.g. "Jump", "Code" or "DTC".
     pub name: &'static str,
+    /// File offset of the first modified byte.
     pub offset: usize,
+    /// Bytes expected at `offset` in an unmodified file.
     pub original: Vec<u8>,
+    /// Bytes written at `offset` when the patch is applied.
     pub patched: Vec<u8>,
+    /// How much can go wrong on a running car if this patch is wrong.
+    pub risk: RiskLevel,
+    /// What the patch does, in words a first-time user understands. Shown when hovering its status row.
+    pub description: &'static str,
+    /// Byte pattern starting at the patch, used to find the patch in dumps where it is shifted from
+    /// `offset`. `None` entries are wildcards. Patches without a signature are only looked for at `offset`.
+    pub signature: Option<Vec<Option<u8>>>,
+}
+
+/// How risky applying a patch is. High-risk patches need confirmation before they are applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, serde::Serialize, serde::Deserialize)]
+pub enum RiskLevel {
+    /// Cosmetic changes such as disabling a fault code.
+    Low,
+    /// The neutral level, used when a patch set does not specify one.
+    #[default]
+    Normal,
+    /// Changes to the immobilizer logic itself.
+    High,
+}
+
+impl RiskLevel {
+    pub fn label(self) -> &'static str {
+        match self {
+            RiskLevel::Low => "Low",
+            RiskLevel::Normal => "Normal",
+            RiskLevel::High => "High",
+        }
+    }
 }
 
 /// Represents a complete set of patches for a specific firmware version.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PatchSet {
+    /// Firmware version string as stored in the binary, e.g. "ca430037".
     pub version_string: &'static str,
+    /// Hardware variant, for versions that exist on more than one ECU variant.
     pub hardware_variant: Option<&'static str>,
+    /// Size of a complete flash dump for this version, if known.
+    pub expected_size: Option<usize>,
+    /// Address at which file offset 0 is mapped in the ECU's memory space.
+    pub base_address: usize,
+    /// Bounds `(start, end)` (end exclusive) of the region covered by the checksum the DME verifies
+    /// at boot, if the layout of this version is known.
+    pub checksum_region: Option<(usize, usize)>,
+    /// Offset of the stored checksum, as a little-endian value of the scheme's width.
+    pub checksum_offset: Option<usize>,
+    /// Algorithm of the checksum. The known MS43 versions use the 16-bit additive `ByteSum16`.
+    pub checksum_scheme: ChecksumScheme,
+    /// `(offset, length, name)` of regions blanked by `patcher::zeroize` when the user opts in, e.g. a
+    /// stored VIN or the EWS sync bytes. Independent of the patches; none of the built-in sets defines
+    /// one, because no location has been confirmed yet.
+    pub zeroize_regions: Vec<(usize, usize, &'static str)>,
+    /// The patches to apply, in order.
     pub patches: Vec<Patch>,
 }
 
+impl PatchSet {
+    /// Converts a file offset to the address it is mapped to in the ECU's memory space.
+    pub fn offset_to_address(&self, offset: usize) -> usize {
+        self.base_address + offset
+    }
+
+    /// Returns the checksum layout of this version, or `None` if it is not known.
+    pub fn checksum_layout(&self) -> Option<ChecksumRegion> {
+        let ((start, end), checksum_offset) = (self.checksum_region?, self.checksum_offset?);
+        Some(ChecksumRegion { start, end, checksum_offset, scheme: self.checksum_scheme, endian: Endian::Little })
+    }
+
+    /// Converts a mapped address to a file offset. Returns `None` if the address lies below the mapped flash.
+    pub fn address_to_offset(&self, address: usize) -> Option<usize> {
+        address.checked_sub(self.base_address)
+    }
+
+    /// Returns the patches that need confirmation before they are applied.
+    pub fn high_risk_patches(&self) -> Vec<&Patch> {
+        self.patches.iter().filter(|patch| patch.risk == RiskLevel::High).collect()
+    }
+
+    /// Returns a copy for dumps with a `header_len` byte header in front of the flash image, such as
+    /// the 512-byte header some reading tools prepend.
+    ///
+    /// Every patch offset, the checksum layout and the expected size move `header_len` bytes later. The
+    /// base address moves down by the same amount, so offsets still map to the same ECU addresses.
+    pub fn with_header(&self, header_len: usize) -> PatchSet {
+        PatchSet {
+            expected_size: self.expected_size.map(|size| size + header_len),
+            base_address: self.base_address.saturating_sub(header_len),
+            checksum_region: self.checksum_region.map(|(start, end)| (start + header_len, end + header_len)),
+            checksum_offset: self.checksum_offset.map(|offset| offset + header_len),
+            zeroize_regions: self.zeroize_regions.iter().map(|&(offset, length, name)| (offset + header_len, length, name)).collect(),
+            patches: self.patches.iter().map(|patch| Patch { offset: patch.offset + header_len, ..patch.clone() }).collect(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Description of the Jump patch, shared by every built-in set.
+const JUMP_DESCRIPTION: &str =
+    "Redirects the EWS check to the bypass routine, so the engine no longer waits for the immobilizer code.";
+/// Description of the Code patch, shared by every built-in set.
+const CODE_DESCRIPTION: &str = "Inserts the bypass routine into unused flash. It reports the immobilizer check as passed.";
+/// Description of the DTC patch, shared by every built-in set.
+const DTC_DESCRIPTION: &str = "Suppresses the immobilizer fault code, which would otherwise be stored because no EWS module answers.";
+
 /// Returns a list of all supported patch sets.
 pub fn get_all_patch_sets() -> Vec<PatchSet> {
     vec![
         PatchSet {
             version_string: "ca430037",
             hardware_variant: None,
+            expected_size: Some(MS43_FLASH_SIZE),
+            base_address: MS43_FLASH_BASE_ADDRESS,
+            checksum_region: None,
+            checksum_offset: None,
+            checksum_scheme: ChecksumScheme::ByteSum16,
+            zeroize_regions: Vec::new(),
             patches: vec![
-                Patch { name: "Jump", offset: 0x54E8C, original: vec![0xDA, 0x0B, 0x5A, 0x1C], patched: vec![0xDA, 0x0D, 0x0C, 0x35] },
-                Patch { name: "Code", offset: 0x5350C, original: vec![0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], patched: vec![0xDA, 0x0B, 0xE6, 0x39, 0x6E, 0x18, 0xDB, 0x00] },
-                Patch { name: "DTC", offset: 0x7099B, original: vec![0x02], patched: vec![0x00] },
+                Patch { name: "Jump", offset: 0x54E8C, original: vec![0xDA, 0x0B, 0x5A, 0x1C], patched: vec![0xDA, 0x0D, 0x0C, 0x35], risk: RiskLevel::High, description: JUMP_DESCRIPTION, signature: None },
+                Patch { name: "Code", offset: 0x5350C, original: vec![0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], patched: vec![0xDA, 0x0B, 0xE6, 0x39, 0x6E, 0x18, 0xDB, 0x00], risk: RiskLevel::Normal, description: CODE_DESCRIPTION, signature: None },
+                Patch { name: "DTC", offset: 0x7099B, original: vec![0x02], patched: vec![0x00], risk: RiskLevel::Low, description: DTC_DESCRIPTION, signature: None },
             ],
         },
         PatchSet {
             version_string: "ca430056",
             hardware_variant: Some("5WK90015"),
+            expected_size: Some(MS43_FLASH_SIZE),
+            base_address: MS43_FLASH_BASE_ADDRESS,
+            checksum_region: None,
+            checksum_offset: None,
+            checksum_scheme: ChecksumScheme::ByteSum16,
+            zeroize_regions: Vec::new(),
             patches: vec![
-                Patch { name: "Jump", offset: 0x57D76, original: vec![0xDA, 0x0B, 0x40, 0x20], patched: vec![0xDA, 0x0D, 0xB2, 0x3B] },
-                Patch { name: "Code", offset: 0x53BB2, original: vec![0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], patched: vec![0xDA, 0x0B, 0xB8, 0x3F, 0x9E, 0x19, 0xDB, 0x00] },
-                Patch { name: "DTC", offset: 0x70A14, original: vec![0x02], patched: vec![0x00] },
+                Patch { name: "Jump", offset: 0x57D76, original: vec![0xDA, 0x0B, 0x40, 0x20], patched: vec![0xDA, 0x0D, 0xB2, 0x3B], risk: RiskLevel::High, description: JUMP_DESCRIPTION, signature: None },
+                Patch { name: "Code", offset: 0x53BB2, original: vec![0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], patched: vec![0xDA, 0x0B, 0xB8, 0x3F, 0x9E, 0x19, 0xDB, 0x00], risk: RiskLevel::Normal, description: CODE_DESCRIPTION, signature: None },
+                Patch { name: "DTC", offset: 0x70A14, original: vec![0x02], patched: vec![0x00], risk: RiskLevel::Low, description: DTC_DESCRIPTION, signature: None },
             ],
         },
         PatchSet {
             version_string: "ca430056",
             hardware_variant: Some("5WK90017"),
+            expected_size: Some(MS43_FLASH_SIZE),
+            base_address: MS43_FLASH_BASE_ADDRESS,
+            checksum_region: None,
+            checksum_offset: None,
+            checksum_scheme: ChecksumScheme::ByteSum16,
+            zeroize_regions: Vec::new(),
             patches: vec![
-                Patch { name: "Jump", offset: 0x57D76, original: vec![0xDA, 0x0B, 0x40, 0x20], patched: vec![0xDA, 0x0D, 0xB2, 0x3B] },
-                Patch { name: "Code", offset: 0x53BB2, original: vec![0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], patched: vec![0xDA, 0x0B, 0xB8, 0x3F, 0x9E, 0x19, 0xDB, 0x00] },
-                Patch { name: "DTC", offset: 0x70A14, original: vec![0x02], patched: vec![0x00] },
+                Patch { name: "Jump", offset: 0x57D76, original: vec![0xDA, 0x0B, 0x40, 0x20], patched: vec![0xDA, 0x0D, 0xB2, 0x3B], risk: RiskLevel::High, description: JUMP_DESCRIPTION, signature: None },
+                Patch { name: "Code", offset: 0x53BB2, original: vec![0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], patched: vec![0xDA, 0x0B, 0xB8, 0x3F, 0x9E, 0x19, 0xDB, 0x00], risk: RiskLevel::Normal, description: CODE_DESCRIPTION, signature: None },
+                Patch { name: "DTC", offset: 0x70A14, original: vec![0x02], patched: vec![0x00], risk: RiskLevel::Low, description: DTC_DESCRIPTION, signature: None },
             ],
         },
         PatchSet {
             version_string: "ca430066",
             hardware_variant: None,
+            expected_size: Some(MS43_FLASH_SIZE),
+            base_address: MS43_FLASH_BASE_ADDRESS,
+            checksum_region: None,
+            checksum_offset: None,
+            checksum_scheme: ChecksumScheme::ByteSum16,
+            zeroize_regions: Vec::new(),
             patches: vec![
-                Patch { name: "Jump", offset: 0x600D8, original: vec![0xDA, 0x0A, 0x64, 0xDD], patched: vec![0xDA, 0x0D, 0xF8, 0x3B] },
-                Patch { name: "Code", offset: 0x53BF8, original: vec![0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], patched: vec![0xDA, 0x0A, 0xDC, 0xFC, 0x0E, 0x1A, 0xDB, 0x00] },
-                Patch { name: "DTC", offset: 0x70A77, original: vec![0x02], patched: vec![0x00] },
+                Patch { name: "Jump", offset: 0x600D8, original: vec![0xDA, 0x0A, 0x64, 0xDD], patched: vec![0xDA, 0x0D, 0xF8, 0x3B], risk: RiskLevel::High, description: JUMP_DESCRIPTION, signature: None },
+                Patch { name: "Code", offset: 0x53BF8, original: vec![0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], patched: vec![0xDA, 0x0A, 0xDC, 0xFC, 0x0E, 0x1A, 0xDB, 0x00], risk: RiskLevel::Normal, description: CODE_DESCRIPTION, signature: None },
+                Patch { name: "DTC", offset: 0x70A77, original: vec![0x02], patched: vec![0x00], risk: RiskLevel::Low, description: DTC_DESCRIPTION, signature: None },
             ],
         },
         PatchSet {
             version_string: "ca430069",
             hardware_variant: None,
+            expected_size: Some(MS43_FLASH_SIZE),
+            base_address: MS43_FLASH_BASE_ADDRESS,
+            checksum_region: None,
+            checksum_offset: None,
+            checksum_scheme: ChecksumScheme::ByteSum16,
+            zeroize_regions: Vec::new(),
             patches: vec![
-                Patch { name: "Jump", offset: 0x600D8, original: vec![0xDA, 0x0A, 0x6C, 0xDD], patched: vec![0xDA, 0x0D, 0xF8, 0x3B] },
-                Patch { name: "Code", offset: 0x53BF8, original: vec![0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], patched: vec![0xDA, 0x0A, 0xE4, 0xFC, 0x0E, 0x1A, 0xDB, 0x00] },
-                Patch { name: "DTC", offset: 0x70A6E, original: vec![0x02], patched: vec![0x00] },
+                Patch { name: "Jump", offset: 0x600D8, original: vec![0xDA, 0x0A, 0x6C, 0xDD], patched: vec![0xDA, 0x0D, 0xF8, 0x3B], risk: RiskLevel::High, description: JUMP_DESCRIPTION, signature: None },
+                Patch { name: "Code", offset: 0x53BF8, original: vec![0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], patched: vec![0xDA, 0x0A, 0xE4, 0xFC, 0x0E, 0x1A, 0xDB, 0x00], risk: RiskLevel::Normal, description: CODE_DESCRIPTION, signature: None },
+                Patch { name: "DTC", offset: 0x70A6E, original: vec![0x02], patched: vec![0x00], risk: RiskLevel::Low, description: DTC_DESCRIPTION, signature: None },
             ],
         },
     ]
 }
 
-// A lazily-initialized HashMap for quick lookups of patch sets by version string.
+/// Errors describing an inconsistent patch definition.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum PatchSetError {
+    #[error("Patch '{patch_name}' at offset {offset:#X} has identical original and patched bytes, so applying it would do nothing.")]
+    IdenticalBytes {
+        patch_name: &'static str,
+        offset: usize,
+    },
+    #[error("Patch '{patch_name}' at offset {offset:#X} has {original_len} original bytes but {patched_len} patched bytes.")]
+    LengthMismatch {
+        patch_name: &'static str,
+        offset: usize,
+        original_len: usize,
+        patched_len: usize,
+    },
+    #[error("Patch '{patch_name}' at offset {offset:#X} has a signature that is empty or contradicts its original bytes.")]
+    InvalidSignature {
+        patch_name: &'static str,
+        offset: usize,
+    },
+    #[error("Patch '{first}' at offset {first_offset:#X} overlaps patch '{second}' at offset {second_offset:#X}, so applying and reverting them would depend on their order.")]
+    Overlap {
+        first: &'static str,
+        first_offset: usize,
+        second: &'static str,
+        second_offset: usize,
+    },
+    #[error("Zeroize region '{region}' at offset {offset:#X} overlaps patch '{patch_name}', so clearing it would break the patch.")]
+    ZeroizeOverlap {
+        region: &'static str,
+        offset: usize,
+        patch_name: &'static str,
+    },
+    #[error("More than one patch set is defined for version '{version}' (variant {}).", variant.unwrap_or("none"))]
+    DuplicateKey {
+        version: &'static str,
+        variant: Option<&'static str>,
+    },
+}
+
+/// Checks a patch set for definition mistakes.
+///
+/// This is mainly a guard for hand-authored patch data: every patch must actually change the bytes it covers,
+/// its original and patched bytes must have the same length, its signature, if any, must match
+/// its original bytes, no two patches may cover the same bytes, and no zeroize region may cover a patch.
+pub fn validate_patch_set(set: &PatchSet) -> Result<(), PatchSetError> {
+    for patch in &set.patches {
+        if patch.original.len() != patch.patched.len() {
+            return Err(PatchSetError::LengthMismatch {
+                patch_name: patch.name,
+                offset: patch.offset,
+                original_len: patch.original.len(),
+                patched_len: patch.patched.len(),
+            });
+        }
+        if patch.original == patch.patched {
+            return Err(PatchSetError::IdenticalBytes { patch_name: patch.name, offset: patch.offset });
+        }
+        if let Some(signature) = &patch.signature {
+            // The signature starts at the patch, so its fixed bytes must agree with the original bytes.
+            let contradicts = signature.iter().zip(&patch.original).any(|(s, o)| s.is_some_and(|s| s != *o));
+            if signature.iter().all(Option::is_none) || contradicts {
+                return Err(PatchSetError::InvalidSignature { patch_name: patch.name, offset: patch.offset });
+            }
+        }
+    }
+    for (index, first) in set.patches.iter().enumerate() {
+        let first_end = first.offset + first.original.len();
+        for second in &set.patches[index + 1..] {
+            if first.offset < second.offset + second.original.len() && second.offset < first_end {
+                return Err(PatchSetError::Overlap {
+                    first: first.name,
+                    first_offset: first.offset,
+                    second: second.name,
+                    second_offset: second.offset,
+                });
+            }
+        }
+    }
+    for &(offset, length, region) in &set.zeroize_regions {
+        let end = offset + length;
+        if let Some(patch) = set.patches.iter().find(|patch| offset < patch.offset + patch.original.len() && patch.offset < end) {
+            return Err(PatchSetError::ZeroizeOverlap { region, offset, patch_name: patch.name });
+        }
+    }
+    Ok(())
+}
+
+/// Validates every patch set and checks that no two share a `(version, variant)` key.
+///
+/// Returns all problems found, so they can be reported at once. An empty list means the data is sane.
+pub fn validate_all_patch_sets(sets: &[PatchSet]) -> Vec<PatchSetError> {
+    let mut errors = Vec::new();
+    let mut seen = HashSet::new();
+    for set in sets {
+        if let Err(e) = validate_patch_set(set) {
+            errors.push(e);
+        }
+        if !seen.insert((set.version_string, set.hardware_variant)) {
+            errors.push(PatchSetError::DuplicateKey { version: set.version_string, variant: set.hardware_variant });
+        }
+    }
+    errors
+}
+
+/// A difference between two revisions of a patch set, matched by patch name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchDiff {
+    /// The patch only exists in the new set.
+    Added(Patch),
+    /// The patch only exists in the old set.
+    Removed(Patch),
+    /// The patch exists in both sets with different contents.
+    Changed { old: Patch, new: Patch },
+}
+
+impl fmt::Display for PatchDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchDiff::Added(patch) => write!(
+                f,
+                "+ {} at {:#X}: {:02X?} -> {:02X?}",
+                patch.name, patch.offset, patch.original, patch.patched
+            ),
+            PatchDiff::Removed(patch) => write!(f, "- {} at {:#X}", patch.name, patch.offset),
+            PatchDiff::Changed { old, new } => {
+                write!(f, "~ {}:", old.name)?;
+                if old.offset != new.offset {
+                    write!(f, " offset {:#X} -> {:#X};", old.offset, new.offset)?;
+                }
+                if old.original != new.original {
+                    write!(f, " original {:02X?} -> {:02X?};", old.original, new.original)?;
+                }
+                if old.patched != new.patched {
+                    write!(f, " patched {:02X?} -> {:02X?};", old.patched, new.patched)?;
+                }
+                if old.risk != new.risk {
+                    write!(f, " risk {} -> {};", old.risk.label(), new.risk.label())?;
+                }
+                if old.signature != new.signature {
+                    write!(f, " signature changed;")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Compares two patch sets by patch name.
+///
+/// Changed and removed patches are listed in the order of `old`, followed by the added patches in the order of `new`.
+pub fn diff_patch_sets(old: &PatchSet, new: &PatchSet) -> Vec<PatchDiff> {
+    let mut diffs = Vec::new();
+    for old_patch in &old.patches {
+        match new.patches.iter().find(|patch| patch.name == old_patch.name) {
+            Some(new_patch) if new_patch != old_patch => {
+                diffs.push(PatchDiff::Changed { old: old_patch.clone(), new: new_patch.clone() })
+            }
+            Some(_) => {}
+            None => diffs.push(PatchDiff::Removed(old_patch.clone())),
+        }
+    }
+    for new_patch in &new.patches {
+        if !old.patches.iter().any(|patch| patch.name == new_patch.name) {
+            diffs.push(PatchDiff::Added(new_patch.clone()));
+        }
+    }
+    diffs
+}
+
+/// Formats patch sets as a plain-text table with one row per version and variant.
+pub fn supported_versions_table(sets: &[&PatchSet]) -> String {
+    let rows: Vec<(String, String, String)> = sets
+        .iter()
+        .map(|set| {
+            let names: Vec<&str> = set.patches.iter().map(|patch| patch.name).collect();
+            (set.version_string.to_string(), set.hardware_variant.unwrap_or("-").to_string(), names.join(", "))
+        })
+        .collect();
+
+    let headers = ("Version", "Variant", "Patches");
+    let version_width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0).max(headers.0.len());
+    let variant_width = rows.iter().map(|r| r.1.len()).max().unwrap_or(0).max(headers.1.len());
+
+    let mut out = format!("{:<version_width$}  {:<variant_width$}  {}\n", headers.0, headers.1, headers.2);
+    out.push_str(&format!("{}  {}  {}\n", "-".repeat(version_width), "-".repeat(variant_width), "-".repeat(headers.2.len())));
+    for (version, variant, patches) in rows {
+        out.push_str(&format!("{:<version_width$}  {:<variant_width$}  {}\n", version, variant, patches));
+    }
+    out
+}
+
 lazy_static! {
-    pub static ref PATCH_SETS_MAP: HashMap<(&'static str, Option<&'static str>), PatchSet> = {
-        let mut m = HashMap::new();
-        for patch_set in get_all_patch_sets() {
-            m.insert((patch_set.version_string, patch_set.hardware_variant), patch_set);
+    /// The built-in patch sets, in definition order.
+    static ref BUILTIN_PATCH_SETS: Vec<PatchSet> = get_all_patch_sets();
+    /// Patch sets loaded from external files, see `register_patch_sets`.
+    static ref EXTERNAL_PATCH_SETS: RwLock<Vec<&'static PatchSet>> = RwLock::new(Vec::new());
+    /// Sets built by `with_header`, keyed by the address of the original set and the header length.
+    static ref SHIFTED_PATCH_SETS: Mutex<Vec<((usize, usize), &'static PatchSet)>> = Mutex::new(Vec::new());
+}
+
+/// Returns `patch_set.with_header(header_len)` as a set that lives for the rest of the program run.
+///
+/// Each combination is built once, so loading many files with a header does not leak a set per file.
+pub fn with_header(patch_set: &'static PatchSet, header_len: usize) -> &'static PatchSet {
+    if header_len == 0 {
+        return patch_set;
+    }
+    let mut shifted = SHIFTED_PATCH_SETS.lock().unwrap_or_else(|e| e.into_inner());
+    let key = (patch_set as *const PatchSet as usize, header_len);
+    if let Some(&(_, set)) = shifted.iter().find(|(existing, _)| *existing == key) {
+        return set;
+    }
+    let set: &'static PatchSet = Box::leak(Box::new(patch_set.with_header(header_len)));
+    shifted.push((key, set));
+    set
+}
+
+/// Returns every patch set known to detection: the built-in ones in definition order, followed by
+/// the registered external ones in registration order.
+pub fn known_patch_sets() -> Vec<&'static PatchSet> {
+    let external = EXTERNAL_PATCH_SETS.read().unwrap_or_else(|e| e.into_inner());
+    BUILTIN_PATCH_SETS.iter().chain(external.iter().copied()).collect()
+}
+
+/// Adds externally defined patch sets to the ones known to detection.
+///
+/// Every set is validated, and none may share a `(version, variant)` key with a known set. If any
+/// problem is found, nothing is registered and every problem is returned. Registered sets live for the rest of the program run.
+pub fn register_patch_sets(sets: Vec<PatchSet>) -> Result<usize, Vec<PatchSetError>> {
+    let mut external = EXTERNAL_PATCH_SETS.write().unwrap_or_else(|e| e.into_inner());
+    let mut errors: Vec<PatchSetError> = sets.iter().filter_map(|set| validate_patch_set(set).err()).collect();
+    let mut seen: HashSet<_> = BUILTIN_PATCH_SETS
+        .iter()
+        .chain(external.iter().copied())
+        .map(|set| (set.version_string, set.hardware_variant))
+        .collect();
+    for set in &sets {
+        if !seen.insert((set.version_string, set.hardware_variant)) {
+            errors.push(PatchSetError::DuplicateKey { version: set.version_string, variant: set.hardware_variant });
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let count = sets.len();
+    external.extend(sets.into_iter().map(|set| &*Box::leak(Box::new(set))));
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_patch_sets_are_valid() {
+        for set in get_all_patch_sets() {
+            assert_eq!(validate_patch_set(&set), Ok(()), "{} {:?}", set.version_string, set.hardware_variant);
+        }
+    }
+
+    #[test]
+    fn a_header_moves_the_offsets_but_not_the_addresses() {
+        let set = known_patch_sets()[0];
+        let shifted = with_header(set, 0x200);
+        assert!(std::ptr::eq(shifted, with_header(set, 0x200)));
+        assert!(std::ptr::eq(set, with_header(set, 0)));
+        assert_eq!(shifted.patches[0].offset, set.patches[0].offset + 0x200);
+        assert_eq!(shifted.expected_size, Some(MS43_FLASH_SIZE + 0x200));
+        assert_eq!(shifted.offset_to_address(shifted.patches[2].offset), set.offset_to_address(set.patches[2].offset));
+        assert_eq!((shifted.version_string, &shifted.patches[1].original), (set.version_string, &set.patches[1].original));
+    }
+
+    #[test]
+    fn supported_versions_table_lists_every_patch_set() {
+        let sets = get_all_patch_sets();
+        let table = supported_versions_table(&sets.iter().collect::<Vec<_>>());
+        assert_eq!(table.lines().count(), sets.len() + 2);
+        assert!(table.starts_with("Version   Variant   Patches\n"));
+        assert!(table.contains("ca430056  5WK90015  Jump, Code, DTC"));
+        assert!(table.contains("ca430037  -         Jump, Code, DTC"));
+    }
+
+    #[test]
+    fn builtin_patch_sets_pass_the_self_test() {
+        assert_eq!(validate_all_patch_sets(&get_all_patch_sets()), vec![]);
+    }
+
+    #[test]
+    fn duplicate_keys_and_length_mismatches_are_reported() {
+        let mut sets = get_all_patch_sets();
+        sets.push(sets[0].clone());
+        sets[1].patches[2].patched = vec![0x00, 0x00];
+
+        let errors = validate_all_patch_sets(&sets);
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], PatchSetError::LengthMismatch { patch_name: "DTC", original_len: 1, patched_len: 2, .. }));
+        assert_eq!(errors[1], PatchSetError::DuplicateKey { version: "ca430037", variant: None });
+    }
+
+    #[test]
+    fn diff_reports_added_removed_and_changed_patches() {
+        let sets = get_all_patch_sets();
+        let old = &sets[0];
+        assert_eq!(diff_patch_sets(old, old), vec![]);
+
+        let mut new = old.clone();
+        new.patches.remove(0);
+        new.patches[0].offset += 2;
+        new.patches.push(Patch { name: "Extra", offset: 0x100, original: vec![0x01], patched: vec![0x02], risk: RiskLevel::Normal, description: "", signature: None });
+
+        let diffs = diff_patch_sets(old, &new);
+        assert_eq!(diffs.len(), 3);
+        assert_eq!(diffs[0], PatchDiff::Removed(old.patches[0].clone()));
+        assert_eq!(diffs[1].to_string(), "~ Code: offset 0x5350C -> 0x5350E;");
+        assert_eq!(diffs[2].to_string(), "+ Extra at 0x100: [01] -> [02]");
+    }
+
+    #[test]
+    fn identical_original_and_patched_bytes_are_rejected() {
+        let mut set = get_all_patch_sets().remove(0);
+        set.patches[2].patched = set.patches[2].original.clone();
+        assert_eq!(
+            validate_patch_set(&set),
+            Err(PatchSetError::IdenticalBytes { patch_name: "DTC", offset: set.patches[2].offset })
+        );
+    }
+
+    #[test]
+    fn overlapping_patches_are_rejected() {
+        let mut set = get_all_patch_sets().remove(0);
+        let jump = set.patches[0].clone();
+        set.patches.push(Patch { name: "Tail", offset: jump.offset + 3, original: vec![0x1C, 0x00], patched: vec![0x00, 0x00], ..jump.clone() });
+        let error = validate_patch_set(&set).unwrap_err();
+        assert_eq!(
+            error,
+            PatchSetError::Overlap { first: "Jump", first_offset: jump.offset, second: "Tail", second_offset: jump.offset + 3 }
+        );
+        assert!(error.to_string().starts_with("Patch 'Jump' at offset 0x54E8C overlaps patch 'Tail' at offset 0x54E8F"));
+
+        // Adjacent patches do not overlap.
+        set.patches.last_mut().unwrap().offset = jump.offset + jump.original.len();
+        assert_eq!(validate_patch_set(&set), Ok(()));
+
+        set.zeroize_regions.push((jump.offset - 4, 5, "VIN"));
+        assert_eq!(
+            validate_patch_set(&set),
+            Err(PatchSetError::ZeroizeOverlap { region: "VIN", offset: jump.offset - 4, patch_name: "Jump" })
+        );
+        set.zeroize_regions[0].1 = 4;
+        assert_eq!(validate_patch_set(&set), Ok(()));
+    }
+
+    #[test]
+    fn only_the_immo_jump_is_high_risk() {
+        assert_eq!(RiskLevel::default(), RiskLevel::Normal);
+        for set in get_all_patch_sets() {
+            let names: Vec<&str> = set.high_risk_patches().iter().map(|patch| patch.name).collect();
+            assert_eq!(names, vec!["Jump"], "{}", set.version_string);
         }
-        m
-    };
+    }
 }
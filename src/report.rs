@@ -0,0 +1,220 @@
+//! This module generates human-readable reports documenting the changes made to a firmware file.
+
+use crate::checksum::crc32;
+use crate::fingerprint::sha256;
+use crate::patcher::patch_offset;
+use crate::patches::PatchSet;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+/// Converts a byte slice to a formatted, spaced-out hex string.
+pub fn bytes_to_hex_string(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Everything that goes into an audit report.
+pub struct ReportInput<'a> {
+    /// Name or path of the firmware file.
+    pub file_name: &'a str,
+    pub patch_set: &'a PatchSet,
+    /// Hardware variant read from the file, if any. The patch set's variant is reported otherwise.
+    pub hardware_variant: Option<&'a str>,
+    /// The firmware as it was loaded.
+    pub before: &'a [u8],
+    /// The firmware after the changes.
+    pub after: &'a [u8],
+    /// Session log lines to include at the end of the report.
+    pub log: &'a [String],
+}
+
+/// Returns a slice of `data` starting at `offset`, clamped to the end of the data.
+fn region(data: &[u8], offset: usize, len: usize) -> &[u8] {
+    let start = offset.min(data.len());
+    let end = (offset + len).min(data.len());
+    &data[start..end]
+}
+
+/// Generates a Markdown audit report describing every patch region before and after the changes.
+pub fn generate_report(input: &ReportInput) -> String {
+    let mut out = String::new();
+    let patch_set = input.patch_set;
+
+    let _ = writeln!(out, "# EWS IMMO Patcher Report");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "- Generated: {}", humantime::format_rfc3339_seconds(SystemTime::now()));
+    let _ = writeln!(out, "- Tool version: ewsms43 {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(out, "- File: {}", input.file_name);
+    let _ = writeln!(out, "- Detected version: {}", patch_set.version_string);
+    let _ = writeln!(out, "- Hardware variant: {}", input.hardware_variant.or(patch_set.hardware_variant).unwrap_or("N/A"));
+    let _ = writeln!(out, "- File size: {} bytes", input.after.len());
+    let _ = writeln!(out, "- CRC32 before: {:#010X}", crc32(input.before));
+    let _ = writeln!(out, "- CRC32 after: {:#010X}", crc32(input.after));
+    let _ = writeln!(out, "- SHA-256 before: {}", sha256(input.before));
+    let _ = writeln!(out, "- SHA-256 after: {}", sha256(input.after));
+    let _ = writeln!(out);
+
+    let _ = writeln!(out, "## Patches");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "| Patch | Offset | Bytes changed | Before | After |");
+    let _ = writeln!(out, "|-------|--------|---------------|--------|-------|");
+    for patch in &patch_set.patches {
+        let len = patch.original.len().max(patch.patched.len());
+        // A patch found by its signature is reported where it is, not at its fixed offset.
+        let offset = patch_offset(input.after, patch);
+        let before = region(input.before, offset, len);
+        let after = region(input.after, offset, len);
+        let changed = before.iter().zip(after).filter(|(a, b)| a != b).count();
+        let _ = writeln!(
+            out,
+            "| {} | {:#X} | {} | {} | {} |",
+            patch.name,
+            offset,
+            changed,
+            bytes_to_hex_string(before),
+            bytes_to_hex_string(after)
+        );
+    }
+
+    if !input.log.is_empty() {
+        let _ = writeln!(out);
+        let _ = writeln!(out, "## Log");
+        let _ = writeln!(out);
+        let _ = writeln!(out, "```");
+        for line in input.log {
+            let _ = writeln!(out, "{}", line);
+        }
+        let _ = writeln!(out, "```");
+    }
+
+    out
+}
+
+/// Number of bytes per row in a side-by-side hex dump.
+pub const DUMP_BYTES_PER_ROW: usize = 16;
+
+/// Which rows a side-by-side hex dump contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpRange {
+    /// Every row of the image.
+    Full,
+    /// Only rows with changes, plus the given number of rows of context around them.
+    ChangedWithContext(usize),
+}
+
+/// Formats one column of a dump row. A changed byte is followed by `*` instead of a space.
+fn dump_column(own: &[u8], other: &[u8], offset: usize) -> String {
+    (offset..offset + DUMP_BYTES_PER_ROW)
+        .map(|i| match own.get(i) {
+            Some(byte) => format!("{:02X}{}", byte, if other.get(i) == Some(byte) { ' ' } else { '*' }),
+            None => "   ".to_string(),
+        })
+        .collect()
+}
+
+/// Generates a text hex dump showing `before` and `after` side by side, one row of
+/// `DUMP_BYTES_PER_ROW` bytes per line.
+///
+/// Each line starts with the row offset. Rows containing changes are marked with `>`, and each
+/// changed byte is followed by `*`. Skipped rows are shown as `...`.
+pub fn side_by_side_dump(before: &[u8], after: &[u8], range: DumpRange) -> String {
+    let rows = before.len().max(after.len()).div_ceil(DUMP_BYTES_PER_ROW);
+    let changed: Vec<bool> = (0..rows)
+        .map(|row| {
+            let offset = row * DUMP_BYTES_PER_ROW;
+            region(before, offset, DUMP_BYTES_PER_ROW) != region(after, offset, DUMP_BYTES_PER_ROW)
+        })
+        .collect();
+    let shown = |row: usize| match range {
+        DumpRange::Full => true,
+        DumpRange::ChangedWithContext(context) => {
+            let first = row.saturating_sub(context);
+            let last = (row + context).min(rows - 1);
+            changed[first..=last].contains(&true)
+        }
+    };
+
+    let mut out = String::new();
+    let width = DUMP_BYTES_PER_ROW * 3;
+    let _ = writeln!(out, "  {:<8}  {:<width$}| After", "Offset", "Before");
+    let mut skipped = false;
+    for (row, &row_changed) in changed.iter().enumerate() {
+        if !shown(row) {
+            skipped = true;
+            continue;
+        }
+        if skipped {
+            let _ = writeln!(out, "  ...");
+            skipped = false;
+        }
+        let offset = row * DUMP_BYTES_PER_ROW;
+        let _ = writeln!(
+            out,
+            "{} {:08X}  {}| {}",
+            if row_changed { '>' } else { ' ' },
+            offset,
+            dump_column(before, after, offset),
+            dump_column(after, before, offset).trim_end()
+        );
+    }
+    if skipped {
+        let _ = writeln!(out, "  ...");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patcher::apply_patches;
+    use crate::testutil::{build_synthetic_firmware, builtin_patch_set};
+
+    #[test]
+    fn report_lists_changed_bytes_per_patch() {
+        let patch_set = builtin_patch_set("ca430037");
+        let before = build_synthetic_firmware(&patch_set);
+        let mut after = before.clone();
+        apply_patches(&mut after, &patch_set).unwrap();
+
+        let report = generate_report(&ReportInput {
+            file_name: "dump.bin",
+            patch_set: &patch_set,
+            hardware_variant: None,
+            before: &before,
+            after: &after,
+            log: &["Success: Patches applied.".to_string()],
+        });
+
+        assert!(report.contains("- Detected version: ca430037"));
+        assert!(report.contains("| Jump | 0x54E8C | 3 | DA 0B 5A 1C | DA 0D 0C 35 |"));
+        assert!(report.contains("| Code | 0x5350C | 8 |"));
+        assert!(report.contains("| DTC | 0x7099B | 1 | 02 | 00 |"));
+        assert!(report.contains("Success: Patches applied."));
+        assert!(report.contains(&format!("- Tool version: ewsms43 {}", env!("CARGO_PKG_VERSION"))));
+        assert!(report.contains(&format!("- SHA-256 before: {}", sha256(&before))));
+    }
+
+    #[test]
+    fn side_by_side_dump_marks_changed_bytes_and_skips_unchanged_rows() {
+        let before: Vec<u8> = (0..64).collect();
+        let mut after = before.clone();
+        after[0x21] = 0xAA;
+
+        let dump = side_by_side_dump(&before, &after, DumpRange::ChangedWithContext(1));
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("  Offset"));
+        assert_eq!(lines[1], "  ...");
+        assert!(lines[2].starts_with("  00000010  10 11"));
+        assert!(lines[3].starts_with("> 00000020  20 21*22"), "{}", lines[3]);
+        assert!(lines[3].contains("| 20 AA*22"), "{}", lines[3]);
+        assert!(lines[4].starts_with("  00000030"));
+
+        let full = side_by_side_dump(&before, &after, DumpRange::Full);
+        assert_eq!(full.lines().count(), 5);
+        assert!(!full.contains("..."));
+    }
+}
@@ -0,0 +1,139 @@
+//! This module searches firmware images for byte patterns, optionally with `??` wildcard bytes.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Number of start offsets scanned between progress reports and cancellation checks.
+pub const SEARCH_CHUNK_SIZE: usize = 0x10000;
+
+/// A search pattern. `None` entries are wildcards that match any byte.
+pub type Pattern = Vec<Option<u8>>;
+
+/// Errors raised when parsing a search pattern.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum PatternError {
+    #[error("The search pattern is empty.")]
+    Empty,
+    #[error("'{0}' is not a hex byte or '??' wildcard.")]
+    InvalidByte(String),
+}
+
+/// Parses a pattern such as `DA 0B ?? 5A`. Bytes may also be written without spaces, e.g. `DA0B??5A`.
+pub fn parse_pattern(input: &str) -> Result<Pattern, PatternError> {
+    let digits: String = input.split_whitespace().collect();
+    if digits.is_empty() {
+        return Err(PatternError::Empty);
+    }
+    if let Some(c) = digits.chars().find(|c| !c.is_ascii()) {
+        return Err(PatternError::InvalidByte(c.to_string()));
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(PatternError::InvalidByte(digits[digits.len() - 1..].to_string()));
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            let token = &digits[i..i + 2];
+            if token == "??" {
+                Ok(None)
+            } else {
+                u8::from_str_radix(token, 16).map(Some).map_err(|_| PatternError::InvalidByte(token.to_string()))
+            }
+        })
+        .collect()
+}
+
+/// Returns true if `pattern` matches `data` at `offset`.
+pub fn matches_at(data: &[u8], offset: usize, pattern: &[Option<u8>]) -> bool {
+    data.get(offset..offset + pattern.len()).is_some_and(|window| {
+        window.iter().zip(pattern).all(|(byte, expected)| expected.is_none_or(|e| e == *byte))
+    })
+}
+
+/// Returns the offset of every match of `pattern` in `data`, including overlapping ones.
+pub fn find_pattern(data: &[u8], pattern: &[Option<u8>]) -> Vec<usize> {
+    let mut matches = Vec::new();
+    search_chunked(data, pattern, &AtomicBool::new(false), |chunk, _| matches.extend_from_slice(chunk));
+    matches
+}
+
+/// Returns the offset of every occurrence of the exact bytes `pattern` in `data`, including overlapping
+/// ones. Use `find_pattern` for patterns with wildcards.
+pub fn find_all(data: &[u8], pattern: &[u8]) -> Vec<usize> {
+    let pattern: Pattern = pattern.iter().copied().map(Some).collect();
+    find_pattern(data, &pattern)
+}
+
+/// Searches `data` chunk by chunk so long scans can report progress and be cancelled.
+///
+/// After each chunk, `on_chunk` receives the matches found in it and the number of start offsets scanned
+/// so far. `cancel` is checked before every chunk. Returns false if the search was cancelled.
+pub fn search_chunked(
+    data: &[u8],
+    pattern: &[Option<u8>],
+    cancel: &AtomicBool,
+    mut on_chunk: impl FnMut(&[usize], usize),
+) -> bool {
+    if pattern.is_empty() || data.len() < pattern.len() {
+        on_chunk(&[], data.len());
+        return true;
+    }
+
+    let last_start = data.len() - pattern.len();
+    let mut start = 0;
+    while start <= last_start {
+        if cancel.load(Ordering::Relaxed) {
+            return false;
+        }
+        let end = (start + SEARCH_CHUNK_SIZE).min(last_start + 1);
+        let matches: Vec<usize> = (start..end).filter(|&offset| matches_at(data, offset, pattern)).collect();
+        // Report the whole file as scanned at the end, including the tail too short to start a match.
+        let scanned = if end > last_start { data.len() } else { end };
+        on_chunk(&matches, scanned);
+        start = end;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_parse_with_and_without_spaces() {
+        assert_eq!(parse_pattern("DA 0b"), Ok(vec![Some(0xDA), Some(0x0B)]));
+        assert_eq!(parse_pattern("da0b??5a"), Ok(vec![Some(0xDA), Some(0x0B), None, Some(0x5A)]));
+        assert_eq!(parse_pattern("  "), Err(PatternError::Empty));
+        assert_eq!(parse_pattern("DA 0"), Err(PatternError::InvalidByte("0".to_string())));
+        assert_eq!(parse_pattern("ZZ"), Err(PatternError::InvalidByte("ZZ".to_string())));
+        assert_eq!(parse_pattern("DAé"), Err(PatternError::InvalidByte("é".to_string())));
+    }
+
+    #[test]
+    fn finds_overlapping_and_wildcard_matches() {
+        let data = [0xAA, 0xAA, 0xAA, 0x01, 0xAA, 0x02];
+        assert_eq!(find_pattern(&data, &[Some(0xAA), Some(0xAA)]), vec![0, 1]);
+        assert_eq!(find_pattern(&data, &[Some(0xAA), None]), vec![0, 1, 2, 4]);
+        assert_eq!(find_pattern(&data, &[None; 7]), Vec::<usize>::new());
+        assert_eq!(find_all(&data, &[0xAA, 0x01]), vec![2]);
+        assert_eq!(find_all(&data, &[]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn chunked_search_reports_progress_and_stops_when_cancelled() {
+        let mut data = vec![0u8; SEARCH_CHUNK_SIZE * 2 + 10];
+        data[SEARCH_CHUNK_SIZE - 1] = 0x12;
+        data[SEARCH_CHUNK_SIZE] = 0x34;
+        let pattern = parse_pattern("12 34").unwrap();
+
+        let mut reports = Vec::new();
+        assert!(search_chunked(&data, &pattern, &AtomicBool::new(false), |m, scanned| reports.push((m.to_vec(), scanned))));
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[0], (vec![SEARCH_CHUNK_SIZE - 1], SEARCH_CHUNK_SIZE));
+        assert_eq!(reports[2].1, data.len());
+
+        let mut calls = 0;
+        assert!(!search_chunked(&data, &pattern, &AtomicBool::new(true), |_, _| calls += 1));
+        assert_eq!(calls, 0);
+    }
+}
@@ -0,0 +1,110 @@
+//! This module checks that every built-in patch set survives a detect, apply and revert round trip on
+//! a synthetic image, which catches copy-paste mistakes in the hardcoded offsets and bytes.
+
+use crate::patcher::{self, PatchStatus};
+use crate::patches::{self, PatchSet, MS43_FLASH_SIZE};
+use crate::version::{self, HW_VARIANT_OFFSET, VERSION_STRING_LENGTH, VERSION_STRING_OFFSET};
+
+/// Builds a synthetic image for `patch_set`: erased flash (0xFF) holding the version string at
+/// `VERSION_STRING_OFFSET`, the hardware variant (if any) at `HW_VARIANT_OFFSET`, and the `original`
+/// bytes of every patch at its offset.
+///
+/// Returns an error if the image of `expected_size` is too small for any of them.
+pub fn build_synthetic_image(patch_set: &PatchSet) -> Result<Vec<u8>, String> {
+    let mut data = vec![0xFF; patch_set.expected_size.unwrap_or(MS43_FLASH_SIZE)];
+    let size = data.len();
+
+    let version_region = data
+        .get_mut(VERSION_STRING_OFFSET..VERSION_STRING_OFFSET + VERSION_STRING_LENGTH)
+        .ok_or_else(|| format!("the version string does not fit in a {} byte image", size))?;
+    version_region.fill(0x00);
+    let version = patch_set.version_string.as_bytes();
+    version_region
+        .get_mut(..version.len())
+        .ok_or("the version string is longer than its field")?
+        .copy_from_slice(version);
+
+    if let Some(variant) = patch_set.hardware_variant {
+        data.get_mut(HW_VARIANT_OFFSET..HW_VARIANT_OFFSET + variant.len())
+            .ok_or_else(|| format!("the hardware variant does not fit in a {} byte image", size))?
+            .copy_from_slice(variant.as_bytes());
+    }
+
+    for patch in &patch_set.patches {
+        data.get_mut(patch.offset..patch.offset + patch.original.len())
+            .ok_or_else(|| format!("the {} patch at {:#X} does not fit in a {} byte image", patch.name, patch.offset, size))?
+            .copy_from_slice(&patch.original);
+    }
+    Ok(data)
+}
+
+/// Returns an error unless every patch of `patch_set` has `expected` status in `data`.
+fn expect_status(data: &[u8], patch_set: &PatchSet, expected: PatchStatus, step: &str) -> Result<(), String> {
+    match patch_set.patches.iter().find(|patch| patcher::get_patch_status(data, patch) != expected) {
+        Some(patch) => Err(format!(
+            "after {} the {} patch is {:?} instead of {:?}",
+            step,
+            patch.name,
+            patcher::get_patch_status(data, patch),
+            expected
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Detects, applies and reverts `patch_set` on its synthetic image, checking every step.
+pub fn round_trip(patch_set: &PatchSet) -> Result<(), String> {
+    let mut data = build_synthetic_image(patch_set)?;
+    let detected = version::detect_version(&data).map_err(|e| format!("detection failed: {}", e))?;
+    if detected.version_string != patch_set.version_string || detected.hardware_variant != patch_set.hardware_variant {
+        return Err(format!(
+            "detected {} (variant {}) instead",
+            detected.version_string,
+            detected.hardware_variant.unwrap_or("N/A")
+        ));
+    }
+    expect_status(&data, patch_set, PatchStatus::Unpatched, "building the image")?;
+    patcher::apply_patches(&mut data, patch_set).map_err(|e| format!("applying failed: {}", e))?;
+    expect_status(&data, patch_set, PatchStatus::Patched, "applying")?;
+    patcher::revert_patches(&mut data, patch_set).map_err(|e| format!("reverting failed: {}", e))?;
+    expect_status(&data, patch_set, PatchStatus::Unpatched, "reverting")
+}
+
+/// Runs `round_trip` for every built-in patch set. Returns one message per failing set, e.g.
+/// "ca430037: applying failed: ...", or an empty list if all of them pass.
+pub fn selftest() -> Vec<String> {
+    patches::get_all_patch_sets()
+        .iter()
+        .filter_map(|set| {
+            let name = match set.hardware_variant {
+                Some(variant) => format!("{} ({})", set.version_string, variant),
+                None => set.version_string.to_string(),
+            };
+            round_trip(set).err().map(|e| format!("{}: {}", name, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::builtin_patch_set;
+
+    #[test]
+    fn every_builtin_patch_set_round_trips() {
+        assert_eq!(selftest(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn a_patch_over_the_version_string_is_caught() {
+        let mut patch_set = builtin_patch_set("ca430037");
+        patch_set.patches[0].offset = VERSION_STRING_OFFSET;
+        assert!(round_trip(&patch_set).unwrap_err().starts_with("detection failed"));
+
+        patch_set.patches[0].offset = MS43_FLASH_SIZE;
+        assert_eq!(
+            round_trip(&patch_set),
+            Err(format!("the Jump patch at {:#X} does not fit in a {} byte image", MS43_FLASH_SIZE, MS43_FLASH_SIZE))
+        );
+    }
+}
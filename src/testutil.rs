@@ -0,0 +1,18 @@
+//! Helpers shared by the unit tests for building synthetic firmware images.
+
+use crate::patches::PatchSet;
+
+/// Builds a minimal in-memory firmware image for the given patch set.
+///
+/// See `selftest::build_synthetic_image` for the layout.
+pub fn build_synthetic_firmware(patch_set: &PatchSet) -> Vec<u8> {
+    crate::selftest::build_synthetic_image(patch_set).expect("patch set does not fit in its image")
+}
+
+/// Returns the built-in patch set for the given version string (first variant if several exist).
+pub fn builtin_patch_set(version_string: &str) -> PatchSet {
+    crate::patches::get_all_patch_sets()
+        .into_iter()
+        .find(|set| set.version_string == version_string)
+        .expect("unknown built-in version")
+}
@@ -0,0 +1,96 @@
+//! This module re-reads a saved file to confirm it holds exactly the bytes that were written, so a bad
+//! USB stick or a full disk is noticed before the file is flashed into a DME.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Errors that can occur while verifying a written file.
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error("Could not re-read the saved file: {0}")]
+    Io(#[from] io::Error),
+    #[error("The saved file is {found} bytes long, but {expected} bytes were written.")]
+    LengthMismatch { expected: usize, found: usize },
+    #[error("The saved file differs from the written data at offset {offset:#X}.")]
+    Mismatch { offset: usize },
+}
+
+impl VerifyError {
+    /// Returns a stable, machine-readable code identifying the kind of error.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            VerifyError::Io(_) => "verify_read_failed",
+            VerifyError::LengthMismatch { .. } => "verify_length_mismatch",
+            VerifyError::Mismatch { .. } => "verify_mismatch",
+        }
+    }
+}
+
+/// Size of the chunks a file is re-read in, so large images are never held in memory twice.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Reads `written` to its end and compares it with `expected`, which must have the same length.
+fn compare(mut written: impl Read, expected: &[u8]) -> Result<(), VerifyError> {
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let (mut found, mut mismatch) = (0, None);
+    loop {
+        let read = match written.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if mismatch.is_none() {
+            let wanted = expected.get(found..).unwrap_or_default();
+            mismatch = buffer[..read].iter().zip(wanted).position(|(a, b)| a != b).map(|position| found + position);
+        }
+        found += read;
+    }
+    if found != expected.len() {
+        return Err(VerifyError::LengthMismatch { expected: expected.len(), found });
+    }
+    match mismatch {
+        Some(offset) => Err(VerifyError::Mismatch { offset }),
+        None => Ok(()),
+    }
+}
+
+/// Re-reads `path` and compares it byte for byte with `expected`.
+pub fn verify_written(path: &Path, expected: &[u8]) -> Result<(), VerifyError> {
+    compare(fs::File::open(path)?, expected)
+}
+
+/// Re-reads the first `expected.len()` bytes of `path` and compares them with `expected`.
+///
+/// Unlike `verify_written`, the target may be longer than the written data, e.g. a block device or
+/// an EEPROM image the data was written to the start of.
+pub fn verify_written_prefix(path: &Path, expected: &[u8]) -> Result<(), VerifyError> {
+    compare(fs::File::open(path)?.take(expected.len() as u64), expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn differing_or_truncated_files_are_reported() {
+        let path = std::env::temp_dir().join(format!("ewsms43-verify-{}.bin", std::process::id()));
+        fs::write(&path, [1, 2, 3, 4]).unwrap();
+        let same = verify_written(&path, &[1, 2, 3, 4]);
+        let changed = verify_written(&path, &[1, 2, 9, 4]);
+        let longer = verify_written(&path, &[1, 2, 3, 4, 5]);
+        let prefix = verify_written_prefix(&path, &[1, 2, 3]);
+        let changed_prefix = verify_written_prefix(&path, &[1, 9]);
+        let longer_prefix = verify_written_prefix(&path, &[1, 2, 3, 4, 5]);
+        fs::remove_file(&path).unwrap();
+
+        assert!(same.is_ok());
+        assert!(matches!(changed, Err(VerifyError::Mismatch { offset: 2 })));
+        assert!(matches!(longer, Err(VerifyError::LengthMismatch { expected: 5, found: 4 })));
+        assert!(prefix.is_ok());
+        assert!(matches!(changed_prefix, Err(VerifyError::Mismatch { offset: 1 })));
+        assert!(matches!(longer_prefix, Err(VerifyError::LengthMismatch { expected: 5, found: 4 })));
+        assert!(matches!(verify_written(&path, &[]), Err(VerifyError::Io(_))));
+    }
+}
@@ -1,57 +1,601 @@
 //! This module handles the detection of the firmware version from the binary data.
 
-use crate::patches::{PatchSet, PATCH_SETS_MAP};
+use crate::fingerprint;
+use crate::patches::{self, PatchSet};
+use crate::report::bytes_to_hex_string;
+use std::ops::RangeInclusive;
 
-const VERSION_STRING_OFFSET: usize = 0x70040;
-const VERSION_STRING_LENGTH: usize = 16;
+pub(crate) const VERSION_STRING_OFFSET: usize = 0x70040;
+pub(crate) const VERSION_STRING_LENGTH: usize = 16;
+/// Offset of the hardware variant (Siemens part number, e.g. "5WK90017"), assumed to be right after the
+/// version string. This placeholder has not been verified on real dumps. When no variant can be read
+/// there and the version exists on several variants, the user is asked to pick one instead.
+pub(crate) const HW_VARIANT_OFFSET: usize = 0x70050;
+pub(crate) const HW_VARIANT_LENGTH: usize = 8;
+
+/// Bytes kept when cleaning a raw version string. Everything else is skipped.
+pub const VERSION_CHAR_RANGE: RangeInclusive<u8> = 0x20..=0x7e;
+
+/// Distance from the version string to the hardware variant that follows it.
+const HW_VARIANT_DISTANCE: usize = HW_VARIANT_OFFSET - VERSION_STRING_OFFSET;
+
+/// Candidate `(offset, length)` locations of the version string, probed in order.
+pub const VERSION_LOCATIONS: &[(usize, usize)] = &[(VERSION_STRING_OFFSET, VERSION_STRING_LENGTH)];
 
 /// Custom error types for version detection.
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
 pub enum VersionError {
+    /// The file ends before the end of the version string.
     #[error("File is too small to contain a version string.")]
     FileTooSmall,
-    #[error("Version string at offset {0:#X} is not valid UTF-8.")]
-    InvalidUtf8(usize),
+    /// A version string was found, but there is no patch set for it.
     #[error("Unsupported or unrecognized version. Found: '{0}'")]
     UnsupportedVersion(String),
-    #[error("Could not identify firmware version string at offset 0x70040.")]
-    UnknownVersion,
+    /// No version string was found at any of the expected offsets.
+    #[error(
+        "Could not identify a firmware version string at any known offset. Raw bytes at {offset:#X}: {} ('{cleaned}')",
+        bytes_to_hex_string(raw)
+    )]
+    UnknownVersion {
+        /// Offset of the probed location.
+        offset: usize,
+        /// The raw bytes found there.
+        raw: Vec<u8>,
+        /// `raw` after `clean_version_bytes`.
+        cleaned: String,
+    },
+    /// The version exists on several hardware variants and the file does not say which one it is.
+    #[error("This version exists on several hardware variants ({}) and the variant could not be read from the file.", candidates.join(", "))]
+    AmbiguousVariant {
+        /// The hardware variants of the matching patch sets.
+        candidates: Vec<String>,
+    },
+}
+
+impl VersionError {
+    /// Returns a stable, machine-readable code identifying the kind of error.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            VersionError::FileTooSmall => "file_too_small",
+            VersionError::UnsupportedVersion(_) => "unsupported_version",
+            VersionError::UnknownVersion { .. } => "unknown_version",
+            VersionError::AmbiguousVariant { .. } => "ambiguous_variant",
+        }
+    }
+}
+
+/// Result of a successful version detection.
+#[derive(Debug, Clone)]
+pub struct Detection {
+    /// The patch set matching the detected version.
+    pub patch_set: &'static PatchSet,
+    /// Offset of the version string that was matched, or `None` if the file was identified by its
+    /// SHA-256 fingerprint.
+    pub version_offset: Option<usize>,
+    /// Hardware variant read from the file, if it holds a readable one.
+    pub hardware_variant: Option<String>,
+    /// Revision suffix after the version string, e.g. "B" for "ca430056_B" (see `parse_version_token`).
+    /// `None` if the string has no suffix or the file was identified by its fingerprint.
+    pub version_suffix: Option<String>,
+}
+
+impl Detection {
+    /// Returns how far the version string was found from `VERSION_STRING_OFFSET`, e.g. `0x200` for a
+    /// dump with a 512-byte header. Patch offsets of such a file are usually off by the same amount.
+    ///
+    /// Zero if the string was at its usual place or the file was identified by its fingerprint.
+    pub fn offset_shift(&self) -> isize {
+        self.version_offset.map_or(0, |offset| offset as isize - VERSION_STRING_OFFSET as isize)
+    }
 }
 
 /// Detects the firmware version from the provided binary data.
 ///
-/// It reads a string from a fixed offset, cleans it, and attempts to match it against a known list of firmware versions.
+/// Known stock files are identified by their SHA-256 fingerprint first. Otherwise it reads a string from
+/// each known offset, cleans it, and attempts to match it against a known list of firmware versions.
+/// Versions that exist on several hardware variants are told apart by the variant stored in the file.
 pub fn detect_version(data: &[u8]) -> Result<&'static PatchSet, VersionError> {
-    // 1. Ensure the file is large enough.
-    if data.len() < VERSION_STRING_OFFSET + VERSION_STRING_LENGTH {
-        return Err(VersionError::FileTooSmall);
+    detect(data).map(|detection| detection.patch_set)
+}
+
+/// Detects the firmware version, also reporting where the version string was found.
+///
+/// A file matching one of `fingerprint::KNOWN_FINGERPRINTS` is identified without reading the version
+/// string. Otherwise each entry of `VERSION_LOCATIONS` is probed in order and the first one holding a
+/// known version wins. If none of them holds a version string at all, the whole file is scanned for
+/// one (see `scan_for_version`).
+pub fn detect(data: &[u8]) -> Result<Detection, VersionError> {
+    let (version_offset, candidates) = find_candidates(data)?;
+    let hardware_variant = match version_offset {
+        Some(offset) => read_hardware_variant_at(data, offset + HW_VARIANT_DISTANCE),
+        None => read_hardware_variant(data),
+    };
+    let patch_set = select_variant(candidates, hardware_variant.as_deref())?;
+    let version_suffix = version_offset.and_then(|offset| {
+        read_version_suffix(&data[offset..(offset + VERSION_STRING_LENGTH).min(data.len())])
+    });
+    Ok(Detection { patch_set, version_offset, hardware_variant, version_suffix })
+}
+
+/// Returns how far the version string of `data` is from `VERSION_STRING_OFFSET`, like
+/// `Detection::offset_shift`, but also for files whose hardware variant is ambiguous.
+///
+/// Zero if the file was identified by its fingerprint or holds no known version.
+pub fn offset_shift(data: &[u8]) -> isize {
+    match find_candidates(data) {
+        Ok((Some(offset), _)) => offset as isize - VERSION_STRING_OFFSET as isize,
+        _ => 0,
     }
+}
+
+/// Returns every patch set matching the version of `data`, one per hardware variant, in definition order.
+///
+/// Unlike `detect`, this does not pick between sets of the same version, so callers can offer the
+/// choice to the user. It fails for the same reasons as `detect`, except `AmbiguousVariant`.
+pub fn detect_all_candidates(data: &[u8]) -> Result<Vec<&'static PatchSet>, VersionError> {
+    find_candidates(data).map(|(_, candidates)| candidates)
+}
 
-    // 2. Read the raw bytes.
-    let version_bytes = &data[VERSION_STRING_OFFSET..(VERSION_STRING_OFFSET + VERSION_STRING_LENGTH)];
+/// Finds the version of `data` and returns where its string is (`None` for a fingerprint match) along
+/// with the patch sets of that version.
+fn find_candidates(data: &[u8]) -> Result<(Option<usize>, Vec<&'static PatchSet>), VersionError> {
+    if let Some(patch_set) = fingerprint::identify(data) {
+        return Ok((None, vec![patch_set]));
+    }
 
-    // 3. Parse the bytes by taking printable ASCII characters until a null byte is found.
-    // This is much more robust than assuming valid UTF-8.
-    let version_str_cleaned: String = version_bytes
+    let mut result = Err(VersionError::FileTooSmall);
+    for &(offset, length) in VERSION_LOCATIONS {
+        // Locations beyond the end of the file are skipped.
+        let Some(raw) = data.get(offset..offset + length) else {
+            continue;
+        };
+        match match_version(offset, raw) {
+            Ok(candidates) => return Ok((Some(offset), candidates)),
+            // Keep the most informative error: an unsupported version beats an unknown one.
+            Err(e @ VersionError::UnsupportedVersion(_)) => result = Err(e),
+            Err(e) => {
+                if !matches!(result, Err(VersionError::UnsupportedVersion(_))) {
+                    result = Err(e);
+                }
+            }
+        }
+    }
+
+    if let Err(VersionError::UnknownVersion { .. }) = result {
+        if let Some(offset) = scan_for_version(data) {
+            let raw = &data[offset..(offset + VERSION_STRING_LENGTH).min(data.len())];
+            return match_version(offset, raw).map(|candidates| (Some(offset), candidates));
+        }
+    }
+    result
+}
+
+/// Length of the base of a version string, e.g. "ca430056": a family prefix padded with digits.
+const VERSION_BASE_LENGTH: usize = 8;
+
+/// Returns the family prefixes of `patch_sets`: the letters their version strings start with, e.g.
+/// "ca" for "ca430056". Sorted and without duplicates.
+fn prefixes_of(patch_sets: &[&PatchSet]) -> Vec<String> {
+    let mut prefixes: Vec<String> = patch_sets
         .iter()
-        .take_while(|&&b| b != 0) // Stop at the first null terminator
-        .filter(|&&b| b >= 0x20 && b <= 0x7e) // Filter for printable ASCII range
-        .map(|&b| b as char)
+        .map(|set| set.version_string.chars().take_while(char::is_ascii_alphabetic).collect::<String>())
+        .filter(|prefix| !prefix.is_empty())
         .collect();
+    prefixes.sort();
+    prefixes.dedup();
+    prefixes
+}
+
+/// Returns the version string prefixes detection accepts, derived from the known patch sets.
+///
+/// A file whose version string starts with none of them is reported as `VersionError::UnknownVersion`,
+/// so adding a patch set of a new family, built in or external, extends detection to it.
+pub fn version_prefixes() -> Vec<String> {
+    prefixes_of(&patches::known_patch_sets())
+}
+
+/// Returns true if `bytes` starts with a version-like string: one of `prefixes` followed by digits up to
+/// `VERSION_BASE_LENGTH` characters, e.g. "ca" and six digits.
+fn looks_like_version(bytes: &[u8], prefixes: &[String]) -> bool {
+    bytes.len() >= VERSION_BASE_LENGTH
+        && prefixes.iter().any(|prefix| {
+            prefix.len() < VERSION_BASE_LENGTH
+                && bytes.starts_with(prefix.as_bytes())
+                && bytes[prefix.len()..VERSION_BASE_LENGTH].iter().all(u8::is_ascii_digit)
+        })
+}
+
+/// Searches the whole file for a known version string, for dumps where it is not at a known offset,
+/// e.g. because a reading tool prepended a header.
+///
+/// Every version-like string (see `version_prefixes`) is tried in file order. Returns the offset of the
+/// first one naming a known version.
+pub fn scan_for_version(data: &[u8]) -> Option<usize> {
+    let prefixes = version_prefixes();
+    (0..data.len()).filter(|&offset| looks_like_version(&data[offset..], &prefixes)).find(|&offset| {
+        let raw = &data[offset..(offset + VERSION_STRING_LENGTH).min(data.len())];
+        match_version(offset, raw).is_ok()
+    })
+}
+
+/// The intermediate results of probing one of the `VERSION_LOCATIONS`.
+#[derive(Debug, Clone)]
+pub struct VersionProbe {
+    pub offset: usize,
+    /// The raw bytes at the location. Shorter than the probe length if the file ends early.
+    pub raw: Vec<u8>,
+    /// `raw` after `clean_version_bytes`.
+    pub cleaned: String,
+    /// The patch set matched by this location, or why there is none.
+    pub result: Result<&'static PatchSet, VersionError>,
+}
+
+/// Probes every location in `VERSION_LOCATIONS` and returns what each one holds.
+///
+/// This exposes the steps behind `detect` for diagnosing files whose version is not recognized.
+pub fn probe_versions(data: &[u8]) -> Vec<VersionProbe> {
+    VERSION_LOCATIONS
+        .iter()
+        .map(|&(offset, length)| {
+            let raw = data.get(offset.min(data.len())..(offset + length).min(data.len())).unwrap_or_default();
+            let result = if raw.len() < length {
+                Err(VersionError::FileTooSmall)
+            } else {
+                let variant = read_hardware_variant_at(data, offset + HW_VARIANT_DISTANCE);
+                match_version(offset, raw).and_then(|candidates| select_variant(candidates, variant.as_deref()))
+            };
+            VersionProbe { offset, raw: raw.to_vec(), cleaned: clean_version_bytes(raw), result }
+        })
+        .collect()
+}
+
+/// Reads the hardware variant stored at `HW_VARIANT_OFFSET`.
+///
+/// Returns `None` unless the location holds a full-length alphanumeric string, such as an erased or
+/// missing field.
+pub fn read_hardware_variant(data: &[u8]) -> Option<String> {
+    read_hardware_variant_at(data, HW_VARIANT_OFFSET)
+}
+
+/// Reads a hardware variant at `offset`, with the same rules as `read_hardware_variant`.
+fn read_hardware_variant_at(data: &[u8], offset: usize) -> Option<String> {
+    let raw = data.get(offset..offset + HW_VARIANT_LENGTH)?;
+    raw.iter().all(u8::is_ascii_alphanumeric).then(|| String::from_utf8_lossy(raw).into_owned())
+}
+
+/// Picks the patch set whose hardware variant matches `variant`, the one stored in the file.
+///
+/// A single candidate is returned as is. Several candidates need a readable variant that matches one of them.
+fn select_variant(candidates: Vec<&'static PatchSet>, variant: Option<&str>) -> Result<&'static PatchSet, VersionError> {
+    if let [only] = candidates[..] {
+        return Ok(only);
+    }
+    candidates
+        .iter()
+        .copied()
+        .find(|set| set.hardware_variant.is_some() && set.hardware_variant == variant)
+        .ok_or_else(|| VersionError::AmbiguousVariant {
+            candidates: candidates.iter().map(|set| set.hardware_variant.unwrap_or("none").to_string()).collect(),
+        })
+}
+
+/// Returns every patch set matching the version string of `data` if its hardware variant cannot be
+/// told apart, so the user can pick one. Empty if detection succeeds or fails for another reason.
+pub fn variant_candidates(data: &[u8]) -> Vec<&'static PatchSet> {
+    match detect(data) {
+        Err(VersionError::AmbiguousVariant { .. }) => detect_all_candidates(data).unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+/// Cleans raw version bytes into a string.
+///
+/// Bytes outside `VERSION_CHAR_RANGE` (including null bytes) are skipped rather than ending the string,
+/// so a stray control or high byte in the middle of a dirty dump does not truncate the version.
+pub fn clean_version_bytes(version_bytes: &[u8]) -> String {
+    version_bytes
+        .iter()
+        .filter(|b| VERSION_CHAR_RANGE.contains(b))
+        .map(|&b| b as char)
+        .collect()
+}
+
+/// Splits a version string into its 8-character base, e.g. "ca430056", and an optional trailing suffix.
+///
+/// The suffix is the first word after the base, with leading delimiters stripped: "ca430056_B" gives
+/// `("ca430056", Some("B"))` and "ca430056 5WK90017" gives `("ca430056", Some("5WK90017"))`. Returns
+/// `None` if `s` does not start with a version of a known family (see `version_prefixes`), or if a
+/// letter or digit follows the base directly, since "ca4300561" is a different version rather than
+/// "ca430056" with a suffix.
+pub fn parse_version_token(s: &str) -> Option<(String, Option<String>)> {
+    let s = s.trim();
+    if !looks_like_version(s.as_bytes(), &version_prefixes()) {
+        return None;
+    }
+    let (base, rest) = s.split_at(VERSION_BASE_LENGTH);
+    if rest.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let suffix = rest
+        .trim_start_matches(|c: char| !c.is_ascii_alphanumeric())
+        .split_whitespace()
+        .next()
+        .map(str::to_string);
+    Some((base.to_string(), suffix))
+}
+
+/// Reads the suffix after the version string in the raw bytes of a version location.
+///
+/// Stray bytes inside the version itself are skipped as in `clean_version_bytes`; after it, bytes
+/// outside `VERSION_CHAR_RANGE` (null padding, erased flash) end the suffix like a space.
+fn read_version_suffix(version_bytes: &[u8]) -> Option<String> {
+    let kept: Vec<usize> = (0..version_bytes.len()).filter(|&i| VERSION_CHAR_RANGE.contains(&version_bytes[i])).collect();
+    let end = kept.get(7)? + 1;
+    let mut token = clean_version_bytes(&version_bytes[..end]);
+    token.extend(version_bytes[end..].iter().map(|&b| if VERSION_CHAR_RANGE.contains(&b) { b as char } else { ' ' }));
+    parse_version_token(&token)?.1
+}
+
+/// How a known version key matched a cleaned version string. Exact matches rank above prefix matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum KeyMatch {
+    /// The string starts with the key, followed by a delimiter.
+    Prefix,
+    /// The string equals the key.
+    Exact,
+}
+
+/// Matches a known version key against the cleaned version string.
+///
+/// `kept` holds the raw index of every character of `cleaned`. A prefix match requires the raw byte
+/// after the key to be a delimiter (null or any other non-alphanumeric byte), so that a longer version
+/// such as "ca4300561" is not taken for "ca430056".
+fn key_match(version_bytes: &[u8], kept: &[usize], cleaned: &str, key: &str) -> Option<KeyMatch> {
+    if cleaned == key {
+        return Some(KeyMatch::Exact);
+    }
+    if key.is_empty() || !cleaned.starts_with(key) {
+        return None;
+    }
+    let next = version_bytes.get(kept[key.len() - 1] + 1);
+    next.is_none_or(|b| !b.is_ascii_alphanumeric()).then_some(KeyMatch::Prefix)
+}
 
+/// Cleans the raw version bytes and looks the resulting string up in the known versions.
+///
+/// An exact match wins over a delimited prefix match, and a longer key over a shorter one. Returns
+/// every patch set with the best key, one per hardware variant, in definition order.
+fn match_version(offset: usize, version_bytes: &[u8]) -> Result<Vec<&'static PatchSet>, VersionError> {
+    let version_str_cleaned = clean_version_bytes(version_bytes);
 
-    // 4. Check if the cleaned string looks like a version we handle.
-    if !version_str_cleaned.starts_with("ca") {
-        return Err(VersionError::UnknownVersion);
+    // Check if the cleaned string starts like a version of a family we handle.
+    if !version_prefixes().iter().any(|prefix| version_str_cleaned.starts_with(prefix.as_str())) {
+        return Err(VersionError::UnknownVersion { offset, raw: version_bytes.to_vec(), cleaned: version_str_cleaned });
     }
 
-    // 5. Find the corresponding PatchSet in our map using a more robust check.
-    // We check if the cleaned string from the file *starts with* a known version string.
-    // This handles cases where the file might have extra garbage after the version number.
-    PATCH_SETS_MAP
+    let kept: Vec<usize> = (0..version_bytes.len()).filter(|&i| VERSION_CHAR_RANGE.contains(&version_bytes[i])).collect();
+    let ranked: Vec<((KeyMatch, usize), &'static PatchSet)> = patches::known_patch_sets()
+        .into_iter()
+        .filter_map(|patch_set| {
+            key_match(version_bytes, &kept, &version_str_cleaned, patch_set.version_string)
+                // Rank by match kind, then key length.
+                .map(|kind| ((kind, patch_set.version_string.len()), patch_set))
+        })
+        .collect();
+    let best = ranked
         .iter()
-        .find(|((version_key, _), _)| version_str_cleaned.starts_with(*version_key))
-        .map(|(_, patch_set)| patch_set)
-        .ok_or_else(|| VersionError::UnsupportedVersion(version_str_cleaned.to_string()))
+        .map(|(rank, _)| *rank)
+        .max()
+        .ok_or_else(|| VersionError::UnsupportedVersion(version_str_cleaned.to_string()))?;
+    Ok(ranked.into_iter().filter(|(rank, _)| *rank == best).map(|(_, patch_set)| patch_set).collect())
 }
 
+/// Largest edit distance for which `closest_known_version` still suggests a version.
+const MAX_SUGGESTION_DISTANCE: usize = 3;
+
+/// Computes the Levenshtein edit distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Suggests the supported version closest to an unsupported one, by edit distance.
+///
+/// Only the leading characters of `version` are compared, matching the prefix lookup used during detection.
+/// Returns `None` if no supported version is within a few edits. Ties go to the lowest version string.
+pub fn closest_known_version(version: &str) -> Option<&'static str> {
+    patches::known_patch_sets()
+        .into_iter()
+        .map(|set| {
+            let key = set.version_string;
+            let compared = version.get(..key.len()).unwrap_or(version);
+            (edit_distance(compared, key), key)
+        })
+        .filter(|&(distance, _)| distance <= MAX_SUGGESTION_DISTANCE)
+        .min()
+        .map(|(_, key)| key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{build_synthetic_firmware, builtin_patch_set};
+
+    #[test]
+    fn detects_version_at_the_first_location() {
+        let patch_set = builtin_patch_set("ca430037");
+        let data = build_synthetic_firmware(&patch_set);
+        let detection = detect(&data).unwrap();
+        assert_eq!(detection.patch_set.version_string, "ca430037");
+        assert_eq!(detection.version_offset, Some(VERSION_STRING_OFFSET));
+    }
+
+    #[test]
+    fn reports_file_too_small_and_unknown_versions() {
+        assert!(matches!(detect_version(&[0u8; 0x100]), Err(VersionError::FileTooSmall)));
+
+        let mut data = build_synthetic_firmware(&builtin_patch_set("ca430037"));
+        data[VERSION_STRING_OFFSET..VERSION_STRING_OFFSET + 8].copy_from_slice(b"xx430037");
+        match detect_version(&data) {
+            Err(e @ VersionError::UnknownVersion { .. }) => {
+                assert!(e.to_string().contains("78 78 34 33"));
+                assert!(e.to_string().contains("'xx430037"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        data[VERSION_STRING_OFFSET..VERSION_STRING_OFFSET + 8].copy_from_slice(b"ca439999");
+        assert!(matches!(detect_version(&data), Err(VersionError::UnsupportedVersion(v)) if v == "ca439999"));
+    }
+
+    #[test]
+    fn version_is_found_in_a_dump_with_a_header() {
+        let mut data = vec![0xFF; 0x200];
+        data.extend(build_synthetic_firmware(&builtin_patch_set("ca430037")));
+        let detection = detect(&data).unwrap();
+        assert_eq!(detection.patch_set.version_string, "ca430037");
+        assert_eq!(detection.version_offset, Some(VERSION_STRING_OFFSET + 0x200));
+        assert_eq!(detection.offset_shift(), 0x200);
+
+        // The variant is read next to the version string that was found.
+        let set = crate::patches::get_all_patch_sets().into_iter().find(|set| set.hardware_variant == Some("5WK90017")).unwrap();
+        let mut data = build_synthetic_firmware(&set);
+        data.drain(..0x10);
+        let detection = detect(&data).unwrap();
+        assert_eq!(detection.patch_set.hardware_variant, Some("5WK90017"));
+        assert_eq!(detection.offset_shift(), -0x10);
+
+        // Version-like strings of unknown versions are skipped; without a known one the error is kept.
+        let mut data = vec![0u8; 0x80000];
+        data[0x100..0x108].copy_from_slice(b"ca999999");
+        assert!(scan_for_version(&data).is_none());
+        assert!(matches!(detect(&data), Err(VersionError::UnknownVersion { .. })));
+    }
+
+    #[test]
+    fn accepted_prefixes_follow_the_known_patch_sets() {
+        assert_eq!(version_prefixes(), ["ca"]);
+        let mut other_family = builtin_patch_set("ca430037");
+        other_family.version_string = "zb420012";
+        let builtin = builtin_patch_set("ca430056");
+        let prefixes = prefixes_of(&[&builtin, &other_family, &builtin]);
+        assert_eq!(prefixes, ["ca", "zb"]);
+
+        assert!(looks_like_version(b"zb420012", &prefixes));
+        assert!(!looks_like_version(b"zb42001", &prefixes));
+        assert!(!looks_like_version(b"zb42x012", &prefixes));
+        assert!(!looks_like_version(b"zb420012", &version_prefixes()));
+    }
+
+    #[test]
+    fn stray_bytes_inside_the_version_string_are_skipped() {
+        assert_eq!(clean_version_bytes(b"ca43\x8a0037\0\0\0"), "ca430037");
+        assert_eq!(clean_version_bytes(b"ca43\x000037"), "ca430037");
+
+        let mut data = build_synthetic_firmware(&builtin_patch_set("ca430037"));
+        data[VERSION_STRING_OFFSET..VERSION_STRING_OFFSET + 9].copy_from_slice(b"ca4\xff30037");
+        assert_eq!(detect_version(&data).unwrap().version_string, "ca430037");
+    }
+
+    #[test]
+    fn closest_known_version_suggests_a_near_sibling() {
+        assert_eq!(edit_distance("ca430099", "ca430069"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(closest_known_version("ca430099"), Some("ca430069"));
+        assert_eq!(closest_known_version("ca430058garbage"), Some("ca430056"));
+        assert_eq!(closest_known_version("zz999999"), None);
+    }
+
+    #[test]
+    fn probes_expose_the_raw_bytes_and_cleaned_string() {
+        let data = build_synthetic_firmware(&builtin_patch_set("ca430069"));
+        let probes = probe_versions(&data);
+        assert_eq!(probes.len(), VERSION_LOCATIONS.len());
+        assert_eq!(probes[0].offset, VERSION_STRING_OFFSET);
+        assert_eq!(&probes[0].raw[..8], b"ca430069");
+        assert_eq!(probes[0].cleaned, "ca430069");
+        assert_eq!(probes[0].result.as_ref().unwrap().version_string, "ca430069");
+
+        let probes = probe_versions(&data[..VERSION_STRING_OFFSET + 4]);
+        assert_eq!(probes[0].raw, b"ca43");
+        assert!(matches!(probes[0].result, Err(VersionError::FileTooSmall)));
+    }
+
+    #[test]
+    fn a_longer_version_does_not_match_a_shorter_key() {
+        let mut data = build_synthetic_firmware(&builtin_patch_set("ca430056"));
+        let mut write_version = |bytes: &[u8]| {
+            let region = &mut data[VERSION_STRING_OFFSET..VERSION_STRING_OFFSET + VERSION_STRING_LENGTH];
+            region.fill(0x00);
+            region[..bytes.len()].copy_from_slice(bytes);
+            detect_version(&data).map(|set| set.version_string)
+        };
+
+        assert_eq!(write_version(b"ca430056"), Ok("ca430056"));
+        assert_eq!(write_version(b"ca4300561"), Err(VersionError::UnsupportedVersion("ca4300561".to_string())));
+        assert_eq!(write_version(b"ca430056a"), Err(VersionError::UnsupportedVersion("ca430056a".to_string())));
+        // A null or punctuation byte after the key is a delimiter, even when followed by more text.
+        assert_eq!(write_version(b"ca430056\0\0xyz"), Ok("ca430056"));
+        assert_eq!(write_version(b"ca430056-01"), Ok("ca430056"));
+        assert_eq!(write_version(b"ca430056 "), Ok("ca430056"));
+    }
+
+    #[test]
+    fn version_tokens_are_split_into_base_and_suffix() {
+        assert_eq!(parse_version_token("ca430056"), Some(("ca430056".to_string(), None)));
+        assert_eq!(parse_version_token("ca430056_B"), Some(("ca430056".to_string(), Some("B".to_string()))));
+        assert_eq!(parse_version_token("ca430056 5WK90017"), Some(("ca430056".to_string(), Some("5WK90017".to_string()))));
+        assert_eq!(parse_version_token("ca430056-01  "), Some(("ca430056".to_string(), Some("01".to_string()))));
+        assert_eq!(parse_version_token("ca4300561"), None);
+        assert_eq!(parse_version_token("ca43005"), None);
+
+        // Detection still picks the set by its base and reports the suffix.
+        let set = crate::patches::get_all_patch_sets().into_iter().find(|set| set.hardware_variant == Some("5WK90017")).unwrap();
+        let mut data = build_synthetic_firmware(&set);
+        assert_eq!(detect(&data).unwrap().version_suffix, None);
+        data[VERSION_STRING_OFFSET + 8..VERSION_STRING_OFFSET + 10].copy_from_slice(b"_B");
+        let detection = detect(&data).unwrap();
+        assert_eq!(detection.patch_set.hardware_variant, Some("5WK90017"));
+        assert_eq!(detection.version_suffix.as_deref(), Some("B"));
+    }
+
+    #[test]
+    fn the_hardware_variant_selects_between_sets_of_one_version() {
+        for variant in ["5WK90015", "5WK90017"] {
+            let set = crate::patches::get_all_patch_sets()
+                .into_iter()
+                .find(|set| set.hardware_variant == Some(variant))
+                .unwrap();
+            let data = build_synthetic_firmware(&set);
+            let detection = detect(&data).unwrap();
+            assert_eq!(detection.patch_set.hardware_variant, Some(variant));
+            assert_eq!(detection.hardware_variant.as_deref(), Some(variant));
+            assert!(variant_candidates(&data).is_empty());
+            assert_eq!(detect_all_candidates(&data).unwrap().len(), 2);
+        }
+
+        // An erased variant field cannot tell the two apart.
+        let mut data = build_synthetic_firmware(&builtin_patch_set("ca430056"));
+        data[HW_VARIANT_OFFSET..HW_VARIANT_OFFSET + HW_VARIANT_LENGTH].fill(0xFF);
+        let error = detect_version(&data).unwrap_err();
+        assert_eq!(error, VersionError::AmbiguousVariant { candidates: vec!["5WK90015".to_string(), "5WK90017".to_string()] });
+        assert_eq!(error.error_code(), "ambiguous_variant");
+        assert_eq!(variant_candidates(&data).len(), 2);
+        let candidates = detect_all_candidates(&data).unwrap();
+        assert_eq!(candidates.iter().map(|set| set.hardware_variant).collect::<Vec<_>>(), [Some("5WK90015"), Some("5WK90017")]);
+
+        // Versions with a single set do not need the variant.
+        let data = build_synthetic_firmware(&builtin_patch_set("ca430037"));
+        assert_eq!(read_hardware_variant(&data), None);
+        assert_eq!(detect(&data).unwrap().patch_set.version_string, "ca430037");
+    }
+}
@@ -0,0 +1,68 @@
+//! End-to-end tests against real firmware dumps in `tests/fixtures`.
+//!
+//! The dumps are not part of the repository by default, so every test passes when none are present.
+
+use ewsms43::{apply_patches, check_patch_status, detect_version, revert_patches, PatchStatus};
+use std::fs;
+use std::path::PathBuf;
+
+/// A fixture file and the version/variant its name says it contains.
+struct Fixture {
+    path: PathBuf,
+    version: String,
+    variant: Option<String>,
+}
+
+/// Collects the `.bin` fixtures, parsing the expected version from each file name.
+fn load_fixtures() -> Vec<Fixture> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures");
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+
+    let mut fixtures: Vec<Fixture> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("bin")))
+        .map(|path| {
+            let stem = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let mut parts = stem.split('_');
+            let version = parts.next().unwrap_or_default().to_string();
+            let variant = parts.next().filter(|p| p.starts_with("5WK")).map(str::to_string);
+            Fixture { path, version, variant }
+        })
+        .collect();
+    fixtures.sort_by(|a, b| a.path.cmp(&b.path));
+
+    if fixtures.is_empty() {
+        eprintln!("No firmware fixtures found in {}, skipping.", dir.display());
+    }
+    fixtures
+}
+
+#[test]
+fn fixtures_are_detected_and_round_trip() {
+    for fixture in load_fixtures() {
+        let name = fixture.path.display();
+        let original = fs::read(&fixture.path).unwrap();
+
+        let patch_set = detect_version(&original).unwrap_or_else(|e| panic!("{}: detection failed: {}", name, e));
+        assert_eq!(patch_set.version_string, fixture.version, "{}", name);
+        if let Some(variant) = &fixture.variant {
+            assert_eq!(patch_set.hardware_variant, Some(variant.as_str()), "{}", name);
+        }
+
+        let mut data = original.clone();
+        match check_patch_status(&data, patch_set) {
+            (PatchStatus::Unpatched, PatchStatus::Unpatched, PatchStatus::Unpatched) => {
+                apply_patches(&mut data, patch_set).unwrap();
+                revert_patches(&mut data, patch_set).unwrap();
+            }
+            (PatchStatus::Patched, PatchStatus::Patched, PatchStatus::Patched) => {
+                revert_patches(&mut data, patch_set).unwrap();
+                apply_patches(&mut data, patch_set).unwrap();
+            }
+            status => panic!("{}: unexpected patch status {:?}", name, status),
+        }
+        assert!(data == original, "{}: round trip changed the file", name);
+    }
+}